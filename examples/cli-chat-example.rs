@@ -1,5 +1,5 @@
 use chat_gpt_lib_rs::client::{ChatGPTError, Message};
-use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Model, Role};
+use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Model};
 use console::{style, StyledObject};
 use dotenvy::dotenv;
 use indicatif::{ProgressBar, ProgressStyle};
@@ -53,12 +53,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client = ChatGPTClient::new(&api_key, "https://api.openai.com");
 
     // Initialize the message history with a system message
-    let mut messages = vec![Message {
-        role: Role::System,
-        content:
-            "Be a helpfull pair programmer, who want to show solutions and examples in code blocks"
-                .to_string(),
-    }];
+    let mut messages = vec![Message::system(
+        "Be a helpfull pair programmer, who want to show solutions and examples in code blocks",
+    )];
 
     // Check if any command line arguments are provided
     let mut args: Skip<env::Args> = env::args().skip(1);
@@ -95,17 +92,12 @@ async fn process_user_input(
     user_message_content: String,
 ) -> Result<(), ChatGPTError> {
     // Add the user message to the message history
-    messages.push(Message {
-        role: Role::User,
-        content: user_message_content.trim().to_string(),
-    });
+    messages.push(Message::user(user_message_content.trim()));
 
     // Prepare the ChatInput object for the API call
-    let input = ChatInput {
-        model: Model::Gpt_4o,
-        messages: messages.clone(),
-        ..Default::default()
-    };
+    let input = ChatInput::builder(Model::Gpt_4o)
+        .messages(messages.clone())
+        .build();
 
     // Set up a spinner to display while waiting for the API response
     let spinner = ProgressBar::new_spinner();
@@ -137,15 +129,12 @@ async fn process_user_input(
     } else {
         style("Computer: ").color256(39)
     };
-    let computer_response: StyledObject<String> = style(assistant_message.clone());
+    let computer_response: StyledObject<String> = style(assistant_message.to_string());
 
     println!("{}{}", computer_label, computer_response);
 
     // Add the assistant's message to the message history
-    messages.push(Message {
-        role: Role::Assistant,
-        content: assistant_message.clone(),
-    });
+    messages.push(Message::assistant(assistant_message.clone()));
 
     Ok(())
 }
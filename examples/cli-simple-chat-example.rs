@@ -1,4 +1,4 @@
-use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model, Role};
+use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
 use dotenvy::dotenv;
 use std::env;
 use std::error::Error;
@@ -32,10 +32,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let client = ChatGPTClient::new(&api_key, "https://api.openai.com");
 
     // Create a vector of messages with an initial system message
-    let mut messages = vec![Message {
-        role: Role::System,
-        content: "You are an AI that can answer any question.".to_string(),
-    }];
+    let mut messages = vec![Message::system(
+        "You are an AI that can answer any question.",
+    )];
 
     // Start an input loop
     loop {
@@ -48,17 +47,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
         stdin().read_line(&mut user_input).unwrap();
 
         // Add the user's message to the messages vector
-        messages.push(Message {
-            role: Role::User,
-            content: user_input.trim().to_string(),
-        });
+        messages.push(Message::user(user_input.trim()));
 
         // Define the input for the ChatGPTClient
-        let input = ChatInput {
-            model: Model::Gpt_4o,       // Consider making this configurable
-            messages: messages.clone(), // Pass in the messages vector
-            ..Default::default()
-        };
+        let input = ChatInput::builder(Model::Gpt_4o) // Consider making this configurable
+            .messages(messages.clone()) // Pass in the messages vector
+            .build();
 
         // Call the chat method on the ChatGPTClient with the input
         let response = client.chat(input).await?;
@@ -70,9 +64,6 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("AI Response: {}", ai_message);
 
         // Add the AI's message to the messages vector
-        messages.push(Message {
-            role: Role::Assistant,
-            content: ai_message.clone(),
-        });
+        messages.push(Message::assistant(ai_message.clone()));
     }
 }
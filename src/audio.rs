@@ -0,0 +1,159 @@
+//! Audio input and output through the chat endpoint.
+//!
+//! Models such as `gpt-4o-audio-preview` accept recorded speech as
+//! [`ContentPart::InputAudio`](crate::content::ContentPart::InputAudio) parts and, when
+//! [`ChatInput::modalities`](crate::ChatInput::modalities) includes [`Modality::Audio`], answer
+//! with spoken audio in the voice and format set by [`ChatInput::audio`](crate::ChatInput::audio).
+//! The answer's audio is returned in [`Message::audio`](crate::Message::audio), together with
+//! its transcript.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::audio::{AudioFormat, AudioOutput, Modality};
+//! use chat_gpt_lib_rs::content::ContentPart;
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let question = std::fs::read("question.wav").unwrap();
+//! let input = ChatInput::builder(Model::Other("gpt-4o-audio-preview".to_string()))
+//!     .message(Message::user(vec![ContentPart::input_audio(
+//!         &question,
+//!         AudioFormat::Wav,
+//!     )]))
+//!     .modalities(vec![Modality::Text, Modality::Audio])
+//!     .audio(AudioOutput::new("alloy", AudioFormat::Mp3))
+//!     .build();
+//! let response = client.chat(input).await?;
+//! if let Some(audio) = &response.choices[0].message.audio {
+//!     println!("{}", audio.transcript);
+//!     std::fs::write("answer.mp3", audio.bytes().unwrap()).unwrap();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use base64::engine::general_purpose::STANDARD;
+use base64::{DecodeError, Engine};
+use serde::{Deserialize, Serialize};
+
+/// A kind of output the model may produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Text,
+    Audio,
+}
+
+/// The encoding of audio data.
+///
+/// Input audio has to be [`Wav`](Self::Wav) or [`Mp3`](Self::Mp3); output audio may use any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Flac,
+    Opus,
+    /// Raw 16-bit little-endian samples at 24 kHz.
+    Pcm16,
+}
+
+/// How the model's spoken answer is produced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AudioOutput {
+    /// The voice to speak with, e.g. `alloy`.
+    pub voice: String,
+    /// The encoding of the returned audio.
+    pub format: AudioFormat,
+}
+
+impl AudioOutput {
+    /// Speaks with `voice`, encoded as `format`.
+    pub fn new(voice: impl Into<String>, format: AudioFormat) -> Self {
+        Self {
+            voice: voice.into(),
+            format,
+        }
+    }
+}
+
+/// Recorded audio in an input audio part.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct InputAudio {
+    /// The base64-encoded audio.
+    pub data: String,
+    /// The encoding of the audio.
+    pub format: AudioFormat,
+}
+
+/// The spoken answer of the model.
+///
+/// In streamed responses each chunk carries a piece of `data` and `transcript`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MessageAudio {
+    /// Identifies the audio when the message is sent back in a later request.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// The base64-encoded audio, in the requested format.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub data: String,
+    /// The transcript of the audio.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub transcript: String,
+    /// When the audio expires on the server and can no longer be referenced, in Unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl MessageAudio {
+    /// Decodes the audio data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid base64.
+    pub fn bytes(&self) -> Result<Vec<u8>, DecodeError> {
+        STANDARD.decode(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::ChatResponse;
+    use serde_json::json;
+
+    #[test]
+    fn test_response_audio() {
+        let response: ChatResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o-audio-preview",
+            "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7},
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "audio": {
+                        "id": "audio_1",
+                        "data": "UklGRg==",
+                        "transcript": "Hello!",
+                        "expires_at": 1700003600,
+                    },
+                },
+                "finish_reason": "stop",
+            }],
+        }))
+        .unwrap();
+        let message = &response.choices[0].message;
+        assert!(message.content.is_none());
+        let audio = message.audio.as_ref().unwrap();
+        assert_eq!(audio.transcript, "Hello!");
+        assert_eq!(audio.bytes().unwrap(), b"RIFF");
+        assert_eq!(audio.expires_at, Some(1700003600));
+    }
+}
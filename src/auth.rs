@@ -0,0 +1,571 @@
+//! API key management.
+//!
+//! A client can be configured with several API keys (e.g. from different projects) via
+//! [`ChatGPTClientBuilder::api_keys`](crate::ChatGPTClientBuilder::api_keys). Requests are then
+//! spread across the keys according to a [`KeySelection`] strategy. A key that receives a
+//! `429 Too Many Requests` response is skipped until its `Retry-After` time has passed, or for
+//! 30 seconds if the response does not say; when every key is rate limited, the key that
+//! becomes available first is used.
+//!
+//! Keys can be replaced on a live client with
+//! [`ChatGPTClient::set_api_key`](crate::ChatGPTClient::set_api_key), or fetched before every
+//! request from a [`CredentialsProvider`], e.g. one that reads short-lived keys from a secret
+//! manager. [`EnvVar`] and [`KeyFile`] re-read the key for every request, so rotating an
+//! environment variable or a mounted Kubernetes secret takes effect without a restart.
+//!
+//! Deployments that authenticate with OAuth 2.0 access tokens instead of API keys, such as
+//! Azure OpenAI with Microsoft Entra ID (formerly Azure AD), plug in a [`TokenSource`] via
+//! [`ChatGPTClientBuilder::token_source`](crate::ChatGPTClientBuilder::token_source). The
+//! token is cached and only fetched again shortly before it expires, see [`BearerToken`].
+
+use crate::client::ChatGPTError;
+use crate::rate_limit::retry_after;
+use futures_util::future::{self, BoxFuture};
+use reqwest::header::HeaderMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use web_time::Instant;
+
+/// How long a key is skipped after a `429` response without a `Retry-After` header.
+const DEFAULT_RATE_LIMIT_COOL_DOWN: Duration = Duration::from_secs(30);
+
+/// The credentials a request is authenticated with.
+///
+/// The organization and project, when set, take precedence over the ones configured on the
+/// client builder, but not over the ones in
+/// [`RequestOptions`](crate::client::RequestOptions).
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// The API key, sent as a bearer token.
+    pub api_key: String,
+    /// Organization the request is billed to.
+    pub organization: Option<String>,
+    /// Project the request is scoped to.
+    pub project: Option<String>,
+}
+
+impl Credentials {
+    /// Credentials consisting of just `api_key`.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Credentials")
+            .field("api_key", &"[REDACTED]")
+            .field("organization", &self.organization)
+            .field("project", &self.project)
+            .finish()
+    }
+}
+
+/// Supplies the credentials for each request.
+///
+/// The client awaits [`CredentialsProvider::credentials`] before every request, so
+/// implementations that fetch them remotely (e.g. from AWS Secrets Manager) should cache them
+/// until they expire. [`Credentials`] themselves are a provider that always returns the same
+/// key.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::auth::{Credentials, CredentialsProvider};
+/// use chat_gpt_lib_rs::client::ChatGPTError;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+/// use futures_util::future::BoxFuture;
+///
+/// struct SecretsManager;
+///
+/// impl CredentialsProvider for SecretsManager {
+///     fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+///         Box::pin(async {
+///             // Fetch (or return cached) credentials here.
+///             Ok(Credentials::new("sk-from-secrets-manager"))
+///         })
+///     }
+/// }
+///
+/// let client = ChatGPTClient::builder("", "https://api.openai.com")
+///     .credentials_provider(SecretsManager)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait CredentialsProvider: Send + Sync {
+    /// Returns the credentials to authenticate the next request with.
+    ///
+    /// Errors abort the request; use `ChatGPTError::Credentials` to describe them.
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>>;
+}
+
+impl CredentialsProvider for Credentials {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+        Box::pin(future::ready(Ok(self.clone())))
+    }
+}
+
+/// Reads the API key from an environment variable before every request.
+#[derive(Debug, Clone)]
+pub struct EnvVar {
+    name: String,
+}
+
+impl EnvVar {
+    /// Reads the key from the environment variable `name`, e.g. `OPENAI_API_KEY`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl CredentialsProvider for EnvVar {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+        let result = std::env::var(&self.name)
+            .map(Credentials::new)
+            .map_err(|err| ChatGPTError::Credentials(format!("{}: {err}", self.name)));
+        Box::pin(future::ready(result))
+    }
+}
+
+/// Reads the API key from a file before every request, ignoring surrounding whitespace.
+///
+/// Suited to secrets mounted as files, such as Kubernetes secrets, which are updated in place
+/// when rotated.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct KeyFile {
+    path: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl KeyFile {
+    /// Reads the key from the file at `path`.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CredentialsProvider for KeyFile {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+        let result = std::fs::read_to_string(&self.path)
+            .map(|key| Credentials::new(key.trim()))
+            .map_err(|err| ChatGPTError::Credentials(format!("{}: {err}", self.path.display())));
+        Box::pin(future::ready(result))
+    }
+}
+
+/// How long before its expiry a cached access token is refreshed by default.
+const DEFAULT_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// An OAuth 2.0 access token, sent as a bearer token in place of an API key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    /// The token itself.
+    pub token: String,
+    /// When the token stops being accepted.
+    pub expires_at: Instant,
+}
+
+impl AccessToken {
+    /// A token that expires `expires_in` from now, as reported by a token endpoint.
+    pub fn new(token: impl Into<String>, expires_in: Duration) -> Self {
+        Self {
+            token: token.into(),
+            expires_at: Instant::now() + expires_in,
+        }
+    }
+}
+
+impl Debug for AccessToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("AccessToken")
+            .field("token", &"[REDACTED]")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Fetches access tokens, e.g. from Microsoft Entra ID for the
+/// `https://cognitiveservices.azure.com/.default` scope.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::auth::{AccessToken, TokenSource};
+/// use chat_gpt_lib_rs::client::ChatGPTError;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+/// use futures_util::future::BoxFuture;
+/// use std::time::Duration;
+///
+/// struct ManagedIdentity;
+///
+/// impl TokenSource for ManagedIdentity {
+///     fn token(&self) -> BoxFuture<'_, Result<AccessToken, ChatGPTError>> {
+///         Box::pin(async {
+///             // Request a token from the identity endpoint here.
+///             Ok(AccessToken::new("eyJ0eXAi...", Duration::from_secs(3600)))
+///         })
+///     }
+/// }
+///
+/// let client = ChatGPTClient::builder("", "https://my-resource.openai.azure.com/openai")
+///     .token_source(ManagedIdentity)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait TokenSource: Send + Sync {
+    /// Fetches a new access token.
+    ///
+    /// Errors abort the request; use `ChatGPTError::Credentials` to describe them.
+    fn token(&self) -> BoxFuture<'_, Result<AccessToken, ChatGPTError>>;
+}
+
+/// Authenticates requests with access tokens from a [`TokenSource`].
+///
+/// The token is cached and the source is only asked for a new one when the cached token
+/// expires within the refresh margin, five minutes unless set with
+/// [`BearerToken::refresh_margin`]. Concurrent requests wait for a single refresh.
+pub struct BearerToken<S> {
+    source: S,
+    refresh_margin: Duration,
+    cached: tokio::sync::Mutex<Option<AccessToken>>,
+}
+
+impl<S: TokenSource> BearerToken<S> {
+    /// Authenticates with tokens fetched from `source`.
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            refresh_margin: DEFAULT_TOKEN_REFRESH_MARGIN,
+            cached: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Sets how long before its expiry the token is refreshed.
+    pub fn refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    /// Returns the cached token, refreshing it first if it is about to expire.
+    async fn token(&self) -> Result<String, ChatGPTError> {
+        let mut cached = self.cached.lock().await;
+        let refresh_after = Instant::now() + self.refresh_margin;
+        match &*cached {
+            Some(token) if token.expires_at > refresh_after => Ok(token.token.clone()),
+            _ => {
+                let token = self.source.token().await?;
+                Ok(cached.insert(token).token.clone())
+            }
+        }
+    }
+}
+
+impl<S: TokenSource> CredentialsProvider for BearerToken<S> {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+        Box::pin(async { self.token().await.map(Credentials::new) })
+    }
+}
+
+/// Supplies the API key for each request.
+///
+/// A simpler form of [`CredentialsProvider`] for providers that only deal with the key.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::auth::ApiKeyProvider;
+/// use chat_gpt_lib_rs::client::ChatGPTError;
+/// use futures_util::future::BoxFuture;
+///
+/// struct VaultKeys;
+///
+/// impl ApiKeyProvider for VaultKeys {
+///     fn api_key(&self) -> BoxFuture<'_, Result<String, ChatGPTError>> {
+///         Box::pin(async {
+///             // Fetch (or return a cached) short-lived key here.
+///             Ok("sk-from-vault".to_string())
+///         })
+///     }
+/// }
+/// ```
+pub trait ApiKeyProvider: Send + Sync {
+    /// Returns the API key to authenticate the next request with.
+    ///
+    /// Errors abort the request; use `ChatGPTError::Credentials` to describe them.
+    fn api_key(&self) -> BoxFuture<'_, Result<String, ChatGPTError>>;
+}
+
+/// Adapts an [`ApiKeyProvider`] to a [`CredentialsProvider`].
+pub(crate) struct ApiKeyCredentials<P>(pub(crate) P);
+
+impl<P: ApiKeyProvider> CredentialsProvider for ApiKeyCredentials<P> {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials, ChatGPTError>> {
+        Box::pin(async { self.0.api_key().await.map(Credentials::new) })
+    }
+}
+
+/// How a client with several API keys picks the key for a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeySelection {
+    /// Use the keys in turn.
+    #[default]
+    RoundRobin,
+    /// Prefer keys that were never rate limited, then the one rate limited longest ago.
+    LeastRecentlyRateLimited,
+}
+
+/// The API keys of a client and their rate-limit state.
+#[derive(Debug)]
+pub(crate) struct ApiKeyPool {
+    keys: RwLock<Vec<PooledKey>>,
+    selection: KeySelection,
+    next: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct PooledKey {
+    key: String,
+    health: Mutex<KeyHealth>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyHealth {
+    rate_limited_at: Option<Instant>,
+    rate_limited_until: Option<Instant>,
+}
+
+impl KeyHealth {
+    fn is_rate_limited(&self, now: Instant) -> bool {
+        self.rate_limited_until.is_some_and(|until| until > now)
+    }
+}
+
+impl ApiKeyPool {
+    /// Creates a pool of `keys`.
+    pub(crate) fn new(keys: Vec<String>, selection: KeySelection) -> Self {
+        Self {
+            keys: RwLock::new(pooled(keys)),
+            selection,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replaces all keys of the pool.
+    pub(crate) fn replace(&self, keys: Vec<String>) {
+        *self.keys.write().unwrap() = pooled(keys);
+    }
+
+    /// The first configured key.
+    pub(crate) fn primary(&self) -> String {
+        self.keys.read().unwrap()[0].key.clone()
+    }
+
+    /// Picks the key for the next request.
+    pub(crate) fn select(&self) -> String {
+        let keys = self.keys.read().unwrap();
+        if keys.len() == 1 {
+            return keys[0].key.clone();
+        }
+        let now = Instant::now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let candidates: Vec<(&PooledKey, KeyHealth)> = (0..keys.len())
+            .map(|offset| &keys[(start + offset) % keys.len()])
+            .map(|key| (key, *key.health.lock().unwrap()))
+            .collect();
+
+        let available = candidates
+            .iter()
+            .filter(|(_, health)| !health.is_rate_limited(now));
+        let chosen = match self.selection {
+            KeySelection::RoundRobin => available.map(|(key, _)| *key).next(),
+            KeySelection::LeastRecentlyRateLimited => available
+                .min_by_key(|(_, health)| health.rate_limited_at)
+                .map(|(key, _)| *key),
+        };
+        chosen
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .min_by_key(|(_, health)| health.rate_limited_until)
+                    .map(|(key, _)| *key)
+            })
+            .map_or_else(|| keys[0].key.clone(), |key| key.key.clone())
+    }
+
+    /// Marks `key` as rate limited, based on the headers of its `429` response.
+    pub(crate) fn report_rate_limited(&self, key: &str, headers: &HeaderMap) {
+        let keys = self.keys.read().unwrap();
+        let Some(pooled) = keys.iter().find(|pooled| pooled.key == key) else {
+            return;
+        };
+        let now = Instant::now();
+        let cool_down = retry_after(headers).unwrap_or(DEFAULT_RATE_LIMIT_COOL_DOWN);
+        *pooled.health.lock().unwrap() = KeyHealth {
+            rate_limited_at: Some(now),
+            rate_limited_until: Some(now + cool_down),
+        };
+    }
+}
+
+/// Wraps `keys` for a pool; an empty list yields a single empty key.
+fn pooled(keys: Vec<String>) -> Vec<PooledKey> {
+    let keys = if keys.is_empty() {
+        vec![String::new()]
+    } else {
+        keys
+    };
+    keys.into_iter()
+        .map(|key| PooledKey {
+            key,
+            health: Mutex::new(KeyHealth::default()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(selection: KeySelection) -> ApiKeyPool {
+        ApiKeyPool::new(
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            selection,
+        )
+    }
+
+    fn retry_after_header(seconds: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", seconds.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_round_robin_skips_rate_limited_keys() {
+        let pool = pool(KeySelection::RoundRobin);
+        let picked: Vec<String> = (0..3).map(|_| pool.select()).collect();
+        assert_eq!(picked, ["a", "b", "c"]);
+
+        pool.report_rate_limited("b", &retry_after_header("60"));
+        let picked: Vec<String> = (0..3).map(|_| pool.select()).collect();
+        assert_eq!(picked, ["a", "c", "c"]);
+    }
+
+    #[test]
+    fn test_replace_keys() {
+        let pool = pool(KeySelection::RoundRobin);
+        pool.replace(vec!["rotated".to_string()]);
+        assert_eq!(pool.primary(), "rotated");
+        assert_eq!(pool.select(), "rotated");
+    }
+
+    #[test]
+    fn test_least_recently_rate_limited() {
+        let pool = pool(KeySelection::LeastRecentlyRateLimited);
+        for key in ["a", "b"] {
+            pool.report_rate_limited(key, &retry_after_header("0"));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // "c" was never rate limited, then "a" was rate limited longest ago.
+        assert_eq!(pool.select(), "c");
+        pool.report_rate_limited("c", &retry_after_header("0"));
+        assert_eq!(pool.select(), "a");
+    }
+
+    #[test]
+    fn test_all_keys_rate_limited() {
+        let pool = pool(KeySelection::RoundRobin);
+        pool.report_rate_limited("a", &retry_after_header("30"));
+        pool.report_rate_limited("b", &retry_after_header("10"));
+        pool.report_rate_limited("c", &retry_after_header("20"));
+        assert_eq!(pool.select(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_env_var_and_key_file() {
+        std::env::set_var("CHAT_GPT_LIB_RS_TEST_KEY", "sk-from-env");
+        let credentials = EnvVar::new("CHAT_GPT_LIB_RS_TEST_KEY")
+            .credentials()
+            .await
+            .unwrap();
+        assert_eq!(credentials, Credentials::new("sk-from-env"));
+        assert!(matches!(
+            EnvVar::new("CHAT_GPT_LIB_RS_UNSET_KEY").credentials().await,
+            Err(ChatGPTError::Credentials(_))
+        ));
+
+        let path = std::env::temp_dir().join(format!("chat-gpt-lib-rs-key-{}", std::process::id()));
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        let key_file = KeyFile::new(&path);
+        assert_eq!(
+            key_file.credentials().await.unwrap().api_key,
+            "sk-from-file"
+        );
+        std::fs::write(&path, "sk-rotated").unwrap();
+        assert_eq!(key_file.credentials().await.unwrap().api_key, "sk-rotated");
+        std::fs::remove_file(&path).unwrap();
+        assert!(key_file.credentials().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_refreshes_before_expiry() {
+        use std::sync::Arc;
+
+        struct CountingSource(Arc<AtomicUsize>, Duration);
+
+        impl TokenSource for CountingSource {
+            fn token(&self) -> BoxFuture<'_, Result<AccessToken, ChatGPTError>> {
+                let n = self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(future::ready(Ok(AccessToken::new(
+                    format!("token-{n}"),
+                    self.1,
+                ))))
+            }
+        }
+
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let long_lived =
+            BearerToken::new(CountingSource(fetches.clone(), Duration::from_secs(3600)));
+        for _ in 0..3 {
+            assert_eq!(long_lived.credentials().await.unwrap().api_key, "token-0");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Tokens expiring within the refresh margin are fetched again for every request.
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let short_lived =
+            BearerToken::new(CountingSource(fetches.clone(), Duration::from_secs(60)));
+        assert_eq!(short_lived.credentials().await.unwrap().api_key, "token-0");
+        assert_eq!(short_lived.credentials().await.unwrap().api_key, "token-1");
+        let short_lived = short_lived.refresh_margin(Duration::from_secs(10));
+        assert_eq!(short_lived.credentials().await.unwrap().api_key, "token-1");
+    }
+
+    #[test]
+    fn test_credentials_debug_redacts_key() {
+        let debug = format!("{:?}", Credentials::new("sk-secret"));
+        assert!(!debug.contains("sk-secret"));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(
+            retry_after(&retry_after_header("1.5")),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(retry_after(&retry_after_header("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,449 @@
+//! Hard spending limits for a client.
+//!
+//! A [`Budget`] caps the tokens or US dollars spent per time window. Once the budget of the
+//! current window is used up, further requests fail fast with
+//! [`ChatGPTError::BudgetExceeded`](crate::client::ChatGPTError::BudgetExceeded) instead of
+//! reaching the API. Spending is accounted from the `usage` reported in responses; streamed
+//! requests ask for it with `stream_options: {"include_usage": true}` and are accounted from
+//! their last chunk. Since spending cannot be priced for models whose prices this crate does
+//! not know, a dollar budget rejects their requests with
+//! [`ChatGPTError::UnpricedModel`](crate::client::ChatGPTError::UnpricedModel).
+//!
+//! A [`TokenBudget`] limits the prompt and completion tokens of each request instead. Prompts
+//! over the limit are either truncated or rejected with
+//! [`ChatGPTError::TokenBudgetExceeded`](crate::client::ChatGPTError::TokenBudgetExceeded),
+//! before any network call. It is set for every request of a client with
+//! [`ChatGPTClientBuilder::token_budget`](crate::ChatGPTClientBuilder::token_budget), or for
+//! the requests of one conversation with
+//! [`Conversation::set_token_budget`](crate::Conversation::set_token_budget).
+
+use crate::client::{ChatGPTError, ChatInput, Message, Usage};
+use crate::models::Model;
+use crate::tokenizer::{count_message_tokens, count_message_tokens_with, TokenEncoder};
+use crate::truncation::TruncationStrategy;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::Mutex;
+use std::time::Duration;
+use web_time::Instant;
+
+/// The quantity a [`Budget`] limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    /// Total (prompt plus completion) tokens.
+    Tokens(u64),
+    /// US dollars, computed from [`Model::pricing`](crate::models::Model::pricing).
+    Dollars(f64),
+}
+
+impl Display for BudgetLimit {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            BudgetLimit::Tokens(tokens) => write!(f, "{tokens} tokens"),
+            BudgetLimit::Dollars(dollars) => write!(f, "${dollars:.2}"),
+        }
+    }
+}
+
+/// A spending limit per time window.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::budget::Budget;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+/// use std::time::Duration;
+///
+/// let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+///     .budget(Budget::dollars(5.0).per(Duration::from_secs(24 * 60 * 60)))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub limit: BudgetLimit,
+    /// Length of the window after which spending resets; `None` means the budget never resets.
+    pub window: Option<Duration>,
+}
+
+impl Budget {
+    /// A budget of `tokens` total tokens that never resets.
+    pub fn tokens(tokens: u64) -> Self {
+        Self {
+            limit: BudgetLimit::Tokens(tokens),
+            window: None,
+        }
+    }
+
+    /// A budget of `dollars` US dollars that never resets.
+    pub fn dollars(dollars: f64) -> Self {
+        Self {
+            limit: BudgetLimit::Dollars(dollars),
+            window: None,
+        }
+    }
+
+    /// Resets the spending every `window`.
+    pub fn per(mut self, window: Duration) -> Self {
+        self.window = Some(window);
+        self
+    }
+}
+
+/// A limit of a [`TokenBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenLimit {
+    /// Tokens of the prompt, estimated with [`count_message_tokens`].
+    Prompt(usize),
+    /// Tokens of the completion, as requested with `max_tokens`.
+    Completion(usize),
+}
+
+impl Display for TokenLimit {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            TokenLimit::Prompt(tokens) => write!(f, "{tokens} prompt tokens"),
+            TokenLimit::Completion(tokens) => write!(f, "{tokens} completion tokens"),
+        }
+    }
+}
+
+/// Limits on the prompt and completion tokens of a single chat request.
+///
+/// Requests without `max_tokens` are capped at the completion limit. Requests over a limit
+/// fail with [`ChatGPTError::TokenBudgetExceeded`], unless a truncation strategy is set: then
+/// the prompt is truncated to fit and `max_tokens` is lowered to the completion limit.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::budget::TokenBudget;
+/// use chat_gpt_lib_rs::truncation::TruncationStrategy;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+///
+/// let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+///     .token_budget(
+///         TokenBudget::prompt(4000)
+///             .completion(500)
+///             .truncate(TruncationStrategy::KeepInstructions),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenBudget {
+    pub max_prompt_tokens: Option<usize>,
+    pub max_completion_tokens: Option<usize>,
+    /// How prompts over the limit are truncated; `None` rejects them.
+    pub truncation: Option<TruncationStrategy>,
+}
+
+impl TokenBudget {
+    /// A budget of `tokens` prompt tokens per request.
+    pub fn prompt(tokens: usize) -> Self {
+        Self {
+            max_prompt_tokens: Some(tokens),
+            ..Default::default()
+        }
+    }
+
+    /// Limits the completion of each request to `tokens` tokens.
+    pub fn completion(mut self, tokens: usize) -> Self {
+        self.max_completion_tokens = Some(tokens);
+        self
+    }
+
+    /// Truncates prompts over the limit with `strategy` instead of rejecting them.
+    pub fn truncate(mut self, strategy: TruncationStrategy) -> Self {
+        self.truncation = Some(strategy);
+        self
+    }
+
+    /// Fits `input` into the budget, with its prompt tokens estimated by
+    /// [`count_message_tokens`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatGPTError::TokenBudgetExceeded` if `input` exceeds a limit and cannot be
+    /// truncated to fit.
+    pub fn apply(&self, input: &mut ChatInput) -> Result<(), ChatGPTError> {
+        self.apply_counted(input, &count_message_tokens)
+    }
+
+    /// Fits `input` into the budget like [`TokenBudget::apply`], but with its prompt tokens
+    /// counted exactly by `encoder`, the tokenizer of the model.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatGPTError::TokenBudgetExceeded` if `input` exceeds a limit and cannot be
+    /// truncated to fit.
+    pub fn apply_with(
+        &self,
+        input: &mut ChatInput,
+        encoder: &(impl TokenEncoder + ?Sized),
+    ) -> Result<(), ChatGPTError> {
+        self.apply_counted(input, &|messages| {
+            count_message_tokens_with(messages, encoder)
+        })
+    }
+
+    /// Fits `input` into the budget, with its prompt tokens counted by `count`.
+    pub(crate) fn apply_counted(
+        &self,
+        input: &mut ChatInput,
+        count: &dyn Fn(&[Message]) -> usize,
+    ) -> Result<(), ChatGPTError> {
+        if let Some(limit) = self.max_prompt_tokens {
+            if let Some(strategy) = &self.truncation {
+                strategy.truncate_counted(&mut input.messages, limit, count);
+            }
+            let tokens = count(&input.messages);
+            if tokens > limit {
+                return Err(ChatGPTError::TokenBudgetExceeded {
+                    limit: TokenLimit::Prompt(limit),
+                    tokens,
+                });
+            }
+        }
+        if let Some(limit) = self.max_completion_tokens {
+            match input.max_tokens {
+                Some(tokens) if tokens > limit && self.truncation.is_none() => {
+                    return Err(ChatGPTError::TokenBudgetExceeded {
+                        limit: TokenLimit::Completion(limit),
+                        tokens,
+                    });
+                }
+                Some(tokens) if tokens <= limit => {}
+                _ => input.max_tokens = Some(limit),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the spending against a [`Budget`].
+#[derive(Debug)]
+pub(crate) struct BudgetTracker {
+    budget: Budget,
+    state: Mutex<Spending>,
+}
+
+#[derive(Debug)]
+struct Spending {
+    window_start: Instant,
+    tokens: u64,
+    dollars: f64,
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            state: Mutex::new(Spending {
+                window_start: Instant::now(),
+                tokens: 0,
+                dollars: 0.0,
+            }),
+        }
+    }
+
+    fn current(&self) -> std::sync::MutexGuard<'_, Spending> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(window) = self.budget.window {
+            if state.window_start.elapsed() >= window {
+                *state = Spending {
+                    window_start: Instant::now(),
+                    tokens: 0,
+                    dollars: 0.0,
+                };
+            }
+        }
+        state
+    }
+
+    /// Fails with `ChatGPTError::BudgetExceeded` if the budget of the current window is used up.
+    pub(crate) fn check(&self) -> Result<(), ChatGPTError> {
+        let state = self.current();
+        let (spent, exhausted) = match self.budget.limit {
+            BudgetLimit::Tokens(limit) => {
+                (BudgetLimit::Tokens(state.tokens), state.tokens >= limit)
+            }
+            BudgetLimit::Dollars(limit) => {
+                (BudgetLimit::Dollars(state.dollars), state.dollars >= limit)
+            }
+        };
+        if exhausted {
+            Err(ChatGPTError::BudgetExceeded {
+                limit: self.budget.limit,
+                spent,
+                resets_in: self
+                    .budget
+                    .window
+                    .map(|window| window.saturating_sub(state.window_start.elapsed())),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fails with `ChatGPTError::UnpricedModel` if this is a dollar budget and the prices of
+    /// `model` are unknown, so its spending could not be accounted.
+    pub(crate) fn check_priced(&self, model: &Model) -> Result<(), ChatGPTError> {
+        match self.budget.limit {
+            BudgetLimit::Dollars(_) if model.known_pricing().is_none() => {
+                Err(ChatGPTError::UnpricedModel {
+                    model: model.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds the usage and cost in US dollars of a finished request to the current window.
+    pub(crate) fn record(&self, usage: &Usage, dollars: f64) {
+        let mut state = self.current();
+        state.tokens += usage.total_tokens.max(0) as u64;
+        state.dollars += dollars;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: i64, completion_tokens: i64) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let tracker = BudgetTracker::new(Budget::tokens(100));
+        assert!(tracker.check().is_ok());
+
+        tracker.record(&usage(60, 30), 0.0);
+        assert!(tracker.check().is_ok());
+
+        tracker.record(&usage(5, 5), 0.0);
+        match tracker.check() {
+            Err(ChatGPTError::BudgetExceeded {
+                limit,
+                spent,
+                resets_in,
+            }) => {
+                assert_eq!(limit, BudgetLimit::Tokens(100));
+                assert_eq!(spent, BudgetLimit::Tokens(100));
+                assert_eq!(resets_in, None);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_token_budget_per_request() {
+        use crate::client::Message;
+
+        let mut input = ChatInput {
+            messages: vec![Message::user("a".repeat(400)), Message::user("Hi")],
+            ..Default::default()
+        };
+        let budget = TokenBudget::prompt(50).completion(100);
+        match budget.apply(&mut input.clone()) {
+            Err(ChatGPTError::TokenBudgetExceeded { limit, tokens }) => {
+                assert_eq!(limit, TokenLimit::Prompt(50));
+                assert_eq!(tokens, 108);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let budget = budget.truncate(TruncationStrategy::DropOldest);
+        input.max_tokens = Some(1000);
+        budget.apply(&mut input).unwrap();
+        assert_eq!(input.messages.len(), 1);
+        assert_eq!(input.max_tokens, Some(100));
+
+        input.max_tokens = Some(1000);
+        assert!(matches!(
+            TokenBudget::default().completion(100).apply(&mut input),
+            Err(ChatGPTError::TokenBudgetExceeded {
+                limit: TokenLimit::Completion(100),
+                tokens: 1000,
+            })
+        ));
+        input.max_tokens = None;
+        TokenBudget::default()
+            .completion(100)
+            .apply(&mut input)
+            .unwrap();
+        assert_eq!(input.max_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_token_budget_with_tokenizer() {
+        use crate::client::Message;
+
+        let input = ChatInput {
+            messages: vec![Message::user("word ".repeat(100)), Message::user("Hi")],
+            ..Default::default()
+        };
+        // One token per word, 100 instead of the estimated 125.
+        let words = |text: &str| text.split_whitespace().map(|_| 0).collect::<Vec<u32>>();
+        let budget = TokenBudget::prompt(110).truncate(TruncationStrategy::DropOldest);
+
+        let mut estimated = input.clone();
+        budget.apply(&mut estimated).unwrap();
+        assert_eq!(estimated.messages.len(), 1);
+        let mut counted = input;
+        budget.apply_with(&mut counted, &words).unwrap();
+        assert_eq!(counted.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_dollar_budget() {
+        let tracker = BudgetTracker::new(Budget::dollars(1.0));
+        // 100k prompt tokens of gpt-4 cost $3.
+        let usage = usage(100_000, 0);
+        tracker.record(&usage, Model::Gpt_4.pricing().cost(&usage));
+        assert!(matches!(
+            tracker.check(),
+            Err(ChatGPTError::BudgetExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dollar_budget_rejects_unpriced_models() {
+        let tracker = BudgetTracker::new(Budget::dollars(1.0));
+        assert!(tracker.check_priced(&Model::Gpt_4o).is_ok());
+        assert!(tracker
+            .check_priced(&Model::Other("gpt-4o-2024-08-06".to_string()))
+            .is_ok());
+        assert!(matches!(
+            tracker.check_priced(&Model::Other("llama3.2".to_string())),
+            Err(ChatGPTError::UnpricedModel { model }) if model == "llama3.2"
+        ));
+
+        let tracker = BudgetTracker::new(Budget::tokens(100));
+        assert!(tracker
+            .check_priced(&Model::Other("llama3.2".to_string()))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_budget_window_resets() {
+        let tracker = BudgetTracker::new(Budget::tokens(10).per(Duration::from_millis(20)));
+        tracker.record(&usage(10, 0), 0.0);
+        assert!(tracker.check().is_err());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(tracker.check().is_ok());
+    }
+
+    #[test]
+    fn test_budget_limit_display() {
+        assert_eq!(BudgetLimit::Tokens(500).to_string(), "500 tokens");
+        assert_eq!(BudgetLimit::Dollars(2.5).to_string(), "$2.50");
+    }
+}
@@ -0,0 +1,466 @@
+//! Response caching.
+//!
+//! A [`ResponseCache`] stores the responses of deterministic chat requests (`temperature` set
+//! to `0`) under a [`CacheKey`] derived from the request, so repeating an identical request is
+//! answered from the cache. [`LruCache`] is an in-memory implementation:
+//!
+//! ```
+//! use chat_gpt_lib_rs::cache::LruCache;
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//!
+//! let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+//!     .response_cache(LruCache::new(1000))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! On native targets, [`DiskCache`] persists the responses as JSON files in a directory, so
+//! they survive process restarts (e.g. when rerunning a batch job over mostly identical
+//! prompts).
+//!
+//! A [`SemanticCache`] embeds the prompt of every chat request and serves the cached
+//! completion of an earlier request whose prompt embedding is close enough, so rephrasings of
+//! the same question ("What's the capital of France?" / "Capital of France?") are answered
+//! without another completion call. Enable it with
+//! [`ChatGPTClientBuilder::semantic_cache`](crate::ChatGPTClientBuilder::semantic_cache):
+//!
+//! ```
+//! use chat_gpt_lib_rs::cache::SemanticCache;
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//!
+//! let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+//!     .semantic_cache(SemanticCache::new(0.95).max_entries(500))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Each lookup costs one embeddings call. Only prompts sent to the same [`Model`] are compared;
+//! sampling parameters such as `temperature` are not part of the cache key.
+
+use crate::client::{ChatInput, ChatResponse};
+use crate::embeddings::{cosine_similarity, EmbeddingsInput};
+use crate::models::{EmbeddingModel, Model};
+#[cfg(not(target_arch = "wasm32"))]
+use log::{debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io};
+
+/// Identifies a chat request in a [`ResponseCache`].
+///
+/// The key is a 64-bit FNV-1a hash of the normalized JSON body of the request, which is stable
+/// across processes and crate versions as long as the request serializes the same way. Fields
+/// that do not influence the completion (`stream`, `user`) are not part of the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey(pub u64);
+
+impl CacheKey {
+    /// The key of the request `input`.
+    pub fn for_input(input: &ChatInput) -> Self {
+        let mut body = serde_json::to_value(input).unwrap_or_default();
+        if let Some(body) = body.as_object_mut() {
+            body.remove("stream");
+            body.remove("user");
+        }
+        // Objects serialize with sorted keys, so the JSON text is a canonical form.
+        Self(fnv1a(body.to_string().as_bytes()))
+    }
+}
+
+/// Formats the key as 16 lowercase hex digits.
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Whether `input` asks for a deterministic completion that may be served from a
+/// [`ResponseCache`].
+pub(crate) fn is_deterministic(input: &ChatInput) -> bool {
+    input.temperature == Some(0.0)
+}
+
+/// A store for the responses of deterministic chat requests.
+///
+/// Implementations are called inline on the request path and should be cheap.
+pub trait ResponseCache: Send + Sync {
+    /// Returns the cached response for `key`, if any.
+    fn get(&self, key: &CacheKey) -> Option<ChatResponse>;
+    /// Stores `response` under `key`.
+    fn put(&self, key: CacheKey, response: ChatResponse);
+}
+
+/// An in-memory [`ResponseCache`] that evicts the least recently used entry when full.
+#[derive(Debug)]
+pub struct LruCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    entries: HashMap<CacheKey, ChatResponse>,
+    /// Keys from least to most recently used.
+    order: VecDeque<CacheKey>,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &CacheKey) {
+        if let Some(position) = self.order.iter().position(|k| k == key) {
+            self.order.remove(position);
+        }
+        self.order.push_back(*key);
+    }
+}
+
+impl LruCache {
+    /// Creates a cache holding up to `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// Number of cached responses.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Whether the cache holds no responses.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached responses.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+    }
+}
+
+impl ResponseCache for LruCache {
+    fn get(&self, key: &CacheKey) -> Option<ChatResponse> {
+        let mut state = self.state.lock().unwrap();
+        let response = state.entries.get(key).cloned()?;
+        state.touch(key);
+        Some(response)
+    }
+
+    fn put(&self, key: CacheKey, response: ChatResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.entries.insert(key, response).is_none() && state.entries.len() > self.capacity {
+            if let Some(oldest) = state.order.pop_front() {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.touch(&key);
+    }
+}
+
+/// A [`ResponseCache`] storing every response as a JSON file named after its key.
+///
+/// Entries are never evicted; delete the directory (or individual files) to invalidate them.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskCache {
+    /// Opens the cache in `dir`, creating the directory if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the directory cannot be created.
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The directory holding the cached responses.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ResponseCache for DiskCache {
+    fn get(&self, key: &CacheKey) -> Option<ChatResponse> {
+        let path = self.path(key);
+        let contents = fs::read(&path).ok()?;
+        match serde_json::from_slice(&contents) {
+            Ok(response) => Some(response),
+            Err(err) => {
+                debug!("Ignoring unreadable cache entry {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: CacheKey, response: ChatResponse) {
+        let path = self.path(&key);
+        // Write to a temporary file first, so concurrent readers never see a partial entry.
+        let tmp = path.with_extension("json.tmp");
+        let result = serde_json::to_vec(&response)
+            .map_err(io::Error::from)
+            .and_then(|contents| fs::write(&tmp, contents))
+            .and_then(|()| fs::rename(&tmp, &path));
+        if let Err(err) = result {
+            warn!("Failed to write cache entry {}: {err}", path.display());
+        }
+    }
+}
+
+/// Default number of entries kept by a [`SemanticCache`].
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// A cache of chat responses keyed by the embedding of their prompt.
+#[derive(Debug)]
+pub struct SemanticCache {
+    threshold: f32,
+    embedding_model: EmbeddingModel,
+    max_entries: usize,
+    entries: Mutex<VecDeque<CacheEntry>>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    model: Model,
+    embedding: Vec<f32>,
+    response: ChatResponse,
+}
+
+impl SemanticCache {
+    /// Creates a cache that serves a cached response when the cosine similarity of the prompt
+    /// embeddings is at least `threshold`.
+    ///
+    /// Prompts are embedded with `text-embedding-3-small` and up to 1000 entries are kept.
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            embedding_model: EmbeddingModel::TextEmbedding3Small,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Sets the model used to embed prompts.
+    pub fn embedding_model(mut self, model: EmbeddingModel) -> Self {
+        self.embedding_model = model;
+        self
+    }
+
+    /// Sets the number of entries kept; the oldest entries are evicted first.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// The similarity threshold of this cache.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Number of cached responses.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether the cache holds no responses.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached responses.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The embeddings request for the prompt of `input`.
+    pub(crate) fn embeddings_input(&self, input: &ChatInput) -> EmbeddingsInput {
+        EmbeddingsInput {
+            model: self.embedding_model,
+            input: vec![prompt_text(input)],
+            user: input.user.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the cached response for `model` whose prompt is most similar to `embedding`,
+    /// if its similarity reaches the threshold.
+    pub(crate) fn lookup(&self, model: &Model, embedding: &[f32]) -> Option<ChatResponse> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .filter(|entry| entry.model == *model)
+            .map(|entry| (cosine_similarity(&entry.embedding, embedding), entry))
+            .filter(|(similarity, _)| *similarity >= self.threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, entry)| entry.response.clone())
+    }
+
+    /// Caches `response` under the prompt `embedding`, evicting the oldest entry when full.
+    pub(crate) fn insert(&self, model: Model, embedding: Vec<f32>, response: ChatResponse) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(CacheEntry {
+            model,
+            embedding,
+            response,
+        });
+    }
+}
+
+/// The text embedded for a chat request: one `role: content` line per message.
+fn prompt_text(input: &ChatInput) -> String {
+    input
+        .messages
+        .iter()
+        .map(|message| format!("{}: {}", message.role.as_str(), message.content))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Choice, Message, Usage};
+    use crate::models::Role;
+
+    fn response(content: &str) -> ChatResponse {
+        ChatResponse {
+            id: "chatcmpl-test".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4o".to_string(),
+            usage: Usage::default(),
+            choices: vec![Choice::new(Message::assistant(content), "stop")],
+            extensions: Default::default(),
+            request_id: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn content(response: Option<ChatResponse>) -> Option<String> {
+        response.map(|response| response.choices[0].message.content.to_string())
+    }
+
+    #[test]
+    fn test_cache_key_normalization() {
+        let input = |temperature: f64, user: Option<&str>| ChatInput {
+            temperature: Some(temperature),
+            user: user.map(str::to_string),
+            ..Default::default()
+        };
+        let key = CacheKey::for_input(&input(0.0, None));
+        assert_eq!(key, CacheKey::for_input(&input(0.0, Some("alice"))));
+        assert_ne!(key, CacheKey::for_input(&input(0.5, None)));
+        assert_eq!(key.to_string().len(), 16);
+    }
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let cache = LruCache::new(2);
+        cache.put(CacheKey(1), response("one"));
+        cache.put(CacheKey(2), response("two"));
+        assert!(cache.get(&CacheKey(1)).is_some());
+        cache.put(CacheKey(3), response("three"));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(content(cache.get(&CacheKey(1))).as_deref(), Some("one"));
+        assert_eq!(content(cache.get(&CacheKey(2))), None);
+        assert_eq!(content(cache.get(&CacheKey(3))).as_deref(), Some("three"));
+    }
+
+    #[test]
+    fn test_disk_cache_persists_responses() {
+        let dir = std::env::temp_dir().join(format!("chat-gpt-disk-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).unwrap();
+        assert!(cache.get(&CacheKey(7)).is_none());
+        cache.put(CacheKey(7), response("persisted"));
+
+        // A new instance, as after a restart, sees the stored entry.
+        let reopened = DiskCache::new(&dir).unwrap();
+        assert_eq!(
+            content(reopened.get(&CacheKey(7))).as_deref(),
+            Some("persisted")
+        );
+        assert!(dir.join("0000000000000007.json").exists());
+
+        fs::write(dir.join("0000000000000008.json"), "not json").unwrap();
+        assert!(reopened.get(&CacheKey(8)).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lookup_uses_threshold_and_model() {
+        let cache = SemanticCache::new(0.9);
+        cache.insert(Model::Gpt_4o, vec![1.0, 0.0], response("east"));
+        cache.insert(Model::Gpt_4o, vec![0.0, 1.0], response("north"));
+
+        assert_eq!(
+            content(cache.lookup(&Model::Gpt_4o, &[0.99, 0.1])).as_deref(),
+            Some("east")
+        );
+        assert_eq!(content(cache.lookup(&Model::Gpt_4o, &[1.0, 1.0])), None);
+        assert_eq!(content(cache.lookup(&Model::Gpt_4, &[1.0, 0.0])), None);
+    }
+
+    #[test]
+    fn test_insert_evicts_oldest() {
+        let cache = SemanticCache::new(0.9).max_entries(1);
+        cache.insert(Model::Gpt_4o, vec![1.0, 0.0], response("first"));
+        cache.insert(Model::Gpt_4o, vec![0.0, 1.0], response("second"));
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(content(cache.lookup(&Model::Gpt_4o, &[1.0, 0.0])), None);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_prompt_text() {
+        let input = ChatInput {
+            messages: vec![
+                Message {
+                    role: Role::System,
+                    content: "Be brief.".into(),
+                    ..Default::default()
+                },
+                Message {
+                    role: Role::User,
+                    content: "Hi".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(prompt_text(&input), "system: Be brief.\nuser: Hi");
+    }
+}
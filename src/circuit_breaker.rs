@@ -0,0 +1,211 @@
+//! Fail-fast protection against upstream outages.
+//!
+//! A [`CircuitBreaker`] watches the outcome of recent requests. When the share of upstream
+//! failures (transport errors and `5xx` responses) among them reaches the configured rate, the
+//! circuit *opens* and requests fail immediately with
+//! [`ChatGPTError::CircuitOpen`](crate::client::ChatGPTError::CircuitOpen) instead of piling
+//! up behind timeouts. After the cool-down the circuit is *half-open*: a single trial request
+//! is let through, and its outcome either closes the circuit again or restarts the cool-down.
+//!
+//! Client errors such as `4xx` responses (including `429`) say nothing about the health of the
+//! API and are counted as successes.
+
+use crate::client::ChatGPTError;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use web_time::Instant;
+
+/// Configuration of a circuit breaker.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::circuit_breaker::CircuitBreaker;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+/// use std::time::Duration;
+///
+/// let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+///     .circuit_breaker(CircuitBreaker {
+///         cool_down: Duration::from_secs(60),
+///         ..Default::default()
+///     })
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreaker {
+    /// Share of failed requests, in `(0, 1]`, at which the circuit opens.
+    pub failure_rate: f64,
+    /// Number of recent requests the failure rate is computed over.
+    pub window: usize,
+    /// Minimum number of requests in the window before the circuit may open.
+    pub minimum_requests: usize,
+    /// How long the circuit stays open before a trial request is let through.
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_rate: 0.5,
+            window: 20,
+            minimum_requests: 10,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The state of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent normally.
+    Closed,
+    /// Requests fail fast until the cool-down has passed.
+    Open,
+    /// A trial request decides whether the circuit closes or opens again.
+    HalfOpen,
+}
+
+/// Tracks the request outcomes against a [`CircuitBreaker`].
+#[derive(Debug)]
+pub(crate) struct CircuitBreakerTracker {
+    config: CircuitBreaker,
+    state: Mutex<Circuit>,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    /// Outcomes of the most recent requests, `true` for failures.
+    Closed(VecDeque<bool>),
+    Open {
+        since: Instant,
+    },
+    /// A trial request was let through at `since`.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+impl CircuitBreakerTracker {
+    pub(crate) fn new(config: CircuitBreaker) -> Self {
+        Self {
+            config,
+            state: Mutex::new(Circuit::Closed(VecDeque::new())),
+        }
+    }
+
+    /// The current state of the circuit.
+    pub(crate) fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            Circuit::Closed(_) => CircuitState::Closed,
+            Circuit::Open { .. } => CircuitState::Open,
+            Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Fails with `ChatGPTError::CircuitOpen` unless a request may be sent now.
+    pub(crate) fn check(&self) -> Result<(), ChatGPTError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            Circuit::Closed(_) => Ok(()),
+            // A trial that never reported back (e.g. because it was cancelled) does not keep
+            // the circuit half-open forever.
+            Circuit::Open { since } | Circuit::HalfOpen { since } => {
+                let elapsed = since.elapsed();
+                if elapsed >= self.config.cool_down {
+                    *state = Circuit::HalfOpen {
+                        since: Instant::now(),
+                    };
+                    Ok(())
+                } else {
+                    Err(ChatGPTError::CircuitOpen {
+                        retry_in: self.config.cool_down - elapsed,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Records the outcome of a request that passed [`CircuitBreakerTracker::check`].
+    pub(crate) fn record(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            Circuit::Closed(outcomes) => {
+                outcomes.push_back(failed);
+                while outcomes.len() > self.config.window {
+                    outcomes.pop_front();
+                }
+                let failures = outcomes.iter().filter(|failed| **failed).count();
+                if outcomes.len() >= self.config.minimum_requests
+                    && failures as f64 >= self.config.failure_rate * outcomes.len() as f64
+                {
+                    *state = Circuit::Open {
+                        since: Instant::now(),
+                    };
+                }
+            }
+            Circuit::HalfOpen { .. } if failed => {
+                *state = Circuit::Open {
+                    since: Instant::now(),
+                };
+            }
+            Circuit::HalfOpen { .. } => *state = Circuit::Closed(VecDeque::new()),
+            // Requests admitted before the circuit opened do not change its state.
+            Circuit::Open { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(cool_down: Duration) -> CircuitBreakerTracker {
+        CircuitBreakerTracker::new(CircuitBreaker {
+            failure_rate: 0.5,
+            window: 4,
+            minimum_requests: 4,
+            cool_down,
+        })
+    }
+
+    #[test]
+    fn test_opens_at_failure_rate() {
+        let tracker = tracker(Duration::from_secs(60));
+        for failed in [false, true, false] {
+            tracker.check().unwrap();
+            tracker.record(failed);
+        }
+        assert_eq!(tracker.state(), CircuitState::Closed);
+
+        tracker.record(true);
+        assert_eq!(tracker.state(), CircuitState::Open);
+        assert!(matches!(
+            tracker.check(),
+            Err(ChatGPTError::CircuitOpen { retry_in }) if retry_in > Duration::from_secs(59)
+        ));
+    }
+
+    #[test]
+    fn test_half_open_trial() {
+        let tracker = tracker(Duration::from_millis(10));
+        for _ in 0..4 {
+            tracker.record(true);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Only one trial request is let through.
+        assert!(tracker.check().is_ok());
+        assert_eq!(tracker.state(), CircuitState::HalfOpen);
+        assert!(tracker.check().is_err());
+
+        tracker.record(true);
+        assert_eq!(tracker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(tracker.check().is_ok());
+        tracker.record(false);
+        assert_eq!(tracker.state(), CircuitState::Closed);
+    }
+}
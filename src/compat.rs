@@ -0,0 +1,155 @@
+//! Compatibility with OpenAI-compatible servers that deviate from the OpenAI format.
+//!
+//! Self-hosted servers such as vLLM, llama.cpp and LocalAI implement the chat completions API,
+//! but their responses do not always match OpenAI's exactly. With [`CompatMode::Lenient`],
+//! set through [`ChatGPTClientBuilder::compat_mode`](crate::ChatGPTClientBuilder::compat_mode),
+//! responses are normalized before they are deserialized:
+//!
+//! - Token counts, `created` timestamps and choice indices sent as strings (`"12"`) are
+//!   read as numbers.
+//! - `null` or missing `id`, `object` and `created` fields of stream chunks are read as empty.
+//! - Non-standard finish reasons are mapped onto the OpenAI ones: `eos`, `eos_token`,
+//!   `end_turn` and `stop_sequence` become `stop`, `max_tokens` and `max_length` become
+//!   `length`, and `tool_use` becomes `tool_calls`.
+//!
+//! Fields that OpenAI sends but these servers often omit, such as `usage` or
+//! `system_fingerprint`, are optional in either mode.
+
+use serde_json::{Map, Value};
+
+/// How strictly responses have to follow the OpenAI format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompatMode {
+    /// Responses are deserialized as they are.
+    #[default]
+    Strict,
+    /// Responses are normalized first, see the [module documentation](self).
+    Lenient,
+}
+
+/// Fields whose values are integers in the OpenAI format.
+const INTEGER_FIELDS: &[&str] = &[
+    "created",
+    "index",
+    "prompt_tokens",
+    "completion_tokens",
+    "total_tokens",
+    "cached_tokens",
+    "audio_tokens",
+    "reasoning_tokens",
+];
+
+/// Metadata fields of stream chunks that lenient mode fills in when missing.
+const CHUNK_METADATA_FIELDS: &[&str] = &["id", "object", "created"];
+
+/// Normalizes a chat completion (or other) response for lenient deserialization.
+pub(crate) fn normalize_response(value: &mut Value) {
+    normalize_integers(value);
+    if let Some(choices) = value.get_mut("choices").and_then(Value::as_array_mut) {
+        for choice in choices {
+            if let Some(finish_reason) = choice.get_mut("finish_reason") {
+                normalize_finish_reason(finish_reason);
+            }
+        }
+    }
+}
+
+/// Normalizes a stream chunk for lenient deserialization.
+pub(crate) fn normalize_chunk(value: &mut Value) {
+    normalize_response(value);
+    if let Some(chunk) = value.as_object_mut() {
+        for field in CHUNK_METADATA_FIELDS {
+            if chunk.get(*field).is_none_or(Value::is_null) {
+                let default = if *field == "created" {
+                    Value::from(0)
+                } else {
+                    Value::from("")
+                };
+                chunk.insert(field.to_string(), default);
+            }
+        }
+    }
+}
+
+/// Converts string values of [`INTEGER_FIELDS`] anywhere in `value` to numbers.
+fn normalize_integers(value: &mut Value) {
+    match value {
+        Value::Object(object) => normalize_object_integers(object),
+        Value::Array(values) => values.iter_mut().for_each(normalize_integers),
+        _ => {}
+    }
+}
+
+fn normalize_object_integers(object: &mut Map<String, Value>) {
+    for (key, value) in object.iter_mut() {
+        if let Value::String(text) = value {
+            if INTEGER_FIELDS.contains(&key.as_str()) {
+                if let Ok(number) = text.trim().parse::<i64>() {
+                    *value = Value::from(number);
+                }
+            }
+        } else {
+            normalize_integers(value);
+        }
+    }
+}
+
+/// Maps a non-standard finish reason onto the corresponding OpenAI one.
+fn normalize_finish_reason(finish_reason: &mut Value) {
+    let Value::String(reason) = finish_reason else {
+        return;
+    };
+    let normalized = match reason.as_str() {
+        "eos" | "eos_token" | "end_turn" | "stop_sequence" => "stop",
+        "max_tokens" | "max_length" => "length",
+        "tool_use" => "tool_calls",
+        _ => return,
+    };
+    *reason = normalized.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ChatResponse;
+    use crate::stream::ChatChunk;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_response() {
+        let mut value = json!({
+            "id": "cmpl-1",
+            "object": "chat.completion",
+            "created": "1700000000",
+            "model": "local",
+            "usage": {"prompt_tokens": "5", "completion_tokens": 2, "total_tokens": "7"},
+            "choices": [{
+                "index": "0",
+                "message": {"role": "assistant", "content": "12"},
+                "finish_reason": "eos_token",
+            }],
+        });
+        assert!(serde_json::from_value::<ChatResponse>(value.clone()).is_err());
+
+        normalize_response(&mut value);
+        let response: ChatResponse = serde_json::from_value(value).unwrap();
+        assert_eq!(response.created, 1700000000);
+        assert_eq!(response.usage.total_tokens, 7);
+        assert_eq!(response.choices[0].finish_reason, "stop");
+        // Content is never touched, even if it looks like a number.
+        assert_eq!(response.choices[0].message.content, "12");
+    }
+
+    #[test]
+    fn test_normalize_chunk() {
+        let mut value = json!({
+            "id": null,
+            "model": "local",
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "max_tokens"}],
+        });
+        normalize_chunk(&mut value);
+        let chunk: ChatChunk = serde_json::from_value(value).unwrap();
+        assert_eq!(chunk.id, "");
+        assert_eq!(chunk.choices[0].finish_reason.as_deref(), Some("length"));
+    }
+}
@@ -0,0 +1,400 @@
+//! The content of chat messages.
+//!
+//! A message's [`Content`] is either plain text, a list of [`ContentPart`]s, or absent, as in
+//! assistant messages that only carry tool calls. Text converts into content, so messages are
+//! built from strings directly:
+//!
+//! ```
+//! use chat_gpt_lib_rs::content::{Content, ContentPart};
+//! use chat_gpt_lib_rs::{Message, Role};
+//!
+//! let message = Message::new(Role::User, "Hello!");
+//! assert_eq!(message.content, "Hello!");
+//!
+//! let parts = Content::Parts(vec![ContentPart::text("Hello, "), ContentPart::text("world!")]);
+//! assert_eq!(parts.text(), "Hello, world!");
+//! ```
+//!
+//! Images are attached as parts, either by URL or embedded as a `data:` URL:
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::content::{ContentPart, ImageDetail};
+//!
+//! # fn run() -> std::io::Result<()> {
+//! let parts = vec![
+//!     ContentPart::text("What is in these images?"),
+//!     ContentPart::image_url("https://example.com/cat.jpg").with_detail(ImageDetail::Low),
+//!     ContentPart::image_from_path("dog.png")?,
+//! ];
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Documents such as PDFs are attached the same way, for models that read them directly:
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::content::ContentPart;
+//!
+//! # fn run() -> std::io::Result<()> {
+//! let parts = vec![
+//!     ContentPart::text("Summarize this report."),
+//!     ContentPart::file_from_path("report.pdf")?,
+//! ];
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::audio::{AudioFormat, InputAudio};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+/// The content of a message.
+///
+/// Serializes as a string, an array of parts or `null` respectively.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    /// Plain text.
+    Text(String),
+    /// A list of parts, e.g. text followed by an image.
+    Parts(Vec<ContentPart>),
+    /// No content, as in assistant messages that only carry tool calls.
+    #[default]
+    None,
+}
+
+/// A part of a multi-part message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A piece of text.
+    Text { text: String },
+    /// An image, by URL or as a `data:` URL.
+    ImageUrl { image_url: ImageUrl },
+    /// Recorded audio, e.g. a spoken question.
+    InputAudio { input_audio: InputAudio },
+    /// A document such as a PDF, for models that read files directly.
+    File { file: FileInput },
+}
+
+/// The document of a file part, either uploaded before or embedded in the request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct FileInput {
+    /// The ID of a file uploaded to the Files API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    /// The document as a `data:` URL with the base64-encoded contents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<String>,
+    /// The name of the embedded document, e.g. `report.pdf`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+/// The location of an image part.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ImageUrl {
+    /// An `https` URL, or a `data:` URL with the base64-encoded image.
+    pub url: String,
+    /// The resolution the model sees the image at; the API picks one if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+/// The resolution an image is processed at, trading cost for fidelity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+    /// A 512x512 version of the image, for a fixed, low number of tokens.
+    Low,
+    /// The full image in 512x512 tiles, for more tokens the larger it is.
+    High,
+    /// Let the model choose based on the image size.
+    Auto,
+}
+
+impl ContentPart {
+    /// A text part.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// An image the API downloads from `url`.
+    pub fn image_url(url: impl Into<String>) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    /// Recorded audio in WAV or MP3 `format`, see [`audio`](crate::audio).
+    pub fn input_audio(bytes: &[u8], format: AudioFormat) -> Self {
+        Self::InputAudio {
+            input_audio: InputAudio {
+                data: STANDARD.encode(bytes),
+                format,
+            },
+        }
+    }
+
+    /// A document uploaded to the Files API before, by its ID (`file-...`).
+    pub fn file_id(file_id: impl Into<String>) -> Self {
+        Self::File {
+            file: FileInput {
+                file_id: Some(file_id.into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A document embedded in the request, e.g. the contents of a PDF named `filename`.
+    pub fn file_from_bytes(filename: impl Into<String>, bytes: &[u8]) -> Self {
+        let mime_type = if bytes.starts_with(b"%PDF") {
+            "application/pdf"
+        } else {
+            "application/octet-stream"
+        };
+        Self::File {
+            file: FileInput {
+                file_data: Some(format!(
+                    "data:{mime_type};base64,{}",
+                    STANDARD.encode(bytes)
+                )),
+                filename: Some(filename.into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A document read from the file at `path`, named after the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn file_from_path(path: impl AsRef<Path>) -> IoResult<Self> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::file_from_bytes(filename, &std::fs::read(path)?))
+    }
+
+    /// Sets the detail level of an image part; other parts are returned unchanged.
+    pub fn with_detail(mut self, detail: ImageDetail) -> Self {
+        if let Self::ImageUrl { image_url } = &mut self {
+            image_url.detail = Some(detail);
+        }
+        self
+    }
+
+    /// An image embedded in the request, in PNG, JPEG, GIF or WebP format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if `bytes` are not in one of these formats.
+    pub fn image_from_bytes(bytes: &[u8]) -> IoResult<Self> {
+        let mime_type = image_mime_type(bytes).ok_or_else(|| {
+            IoError::new(ErrorKind::InvalidData, "not a PNG, JPEG, GIF or WebP image")
+        })?;
+        Ok(Self::image_url(format!(
+            "data:{mime_type};base64,{}",
+            STANDARD.encode(bytes)
+        )))
+    }
+
+    /// An image read from the file at `path`, see [`ContentPart::image_from_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not in a supported format.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn image_from_path(path: impl AsRef<Path>) -> IoResult<Self> {
+        Self::image_from_bytes(&std::fs::read(path)?)
+    }
+}
+
+/// Detects the MIME type of an image from its magic bytes.
+fn image_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+impl Content {
+    /// The text of the content; the text parts are concatenated, other parts are skipped.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<String>()
+                .into(),
+            Self::None => Cow::Borrowed(""),
+        }
+    }
+
+    /// Whether there is no content.
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+}
+
+/// Formats the [`text`](Content::text) of the content.
+impl Display for Content {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(&self.text())
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Option<String>> for Content {
+    fn from(text: Option<String>) -> Self {
+        text.map_or(Self::None, Self::Text)
+    }
+}
+
+impl From<Vec<ContentPart>> for Content {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+/// Compares the [`text`](Content::text) of the content.
+impl PartialEq<str> for Content {
+    fn eq(&self, other: &str) -> bool {
+        self.text() == other
+    }
+}
+
+impl PartialEq<&str> for Content {
+    fn eq(&self, other: &&str) -> bool {
+        self.text() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_content_serde() {
+        let cases = [
+            (Content::from("Hi"), json!("Hi")),
+            (
+                Content::Parts(vec![ContentPart::text("Hi")]),
+                json!([{"type": "text", "text": "Hi"}]),
+            ),
+            (Content::None, json!(null)),
+        ];
+        for (content, value) in cases {
+            assert_eq!(serde_json::to_value(&content).unwrap(), value);
+            assert_eq!(serde_json::from_value::<Content>(value).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn test_content_text() {
+        let parts = Content::Parts(vec![ContentPart::text("Hel"), ContentPart::text("lo")]);
+        assert_eq!(parts.text(), "Hello");
+        assert_eq!(parts, "Hello");
+        assert_eq!(Content::None.to_string(), "");
+        assert_eq!(Content::from(None), Content::None);
+    }
+
+    #[test]
+    fn test_image_from_bytes() {
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        let part = ContentPart::image_from_bytes(&png).unwrap();
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            json!({"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}})
+        );
+        assert_eq!(image_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        let err = ContentPart::image_from_bytes(b"%PDF-1.7").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let parts = Content::Parts(vec![ContentPart::text("Describe"), part]);
+        assert_eq!(parts.text(), "Describe");
+    }
+
+    #[test]
+    fn test_image_detail() {
+        let part =
+            ContentPart::image_url("https://example.com/cat.jpg").with_detail(ImageDetail::High);
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            json!({
+                "type": "image_url",
+                "image_url": {"url": "https://example.com/cat.jpg", "detail": "high"},
+            })
+        );
+        assert_eq!(
+            ContentPart::text("Hi").with_detail(ImageDetail::Low),
+            ContentPart::text("Hi")
+        );
+    }
+
+    #[test]
+    fn test_input_audio() {
+        let part = ContentPart::input_audio(b"RIFF", AudioFormat::Wav);
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            json!({"type": "input_audio", "input_audio": {"data": "UklGRg==", "format": "wav"}})
+        );
+    }
+
+    #[test]
+    fn test_file_parts() {
+        let part = ContentPart::file_from_bytes("report.pdf", b"%PDF-1.7");
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            json!({"type": "file", "file": {
+                "file_data": "data:application/pdf;base64,JVBERi0xLjc=",
+                "filename": "report.pdf",
+            }})
+        );
+        assert_eq!(
+            serde_json::to_value(ContentPart::file_id("file-abc")).unwrap(),
+            json!({"type": "file", "file": {"file_id": "file-abc"}})
+        );
+    }
+
+    #[test]
+    fn test_message_without_content() {
+        let message: crate::Message =
+            serde_json::from_value(json!({"role": "assistant", "tool_calls": []})).unwrap();
+        assert!(message.content.is_none());
+    }
+}
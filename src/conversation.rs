@@ -0,0 +1,357 @@
+//! Conversation history with branches.
+//!
+//! A [`Conversation`] keeps the messages exchanged with the model, so each request can carry
+//! the full history. Its history lives on named branches: [`Conversation::branch`] starts a
+//! new branch from the current one (or from an earlier point with
+//! [`Conversation::branch_at`]), so alternative continuations, e.g. an answer regenerated with
+//! different settings, can be explored while the original history stays intact.
+//! [`Conversation::fork`] copies the current branch into a separate conversation.
+//!
+//! Long conversations can be kept within the context window with a
+//! [`SummaryMemory`](crate::memory::SummaryMemory), see [`Conversation::set_memory`].
+//!
+//! Transcripts are exported for review with [`Conversation::to_markdown`], and as training
+//! data in the chat format of OpenAI fine-tuning with [`Conversation::to_jsonl`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::{ChatGPTClient, Conversation, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let mut conversation = Conversation::new();
+//! conversation.push(Message::system("You are a poet."));
+//! conversation.push(Message::user("Write a haiku about Rust."));
+//! let response = client.chat(conversation.to_input(Model::Gpt_4o)).await?;
+//! conversation.push_response(&response);
+//!
+//! // Regenerate the answer with a higher temperature, keeping the first one on `main`.
+//! conversation.branch_at("creative", conversation.len() - 1);
+//! let mut input = conversation.to_input(Model::Gpt_4o);
+//! input.temperature = Some(1.2);
+//! conversation.push_response(&client.chat(input).await?);
+//!
+//! conversation.checkout("main");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::budget::TokenBudget;
+use crate::client::{ChatGPTClient, ChatGPTError, ChatInput, ChatResponse, Message};
+use crate::memory::SummaryMemory;
+use crate::models::Model;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The name of the branch a new conversation starts on.
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// The messages of a conversation, on one or more named branches.
+///
+/// The [`SummaryMemory`] and [`TokenBudget`] of a conversation are not serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    branches: BTreeMap<String, Vec<Message>>,
+    current: String,
+    #[serde(skip)]
+    memory: Option<SummaryMemory>,
+    #[serde(skip)]
+    token_budget: Option<TokenBudget>,
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conversation {
+    /// An empty conversation on the [`DEFAULT_BRANCH`].
+    pub fn new() -> Self {
+        Self::from_messages(Vec::new())
+    }
+
+    /// A conversation whose [`DEFAULT_BRANCH`] holds `messages`.
+    pub fn from_messages(messages: Vec<Message>) -> Self {
+        Self {
+            branches: BTreeMap::from([(DEFAULT_BRANCH.to_string(), messages)]),
+            current: DEFAULT_BRANCH.to_string(),
+            memory: None,
+            token_budget: None,
+        }
+    }
+
+    /// The messages of the current branch.
+    pub fn messages(&self) -> &[Message] {
+        &self.branches[&self.current]
+    }
+
+    /// The number of messages on the current branch.
+    pub fn len(&self) -> usize {
+        self.messages().len()
+    }
+
+    /// Whether the current branch has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.messages().is_empty()
+    }
+
+    /// Appends `message` to the current branch.
+    pub fn push(&mut self, message: Message) {
+        self.current_messages().push(message);
+    }
+
+    /// Appends the message of the first choice of `response` to the current branch.
+    pub fn push_response(&mut self, response: &ChatResponse) {
+        if let Some(choice) = response.choices.first() {
+            self.push(choice.message.clone());
+        }
+    }
+
+    /// Removes the last message of the current branch and returns it.
+    pub fn pop(&mut self) -> Option<Message> {
+        self.current_messages().pop()
+    }
+
+    /// A chat request for `model` with the messages of the current branch.
+    pub fn to_input(&self, model: Model) -> ChatInput {
+        ChatInput {
+            model,
+            messages: self.messages().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Summarizes older turns with `memory` before each [`Conversation::chat`], once the
+    /// history grows beyond its threshold.
+    pub fn set_memory(&mut self, memory: SummaryMemory) {
+        self.memory = Some(memory);
+    }
+
+    /// Limits the tokens of each [`Conversation::chat`] request to `budget`, on top of the
+    /// token budget of the client.
+    ///
+    /// Truncation only affects the request; the history of the conversation is kept.
+    pub fn set_token_budget(&mut self, budget: TokenBudget) {
+        self.token_budget = Some(budget);
+    }
+
+    /// Sends the current branch to `model` and appends the answer to it.
+    ///
+    /// With a [`SummaryMemory`] set, older turns are summarized first if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the summarization or chat request fails, or
+    /// `ChatGPTError::TokenBudgetExceeded` if the request exceeds the token budget of the
+    /// conversation.
+    pub async fn chat(
+        &mut self,
+        client: &ChatGPTClient,
+        model: Model,
+    ) -> Result<ChatResponse, ChatGPTError> {
+        if let Some(memory) = self.memory.clone() {
+            memory.compact(client, self.current_messages()).await?;
+        }
+        let mut input = self.to_input(model);
+        if let Some(budget) = &self.token_budget {
+            budget.apply_counted(&mut input, &|messages| {
+                client.count_message_tokens(messages)
+            })?;
+        }
+        let response = client.chat(input).await?;
+        self.push_response(&response);
+        Ok(response)
+    }
+
+    /// A separate conversation with a copy of the current branch, as its [`DEFAULT_BRANCH`],
+    /// and the same memory and token budget.
+    pub fn fork(&self) -> Self {
+        Self {
+            memory: self.memory.clone(),
+            token_budget: self.token_budget,
+            ..Self::from_messages(self.messages().to_vec())
+        }
+    }
+
+    /// Creates the branch `name` with the messages of the current branch and switches to it.
+    ///
+    /// An existing branch of that name is replaced.
+    pub fn branch(&mut self, name: &str) {
+        self.branch_at(name, self.len());
+    }
+
+    /// Creates the branch `name` with the first `len` messages of the current branch and
+    /// switches to it, e.g. to regenerate the last answer with `len - 1`.
+    ///
+    /// An existing branch of that name is replaced.
+    pub fn branch_at(&mut self, name: &str, len: usize) {
+        let messages = self.messages()[..len.min(self.len())].to_vec();
+        self.branches.insert(name.to_string(), messages);
+        self.current = name.to_string();
+    }
+
+    /// Switches to the branch `name`, returning whether it exists.
+    pub fn checkout(&mut self, name: &str) -> bool {
+        let exists = self.branches.contains_key(name);
+        if exists {
+            self.current = name.to_string();
+        }
+        exists
+    }
+
+    /// The name of the current branch.
+    pub fn current_branch(&self) -> &str {
+        &self.current
+    }
+
+    /// The names of all branches, in alphabetical order.
+    pub fn branches(&self) -> impl Iterator<Item = &str> {
+        self.branches.keys().map(String::as_str)
+    }
+
+    /// The messages of the branch `name`, if it exists.
+    pub fn branch_messages(&self, name: &str) -> Option<&[Message]> {
+        self.branches.get(name).map(Vec::as_slice)
+    }
+
+    /// Deletes the branch `name` and returns its messages; the current branch cannot be
+    /// deleted.
+    pub fn delete_branch(&mut self, name: &str) -> Option<Vec<Message>> {
+        if name == self.current {
+            return None;
+        }
+        self.branches.remove(name)
+    }
+
+    /// The current branch as a Markdown transcript, with a heading per message.
+    ///
+    /// Only the text of each message is included; a refusal is shown in place of empty
+    /// content.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        for message in self.messages() {
+            let mut role = message.role.as_str().chars();
+            let mut heading: String = role
+                .next()
+                .into_iter()
+                .flat_map(char::to_uppercase)
+                .collect();
+            heading.push_str(role.as_str());
+            if let Some(label) = message.name.as_ref().or(message.tool_call_id.as_ref()) {
+                heading.push_str(&format!(" ({label})"));
+            }
+            let text = message.content.text();
+            let text = match &message.refusal {
+                Some(refusal) if text.is_empty() => format!("*Refused: {refusal}*"),
+                _ => text.into_owned(),
+            };
+            if !markdown.is_empty() {
+                markdown.push('\n');
+            }
+            markdown.push_str(&format!("### {heading}\n\n{text}\n"));
+        }
+        markdown
+    }
+
+    /// Every branch as a training example in the chat format of OpenAI fine-tuning, one
+    /// `{"messages": [...]}` object per line.
+    pub fn to_jsonl(&self) -> String {
+        #[derive(Serialize)]
+        struct TrainingExample<'a> {
+            messages: &'a [Message],
+        }
+
+        self.branches
+            .values()
+            .map(|messages| {
+                let example = TrainingExample { messages };
+                serde_json::to_string(&example).expect("messages serialize to JSON") + "\n"
+            })
+            .collect()
+    }
+
+    fn current_messages(&mut self) -> &mut Vec<Message> {
+        self.branches
+            .get_mut(&self.current)
+            .expect("current branch exists")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| message.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut conversation = Conversation::new();
+        conversation.push(Message::user("Hi"));
+        conversation.push(Message::assistant("Hello!"));
+
+        conversation.branch_at("retry", 1);
+        conversation.push(Message::assistant("Hey there!"));
+        assert_eq!(conversation.current_branch(), "retry");
+        assert_eq!(contents(conversation.messages()), ["Hi", "Hey there!"]);
+        assert_eq!(
+            contents(conversation.branch_messages(DEFAULT_BRANCH).unwrap()),
+            ["Hi", "Hello!"]
+        );
+
+        assert!(conversation.checkout(DEFAULT_BRANCH));
+        assert!(!conversation.checkout("missing"));
+        assert_eq!(
+            conversation.branches().collect::<Vec<_>>(),
+            ["main", "retry"]
+        );
+        assert!(conversation.delete_branch(DEFAULT_BRANCH).is_none());
+        assert!(conversation.delete_branch("retry").is_some());
+        assert_eq!(conversation.branches().count(), 1);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut conversation = Conversation::new();
+        conversation.push(Message::system("Be brief."));
+        conversation.push(Message::user("Hi"));
+        conversation.branch("retry");
+        conversation.push(Message::assistant("Hello!"));
+        assert_eq!(
+            conversation.to_markdown(),
+            "### System\n\nBe brief.\n\n### User\n\nHi\n\n### Assistant\n\nHello!\n"
+        );
+
+        let lines: Vec<serde_json::Value> = conversation
+            .to_jsonl()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({"messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"},
+            ]})
+        );
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut conversation = Conversation::from_messages(vec![Message::user("Hi")]);
+        let mut fork = conversation.fork();
+        fork.push(Message::assistant("Hello!"));
+        conversation.pop();
+        assert!(conversation.is_empty());
+        assert_eq!(contents(fork.messages()), ["Hi", "Hello!"]);
+        assert_eq!(fork.to_input(Model::Gpt_4o).messages.len(), 2);
+    }
+}
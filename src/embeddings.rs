@@ -0,0 +1,402 @@
+//! Types of the embeddings API and helpers for comparing embedding vectors.
+//!
+//! [`cosine_similarity`], [`dot_product`] and [`euclidean_distance`] compare two vectors, and
+//! [`nearest_neighbors`] finds the vectors most similar to a query by brute force, which is
+//! fast enough for simple semantic search over a few thousand embeddings:
+//!
+//! ```
+//! use chat_gpt_lib_rs::embeddings::nearest_neighbors;
+//!
+//! let documents = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.8, 0.6]];
+//! let neighbors = nearest_neighbors(&[1.0, 0.1], &documents, 2);
+//! assert_eq!(neighbors[0].0, 0);
+//! assert_eq!(neighbors[1].0, 2);
+//! ```
+//!
+//! With the `simd` feature, the sums behind these functions are accumulated in eight
+//! independent lanes, which the compiler turns into SIMD instructions. Results may then differ
+//! from the sequential sums in the last bits.
+
+use crate::client::Usage;
+use crate::models::EmbeddingModel;
+use crate::tokenizer::count_tokens;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::ops::Range;
+
+/// Represents the input for the embeddings API call.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct EmbeddingsInput {
+    pub model: EmbeddingModel,
+    /// The texts to embed; the response holds one embedding per text, in the same order.
+    pub input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The number of dimensions of the embeddings, fewer than the model's full size; only
+    /// supported by the `text-embedding-3` models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    /// How the embeddings are encoded in the response; [`EncodingFormat::Base64`] makes the
+    /// response about a quarter of the size. Either way they are decoded into
+    /// [`Embedding::embedding`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EncodingFormat>,
+    /// Additional request fields, sent alongside the standard ones, like
+    /// [`ChatInput::extra`](crate::ChatInput::extra).
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for EmbeddingsInput {
+    fn default() -> Self {
+        Self {
+            model: EmbeddingModel::TextEmbedding3Small,
+            input: Vec::new(),
+            user: None,
+            dimensions: None,
+            encoding_format: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+impl EmbeddingsInput {
+    /// The input embedding `input` with `model`.
+    pub fn new(model: EmbeddingModel, input: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            model,
+            input: input.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The encoding of embedding vectors in a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    /// JSON arrays of numbers.
+    Float,
+    /// Base64 strings of little-endian 32-bit floats.
+    Base64,
+}
+
+/// How [`ChatGPTClient::embeddings_batched`](crate::ChatGPTClient::embeddings_batched) splits
+/// many texts into requests.
+///
+/// The defaults match the limits of the OpenAI API: at most 2048 texts and 300,000 tokens per
+/// request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingBatching {
+    /// The most texts per request.
+    pub max_inputs: usize,
+    /// The most tokens per request, estimated with [`count_tokens`].
+    pub max_tokens: usize,
+    /// The most requests in flight at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for EmbeddingBatching {
+    fn default() -> Self {
+        Self {
+            max_inputs: 2048,
+            max_tokens: 300_000,
+            concurrency: 4,
+        }
+    }
+}
+
+impl EmbeddingBatching {
+    /// Splits `texts` into consecutive batches within the limits.
+    ///
+    /// A single text over the token limit gets a batch of its own.
+    pub(crate) fn batches(&self, texts: &[String]) -> Vec<Range<usize>> {
+        let mut batches = Vec::new();
+        let (mut start, mut tokens) = (0, 0);
+        for (index, text) in texts.iter().enumerate() {
+            let text_tokens = count_tokens(text);
+            let full = index - start >= self.max_inputs.max(1)
+                || (index > start && tokens + text_tokens > self.max_tokens);
+            if full {
+                batches.push(start..index);
+                (start, tokens) = (index, 0);
+            }
+            tokens += text_tokens;
+        }
+        if start < texts.len() {
+            batches.push(start..texts.len());
+        }
+        batches
+    }
+}
+
+/// Represents the response from the embeddings API call.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct EmbeddingsResponse {
+    pub object: String,
+    pub data: Vec<Embedding>,
+    pub model: String,
+    pub usage: EmbeddingsUsage,
+    /// Fields of the response this crate does not model.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// A single embedding vector in the embeddings API response.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct Embedding {
+    pub object: String,
+    #[serde(deserialize_with = "floats_or_base64")]
+    pub embedding: Vec<f32>,
+    /// Position of the embedded text in [`EmbeddingsInput::input`].
+    pub index: usize,
+}
+
+/// Represents the usage information in the embeddings API response.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct EmbeddingsUsage {
+    pub prompt_tokens: i64,
+    pub total_tokens: i64,
+}
+
+impl From<&EmbeddingsUsage> for Usage {
+    fn from(usage: &EmbeddingsUsage) -> Self {
+        Usage {
+            prompt_tokens: usage.prompt_tokens,
+            total_tokens: usage.total_tokens,
+            ..Default::default()
+        }
+    }
+}
+
+/// Reads an embedding vector sent as an array of numbers or, with
+/// [`EncodingFormat::Base64`], as a base64 string of little-endian floats.
+fn floats_or_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Floats(Vec<f32>),
+        Base64(String),
+    }
+
+    match Encoded::deserialize(deserializer)? {
+        Encoded::Floats(floats) => Ok(floats),
+        Encoded::Base64(data) => {
+            let bytes = STANDARD.decode(data).map_err(D::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(D::Error::custom(
+                    "base64 embedding is not a whole number of floats",
+                ));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+                .collect())
+        }
+    }
+}
+
+impl AsRef<[f32]> for Embedding {
+    fn as_ref(&self) -> &[f32] {
+        &self.embedding
+    }
+}
+
+/// Sums `f(a[i], b[i])` over both vectors, which have the same length.
+#[cfg(not(feature = "simd"))]
+fn sum_pairs(a: &[f32], b: &[f32], f: impl Fn(f32, f32) -> f32) -> f32 {
+    a.iter().zip(b).map(|(&x, &y)| f(x, y)).sum()
+}
+
+/// Sums `f(a[i], b[i])` over both vectors, which have the same length, in eight lanes.
+#[cfg(feature = "simd")]
+fn sum_pairs(a: &[f32], b: &[f32], f: impl Fn(f32, f32) -> f32) -> f32 {
+    const LANES: usize = 8;
+    let mut lanes = [0.0f32; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let remainder: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&x, &y)| f(x, y))
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((lane, &x), &y) in lanes.iter_mut().zip(x).zip(y) {
+            *lane += f(x, y);
+        }
+    }
+    lanes.iter().sum::<f32>() + remainder
+}
+
+/// Computes the dot product of two vectors.
+///
+/// Returns `0.0` if the vectors differ in length. For the normalized vectors returned by the
+/// OpenAI embedding models, it equals the cosine similarity.
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    sum_pairs(a, b, |x, y| x * y)
+}
+
+/// Computes the Euclidean distance of two vectors.
+///
+/// Returns infinity if the vectors differ in length.
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    sum_pairs(a, b, |x, y| (x - y) * (x - y)).sqrt()
+}
+
+/// Computes the cosine similarity of two vectors, in `[-1, 1]`.
+///
+/// Returns `0.0` if the vectors differ in length or either of them is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let norm_a = sum_pairs(a, a, |x, y| x * y);
+    let norm_b = sum_pairs(b, b, |x, y| x * y);
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot_product(a, b) / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Finds the `k` vectors most similar to `query` by [`cosine_similarity`].
+///
+/// Returns the positions of the vectors in `vectors` with their similarity, most similar
+/// first. `vectors` may be embeddings from an [`EmbeddingsResponse`] or plain `Vec<f32>`s.
+pub fn nearest_neighbors<V: AsRef<[f32]>>(
+    query: &[f32],
+    vectors: &[V],
+    k: usize,
+) -> Vec<(usize, f32)> {
+    let mut neighbors: Vec<(usize, f32)> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, vector)| (index, cosine_similarity(query, vector.as_ref())))
+        .collect();
+    neighbors.sort_by(|a, b| b.1.total_cmp(&a.1));
+    neighbors.truncate(k);
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_vector_math() {
+        let a: Vec<f32> = (0..19).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..19).map(|i| (i % 3) as f32).collect();
+        assert!((dot_product(&a, &b) - 165.0).abs() < 1e-3);
+        assert_eq!(dot_product(&a, &b[1..]), 0.0);
+        assert!((euclidean_distance(&[0.0, 3.0], &[4.0, 0.0]) - 5.0).abs() < 1e-6);
+        assert_eq!(euclidean_distance(&[0.0], &[]), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_nearest_neighbors() {
+        let embeddings: Vec<Embedding> = [[0.0, 1.0], [1.0, 0.0], [0.7, 0.7]]
+            .into_iter()
+            .enumerate()
+            .map(|(index, vector)| Embedding {
+                object: "embedding".to_string(),
+                embedding: vector.to_vec(),
+                index,
+            })
+            .collect();
+        let neighbors = nearest_neighbors(&[1.0, 0.2], &embeddings, 2);
+        let indices: Vec<usize> = neighbors.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [1, 2]);
+        assert!(neighbors[0].1 > neighbors[1].1);
+        assert!(nearest_neighbors(&[1.0], &embeddings, 0).is_empty());
+    }
+
+    #[test]
+    fn test_batches() {
+        let batching = EmbeddingBatching {
+            max_inputs: 3,
+            max_tokens: 10,
+            concurrency: 1,
+        };
+        let texts: Vec<String> = ["a".repeat(8), "b".repeat(8), "c".repeat(8), "d".repeat(8)]
+            .into_iter()
+            .chain(["e".repeat(80), "f".repeat(8)])
+            .collect();
+        // Tokens per text: 2, 2, 2 | 2 | 20, over the token limit on its own | 2.
+        assert_eq!(batching.batches(&texts), [0..3, 3..4, 4..5, 5..6]);
+        assert!(batching.batches(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_embeddings_input_serialization() {
+        let input = EmbeddingsInput {
+            input: vec!["hello".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&input).unwrap(),
+            serde_json::json!({"model": "text-embedding-3-small", "input": ["hello"]})
+        );
+    }
+
+    #[test]
+    fn test_embeddings_response_deserialization() {
+        let json = r#"{
+            "object": "list",
+            "data": [{"object": "embedding", "embedding": [0.1, -0.2], "index": 0}],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 3, "total_tokens": 3}
+        }"#;
+        let response: EmbeddingsResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.data[0].embedding, vec![0.1, -0.2]);
+        assert_eq!(response.usage.prompt_tokens, 3);
+    }
+
+    #[test]
+    fn test_base64_embeddings() {
+        let input = EmbeddingsInput {
+            input: vec!["hello".to_string()],
+            dimensions: Some(256),
+            encoding_format: Some(EncodingFormat::Base64),
+            ..Default::default()
+        };
+        let body = serde_json::to_value(&input).unwrap();
+        assert_eq!(body["dimensions"], 256);
+        assert_eq!(body["encoding_format"], "base64");
+
+        let bytes: Vec<u8> = [0.5f32, -2.0]
+            .iter()
+            .flat_map(|float| float.to_le_bytes())
+            .collect();
+        let embedding: Embedding = serde_json::from_value(serde_json::json!({
+            "object": "embedding",
+            "embedding": STANDARD.encode(bytes),
+            "index": 0,
+        }))
+        .unwrap();
+        assert_eq!(embedding.embedding, vec![0.5, -2.0]);
+
+        let truncated = serde_json::json!({"object": "embedding", "embedding": "AAA=", "index": 0});
+        assert!(serde_json::from_value::<Embedding>(truncated).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! The legacy function calling protocol, enabled by the `legacy-functions` feature.
+//!
+//! Before tool calls, the chat API let models call functions declared in the `functions`
+//! request field, answering with a `function_call` instead of content; the function's result
+//! was sent back as a [`Role::Function`](crate::Role) message. OpenAI deprecated the protocol,
+//! but some proxies and older fine-tuned models still only speak it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::functions::{FunctionCallMode, FunctionDefinition};
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let mut input = ChatInput::builder(Model::Gpt3_5Turbo)
+//!     .message(Message::user("What's the weather in Paris?"))
+//!     .functions(vec![FunctionDefinition {
+//!         name: "get_weather".to_string(),
+//!         description: Some("Returns the current weather in a city".to_string()),
+//!         parameters: serde_json::json!({
+//!             "type": "object",
+//!             "properties": {"city": {"type": "string"}},
+//!             "required": ["city"],
+//!         }),
+//!     }])
+//!     .function_call(FunctionCallMode::Auto)
+//!     .build();
+//! let response = client.chat(input.clone()).await?;
+//! let message = response.choices[0].message.clone();
+//! if let Some(call) = &message.function_call {
+//!     let result = format!("Sunny in {}", call.arguments);
+//!     let reply = Message::function_result(&call.name, result);
+//!     input.messages.extend([message, reply]);
+//!     let response = client.chat(input).await?;
+//!     println!("{}", response.choices[0].message.content);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+/// A function the model may call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    /// The name the model calls the function by.
+    pub name: String,
+    /// What the function does, for the model to decide when to call it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The JSON schema of the function's arguments.
+    pub parameters: Value,
+}
+
+/// Whether and which function the model calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionCallMode {
+    /// Never call a function.
+    None,
+    /// Let the model decide.
+    Auto,
+    /// Call the function with this name.
+    Function(String),
+}
+
+impl Serialize for FunctionCallMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Named<'a> {
+            name: &'a str,
+        }
+
+        match self {
+            Self::None => serializer.serialize_str("none"),
+            Self::Auto => serializer.serialize_str("auto"),
+            Self::Function(name) => Named { name }.serialize(serializer),
+        }
+    }
+}
+
+/// A call of a function by the model.
+///
+/// In streamed responses the first chunk carries the `name` and the following ones pieces of
+/// the `arguments`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    /// The name of the called function.
+    #[serde(default)]
+    pub name: String,
+    /// The arguments as a JSON object, which the model may have generated invalid.
+    #[serde(default)]
+    pub arguments: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ChatInput, ChatResponse, Message};
+    use serde_json::json;
+
+    #[test]
+    fn test_function_request_fields() {
+        let input = ChatInput {
+            functions: Some(vec![FunctionDefinition {
+                name: "get_weather".to_string(),
+                description: None,
+                parameters: json!({"type": "object"}),
+            }]),
+            function_call: Some(FunctionCallMode::Function("get_weather".to_string())),
+            ..Default::default()
+        };
+        let body = serde_json::to_value(&input).unwrap();
+        assert_eq!(
+            body["functions"],
+            json!([{"name": "get_weather", "parameters": {"type": "object"}}])
+        );
+        assert_eq!(body["function_call"], json!({"name": "get_weather"}));
+        assert_eq!(
+            serde_json::to_value(FunctionCallMode::Auto).unwrap(),
+            "auto"
+        );
+    }
+
+    #[test]
+    fn test_function_call_round_trip() {
+        let response: ChatResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-3.5-turbo",
+            "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7},
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "function_call": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"},
+                },
+                "finish_reason": "function_call",
+            }],
+        }))
+        .unwrap();
+        let call = response.choices[0].message.function_call.as_ref().unwrap();
+        assert_eq!(call.name, "get_weather");
+
+        let reply = Message::function_result(&call.name, "Sunny");
+        assert_eq!(
+            serde_json::to_value(&reply).unwrap(),
+            json!({"role": "function", "content": "Sunny", "name": "get_weather"})
+        );
+    }
+}
@@ -5,19 +5,103 @@
 //! This crate exports the following main items:
 //!
 //! - [`ChatGPTClient`]: Represents the main client to interact with the ChatGPT API.
+//! - [`ChatGPTClientBuilder`]: Configures a client, e.g. with default headers.
 //! - [`ChatInput`]: Represents the input for the chat API call.
 //! - [`ChatResponse`]: Represents the response from the chat API call.
 //! - [`Message`]: Represents a message in the chat API call.
+//! - [`Content`]: Represents the content of a message, text or several parts.
+//! - [`Conversation`]: Keeps the history of a chat, with branches for alternative continuations.
+//! - [`RequestOptions`]: Per-call options such as a [`CancellationToken`] for aborting requests.
 //! - [`Model`]: Represents the available OpenAI models.
+//! - [`EmbeddingModel`]: Represents the available OpenAI embedding models.
 //! - [`Role`]: Represents the role of a message in the chat API call.
 //! - [`LogitBias`]: Represents the logit bias used in API calls.
+//! - [`ChatChunk`]: Represents a single chunk of a streamed chat API response.
 //! - [`count_tokens`]: Provides a rough estimation of the number of tokens in a given text.
+//!
+//! The crate compiles to `wasm32-unknown-unknown` when the `wasm` feature is enabled; requests
+//! then go through the browser's `fetch` API and streamed responses are read via `wasm-streams`.
+//!
+//! With the `tracing` feature, every API call is wrapped in a `chat_gpt.request` span carrying
+//! the endpoint, model, request id, status, latency and token usage. The `opentelemetry`
+//! feature adds the attributes of the OpenTelemetry GenAI semantic conventions to that span, for
+//! export through e.g. `tracing-opentelemetry`.
+//!
+//! Request counts, latency and token usage can be collected by registering a
+//! [`metrics::MetricsSink`]; the `metrics` feature provides one backed by the `metrics` crate.
+//!
+//! Embeddings are created with [`ChatGPTClient::embeddings`]. They also back the opt-in
+//! [`cache::SemanticCache`], which answers prompts similar to earlier ones from a cache.
+//! Identical deterministic requests can be cached with a [`cache::ResponseCache`] instead.
+//!
+//! A [`rate_limit::RateLimit`] keeps the client within its requests and tokens per minute,
+//! learning them from the rate limit headers of the responses.
+//!
+//! Inputs and outputs are classified by the content policy categories with
+//! [`ChatGPTClient::moderations`], see the [`moderation`] module.
+//!
+//! Problems with a response that do not fail the request, such as a completion cut off at
+//! `max_tokens`, are listed in [`ChatResponse::warnings`], see the [`warnings`] module.
+//!
+//! The [`providers`] module adapts the same request and response types to other vendors, such
+//! as Anthropic.
+//!
 //! For examples and more detailed usage information, please refer to the documentation of each exported item.
 
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("building for wasm32 requires the `wasm` feature of chat-gpt-lib-rs");
+
+pub mod audio;
+pub mod auth;
+pub mod budget;
+pub mod cache;
+pub mod circuit_breaker;
 pub mod client;
+pub mod compat;
+pub mod content;
+pub mod conversation;
+pub mod embeddings;
+#[cfg(feature = "legacy-functions")]
+pub mod functions;
+pub mod guard;
+mod logging;
+pub mod memory;
+pub mod metrics;
 pub mod models;
+pub mod moderation;
+pub mod prompt;
+pub mod providers;
+pub mod rag;
+pub mod rate_limit;
+pub mod redact;
+pub mod registry;
+pub mod retry;
+pub mod sampling;
+pub mod splitter;
+pub mod store;
+pub mod stream;
+mod telemetry;
+#[cfg(all(any(test, feature = "test-util"), not(target_arch = "wasm32")))]
+pub mod test_util;
 pub mod tokenizer;
+pub mod tools;
+pub mod truncation;
+pub mod validation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vcr;
+pub mod warnings;
 
-pub use client::{ChatGPTClient, ChatInput, ChatResponse, Message};
-pub use models::{LogitBias, Model, Role};
-pub use tokenizer::count_tokens;
+pub use client::{
+    BetaFeature, ChatGPTClient, ChatGPTClientBuilder, ChatInput, ChatInputBuilder, ChatResponse,
+    DryRun, Message, RawResponse, RequestOptions,
+};
+pub use content::Content;
+pub use conversation::Conversation;
+pub use models::{
+    BiasOutOfRange, Deprecation, EmbeddingModel, KnowledgeCutoff, LogitBias, Modalities, Model,
+    ModelPricing, Role,
+};
+pub use reqwest::header;
+pub use stream::ChatChunk;
+pub use tokenizer::{count_message_tokens, count_tokens};
+pub use tokio_util::sync::CancellationToken;
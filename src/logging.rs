@@ -0,0 +1,164 @@
+//! Opt-in logging of request and response payloads.
+//!
+//! When enabled via [`ChatGPTClientBuilder::log_payloads`], outgoing requests and incoming
+//! responses are logged in full at debug level under the `chat_gpt_lib_rs::payload` target.
+//! The API key, any secret registered with [`ChatGPTClientBuilder::redact_secret`] and the
+//! values of credential-carrying headers are replaced by `[REDACTED]` before anything is logged.
+//!
+//! [`ChatGPTClientBuilder::log_payloads`]: crate::ChatGPTClientBuilder::log_payloads
+//! [`ChatGPTClientBuilder::redact_secret`]: crate::ChatGPTClientBuilder::redact_secret
+
+use log::debug;
+use reqwest::header::HeaderMap;
+use reqwest::{Request, StatusCode};
+
+/// Log target used for payload logging.
+const TARGET: &str = "chat_gpt_lib_rs::payload";
+
+/// Replacement text for redacted secrets.
+const REDACTED: &str = "[REDACTED]";
+
+/// Headers whose values are never logged.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "api-key",
+    "x-api-key",
+    "cookie",
+    "set-cookie",
+];
+
+/// Logs payloads with secrets redacted.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PayloadLogger {
+    secrets: Vec<String>,
+}
+
+impl PayloadLogger {
+    /// Creates a logger that redacts the given secrets in addition to the API key.
+    pub(crate) fn new(secrets: Vec<String>) -> Self {
+        Self { secrets }
+    }
+
+    /// Replaces every occurrence of the API key and the configured secrets in `text`.
+    pub(crate) fn redact(&self, text: &str, api_key: &str) -> String {
+        let mut secrets: Vec<&str> = self
+            .secrets
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(api_key))
+            .filter(|secret| !secret.is_empty())
+            .collect();
+        // Replace longer secrets first, so a secret containing another one is fully hidden.
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+
+        let mut redacted = text.to_string();
+        for secret in secrets {
+            redacted = redacted.replace(secret, REDACTED);
+        }
+        redacted
+    }
+
+    /// Logs the method, URL, headers and body of an outgoing request.
+    pub(crate) fn log_request(&self, request: &Request, api_key: &str) {
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+        debug!(
+            target: TARGET,
+            "--> {} {}\n{}\n{}",
+            request.method(),
+            self.redact(request.url().as_str(), api_key),
+            self.format_headers(request.headers(), api_key),
+            self.redact(&body, api_key)
+        );
+    }
+
+    /// Logs the status, headers and body of an incoming response.
+    pub(crate) fn log_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+        api_key: &str,
+    ) {
+        debug!(
+            target: TARGET,
+            "<-- {}\n{}\n{}",
+            status,
+            self.format_headers(headers, api_key),
+            self.redact(&String::from_utf8_lossy(body), api_key)
+        );
+    }
+
+    /// Logs the status and headers of a response whose body is streamed.
+    pub(crate) fn log_stream_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        api_key: &str,
+    ) {
+        debug!(
+            target: TARGET,
+            "<-- {} (streaming)\n{}",
+            status,
+            self.format_headers(headers, api_key)
+        );
+    }
+
+    fn format_headers(&self, headers: &HeaderMap, api_key: &str) -> String {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    self.redact(&String::from_utf8_lossy(value.as_bytes()), api_key)
+                };
+                format!("{name}: {value}")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_api_key_and_secrets() {
+        let logger = PayloadLogger::new(vec!["org-secret".to_string(), String::new()]);
+        let redacted = logger.redact(
+            r#"{"key":"sk-123","org":"org-secret","user":"alice"}"#,
+            "sk-123",
+        );
+        assert_eq!(
+            redacted,
+            r#"{"key":"[REDACTED]","org":"[REDACTED]","user":"alice"}"#
+        );
+    }
+
+    #[test]
+    fn test_redact_overlapping_secrets() {
+        let logger = PayloadLogger::new(vec!["sk-123".to_string()]);
+        assert_eq!(logger.redact("sk-123456", "sk-123456"), "[REDACTED]");
+    }
+
+    #[test]
+    fn test_format_headers_hides_credentials() {
+        let logger = PayloadLogger::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer sk-123".parse().unwrap());
+        headers.insert("x-proxy-token", "sk-123".parse().unwrap());
+        headers.insert("content-type", "application/json".parse().unwrap());
+
+        let formatted = logger.format_headers(&headers, "sk-123");
+        assert!(formatted.contains("authorization: [REDACTED]"));
+        assert!(formatted.contains("x-proxy-token: [REDACTED]"));
+        assert!(formatted.contains("content-type: application/json"));
+        assert!(!formatted.contains("sk-123"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Conversation memory that summarizes older turns.
+//!
+//! A [`SummaryMemory`] keeps long-running conversations within the context window: when the
+//! history of a [`Conversation`](crate::Conversation) exceeds a token threshold, its older
+//! turns are summarized by a (typically cheaper) model and replaced with a single `system`
+//! message holding the summary. Instruction messages and the most recent turns are kept as
+//! they are.
+//!
+//! Set on a conversation with [`Conversation::set_memory`](crate::Conversation::set_memory),
+//! the memory is applied before every [`Conversation::chat`](crate::Conversation::chat).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::memory::SummaryMemory;
+//! use chat_gpt_lib_rs::{ChatGPTClient, Conversation, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let mut conversation = Conversation::new();
+//! conversation.set_memory(SummaryMemory::new(Model::Gpt_4oMini, 8000).keep_recent(6));
+//! conversation.push(Message::user("Let's plan a trip to Japan."));
+//! let response = conversation.chat(&client, Model::Gpt_4o).await?;
+//! println!("{}", response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{ChatGPTClient, ChatGPTError, ChatInput, Message};
+use crate::models::Model;
+use crate::tokenizer::count_message_tokens;
+
+/// The instructions the summaries are written with, unless replaced with
+/// [`SummaryMemory::prompt`].
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize the following conversation in a few \
+    sentences. Keep the facts, decisions and open questions needed to continue it.";
+
+/// The prefix of the `system` message replacing the summarized turns.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation: ";
+
+/// Summarizes the older turns of a history that grows beyond a token threshold.
+#[derive(Debug, Clone)]
+pub struct SummaryMemory {
+    model: Model,
+    max_tokens: usize,
+    keep_recent: usize,
+    prompt: String,
+}
+
+impl SummaryMemory {
+    /// Summarizes histories of more than `max_tokens` tokens with `model`.
+    ///
+    /// By default the four most recent messages are kept.
+    pub fn new(model: Model, max_tokens: usize) -> Self {
+        Self {
+            model,
+            max_tokens,
+            keep_recent: 4,
+            prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+        }
+    }
+
+    /// Sets how many of the most recent messages are kept instead of summarized.
+    pub fn keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Replaces the instructions the summaries are written with.
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// Replaces the older turns of `messages` with a summary if they exceed the threshold,
+    /// returning whether they did.
+    ///
+    /// Instruction messages and the most recent messages are kept. An earlier summary is
+    /// summarized along with the turns after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the summarization request fails; `messages` are unchanged
+    /// then.
+    pub async fn compact(
+        &self,
+        client: &ChatGPTClient,
+        messages: &mut Vec<Message>,
+    ) -> Result<bool, ChatGPTError> {
+        if count_message_tokens(messages) <= self.max_tokens {
+            return Ok(false);
+        }
+        let recent = messages.len().saturating_sub(self.keep_recent);
+        let older: Vec<usize> = (0..recent)
+            .filter(|&index| {
+                !messages[index].role.is_instructions() || is_summary(&messages[index])
+            })
+            .collect();
+        if older.is_empty() {
+            return Ok(false);
+        }
+
+        let transcript = older
+            .iter()
+            .map(|&index| {
+                let message = &messages[index];
+                format!("{}: {}", message.role.as_str(), message.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let input = ChatInput {
+            model: self.model.clone(),
+            messages: vec![
+                Message::system(self.prompt.as_str()),
+                Message::user(transcript),
+            ],
+            ..Default::default()
+        };
+        let response = client.chat(input).await?;
+        let summary = response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.to_string())
+            .unwrap_or_default();
+
+        // The summary takes the place of the first summarized message.
+        let mut summary = Some(Message::system(format!("{SUMMARY_PREFIX}{summary}")));
+        *messages = std::mem::take(messages)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, message)| {
+                if older.contains(&index) {
+                    summary.take()
+                } else {
+                    Some(message)
+                }
+            })
+            .collect();
+        Ok(true)
+    }
+}
+
+/// Whether `message` is a summary written by [`SummaryMemory::compact`].
+fn is_summary(message: &Message) -> bool {
+    message.role.is_instructions() && message.content.text().starts_with(SUMMARY_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+    use crate::test_util::{body_model, chat_completion, mock_chat_completions};
+    use wiremock::MockServer;
+
+    #[tokio::test]
+    async fn test_compact() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .and(body_model(Model::Gpt_4oMini))
+            .respond_with(chat_completion("The user is planning a trip."))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = ChatGPTClient::new("dummy_api_key", &server.uri());
+        let memory = SummaryMemory::new(Model::Gpt_4oMini, 100).keep_recent(1);
+
+        let mut messages = vec![
+            Message::system("Be brief."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+        ];
+        assert!(!memory.compact(&client, &mut messages).await.unwrap());
+
+        messages.push(Message::user("a".repeat(400)));
+        messages.push(Message::user("Where should I go?"));
+        assert!(memory.compact(&client, &mut messages).await.unwrap());
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].content, "Be brief.");
+        assert_eq!(messages[1].role, Role::System);
+        assert_eq!(
+            messages[1].content,
+            "Summary of the earlier conversation: The user is planning a trip."
+        );
+        assert_eq!(messages[2].content, "Where should I go?");
+    }
+}
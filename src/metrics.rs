@@ -0,0 +1,122 @@
+//! Hooks for collecting request metrics.
+//!
+//! Register a [`MetricsSink`] with [`ChatGPTClientBuilder::metrics_sink`] and the client reports
+//! a [`RequestMetrics`] record after every API call. With the `metrics` feature,
+//! [`MetricsCrateSink`] forwards those records to the [`metrics`](https://docs.rs/metrics) facade.
+//!
+//! [`ChatGPTClientBuilder::metrics_sink`]: crate::ChatGPTClientBuilder::metrics_sink
+
+use crate::client::Usage;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Metrics of a single API call, reported once the call has finished.
+#[derive(Debug, Clone)]
+pub struct RequestMetrics {
+    /// Endpoint path the request was sent to, e.g. `/v1/chat/completions`.
+    pub endpoint: &'static str,
+    /// Name of the model the request targeted, e.g. `gpt-4o`.
+    pub model: String,
+    /// HTTP status of the final response, or `None` if no response was received.
+    pub status: Option<StatusCode>,
+    /// Time from starting the call until the response (headers, for streams) was received.
+    pub latency: Duration,
+    /// Number of retries performed before the final attempt.
+    pub retries: u32,
+    /// Token usage reported by the API, if the call succeeded.
+    pub usage: Option<Usage>,
+    /// Whether the call returned successfully.
+    pub success: bool,
+}
+
+/// Receives a [`RequestMetrics`] record for every API call made by the client.
+///
+/// Implementations are called inline on the request path and should be cheap.
+pub trait MetricsSink: Send + Sync {
+    /// Records the metrics of a finished API call.
+    fn record(&self, metrics: &RequestMetrics);
+}
+
+/// A [`MetricsSink`] that forwards to the global recorder of the `metrics` crate.
+///
+/// Reported series, all labelled with `endpoint` and `model`:
+/// - `chat_gpt_requests_total` (counter, additionally labelled with `status`)
+/// - `chat_gpt_request_duration_seconds` (histogram)
+/// - `chat_gpt_retries_total` (counter)
+/// - `chat_gpt_tokens_total` (counter, labelled with `kind` = `prompt` | `completion`)
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsCrateSink;
+
+#[cfg(feature = "metrics")]
+impl MetricsSink for MetricsCrateSink {
+    fn record(&self, metrics: &RequestMetrics) {
+        let endpoint = metrics.endpoint;
+        let model = metrics.model.clone();
+        let status = metrics
+            .status
+            .map(|status| status.as_u16().to_string())
+            .unwrap_or_else(|| "error".to_string());
+
+        ::metrics::counter!(
+            "chat_gpt_requests_total",
+            "endpoint" => endpoint,
+            "model" => model.clone(),
+            "status" => status
+        )
+        .increment(1);
+        ::metrics::histogram!(
+            "chat_gpt_request_duration_seconds",
+            "endpoint" => endpoint,
+            "model" => model.clone()
+        )
+        .record(metrics.latency.as_secs_f64());
+        ::metrics::counter!(
+            "chat_gpt_retries_total",
+            "endpoint" => endpoint,
+            "model" => model.clone()
+        )
+        .increment(u64::from(metrics.retries));
+
+        if let Some(usage) = &metrics.usage {
+            ::metrics::counter!(
+                "chat_gpt_tokens_total",
+                "endpoint" => endpoint,
+                "model" => model.clone(),
+                "kind" => "prompt"
+            )
+            .increment(usage.prompt_tokens.max(0) as u64);
+            ::metrics::counter!(
+                "chat_gpt_tokens_total",
+                "endpoint" => endpoint,
+                "model" => model,
+                "kind" => "completion"
+            )
+            .increment(usage.completion_tokens.max(0) as u64);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_crate_sink_without_recorder() {
+        // Without an installed recorder the `metrics` macros are no-ops.
+        MetricsCrateSink.record(&RequestMetrics {
+            endpoint: "/v1/chat/completions",
+            model: "gpt-4o".to_string(),
+            status: None,
+            latency: Duration::from_secs(1),
+            retries: 2,
+            usage: Some(Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+                ..Default::default()
+            }),
+            success: false,
+        });
+    }
+}
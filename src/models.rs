@@ -1,9 +1,13 @@
-use serde::{Deserialize, Serialize};
+use crate::client::{CostBreakdown, Usage};
+use crate::tokenizer::TokenEncoder;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::fmt::Result as FmtResult;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
+use web_time::{SystemTime, UNIX_EPOCH};
 
 /// `Model` enum represents the available OpenAI models.
 ///
@@ -11,33 +15,315 @@ use thiserror::Error;
 /// Currently supported models are:
 /// - Gpt3_5Turbo
 /// - Gpt4
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+///
+/// Models of other vendors, local models and OpenAI models this enum does not list yet are
+/// named with [`Model::Other`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 #[allow(non_camel_case_types)] // Add this line to suppress the warning
 pub enum Model {
-    #[serde(rename = "gpt-3.5-turbo")]
     Gpt3_5Turbo,
-    #[serde(rename = "gpt-4")]
     Gpt_4,
-    #[serde(rename = "gpt-4-32k")]
     Gpt_4_32k,
-    #[serde(rename = "gpt-4-1106-preview")]
     Gpt_4Turbo,
-    #[serde(rename = "gpt-4o")]
     Gpt_4o,
-    #[serde(rename = "gpt-4-vision-preview")]
+    Gpt_4oMini,
     Gpt_4Turbo_Vision,
+    /// Any other model, by the name the API knows it under (e.g. `claude-3-5-sonnet-latest`
+    /// or `llama3.2`). Its context window and pricing are unknown, see [`Model::context_window`]
+    /// and [`Model::pricing`].
+    Other(String),
 }
 
 impl Model {
+    /// Whether the model is an o-series reasoning model (`o1`, `o3-mini`, ...), which takes
+    /// instructions as `developer` instead of `system` messages.
+    pub fn is_reasoning(&self) -> bool {
+        let name = self.to_string();
+        let mut chars = name.chars();
+        chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// Returns the context window of the model, assumed to be 4096 tokens for
+    /// [`Model::Other`].
+    #[deprecated(
+        note = "use `context_window`, or `max_output_tokens` for the limit of `max_tokens`"
+    )]
     pub fn max_tokens(&self) -> usize {
+        self.context_window()
+    }
+
+    /// Returns the context window of the model, the tokens of prompt and completion together,
+    /// assumed to be 4096 tokens for [`Model::Other`].
+    pub fn context_window(&self) -> usize {
         match self {
-            Model::Gpt3_5Turbo => 4096,
+            Model::Gpt3_5Turbo => 16385,
             Model::Gpt_4 => 8192,
             Model::Gpt_4_32k => 32768,
             Model::Gpt_4o => 128000,
+            Model::Gpt_4oMini => 128000,
             Model::Gpt_4Turbo => 128000,
             Model::Gpt_4Turbo_Vision => 128000,
+            Model::Other(_) => 4096,
+        }
+    }
+
+    /// Returns the most tokens the model produces in one completion, the highest `max_tokens`
+    /// a request may set, assumed to be 4096 tokens for [`Model::Other`].
+    pub fn max_output_tokens(&self) -> usize {
+        match self {
+            Model::Gpt3_5Turbo => 4096,
+            Model::Gpt_4 => 8192,
+            Model::Gpt_4_32k => 32768,
+            Model::Gpt_4o => 16384,
+            Model::Gpt_4oMini => 16384,
+            Model::Gpt_4Turbo => 4096,
+            Model::Gpt_4Turbo_Vision => 4096,
+            Model::Other(_) => 4096,
+        }
+    }
+
+    /// Returns the month the model's training data ends, unknown for [`Model::Other`].
+    pub fn knowledge_cutoff(&self) -> Option<KnowledgeCutoff> {
+        let (year, month) = match self {
+            Model::Gpt3_5Turbo | Model::Gpt_4 | Model::Gpt_4_32k => (2021, 9),
+            Model::Gpt_4Turbo | Model::Gpt_4Turbo_Vision => (2023, 4),
+            Model::Gpt_4o | Model::Gpt_4oMini => (2023, 10),
+            Model::Other(_) => return None,
+        };
+        Some(KnowledgeCutoff { year, month })
+    }
+
+    /// Returns what the model takes and produces besides text. [`Model::Other`] is assumed to
+    /// accept images, and nothing else.
+    pub fn modalities(&self) -> Modalities {
+        match self {
+            Model::Gpt_4o | Model::Gpt_4oMini => Modalities {
+                image_input: true,
+                file_input: true,
+                ..Default::default()
+            },
+            Model::Gpt_4Turbo_Vision | Model::Other(_) => Modalities {
+                image_input: true,
+                ..Default::default()
+            },
+            Model::Gpt3_5Turbo | Model::Gpt_4 | Model::Gpt_4_32k | Model::Gpt_4Turbo => {
+                Modalities::default()
+            }
+        }
+    }
+
+    /// Returns when OpenAI retires the model, if it announced so. Known snapshot names in
+    /// [`Model::Other`], such as `gpt-4-32k-0613`, are covered too.
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        let (announced, shutdown, replacement) = match self.to_string().as_str() {
+            "gpt-4-vision-preview" | "gpt-4-1106-vision-preview" => {
+                ("2024-06-06", "2024-12-06", "gpt-4o")
+            }
+            "gpt-4-32k" | "gpt-4-32k-0314" | "gpt-4-32k-0613" => {
+                ("2024-06-06", "2025-06-06", "gpt-4o")
+            }
+            "gpt-4.5-preview" | "gpt-4.5-preview-2025-02-27" => {
+                ("2025-04-14", "2025-07-14", "gpt-4.1")
+            }
+            "o1-preview" | "o1-preview-2024-09-12" => ("2025-04-28", "2025-07-28", "o3"),
+            _ => return None,
+        };
+        Some(Deprecation {
+            announced,
+            shutdown,
+            replacement,
+        })
+    }
+
+    /// Whether OpenAI announced to retire the model, see [`Model::deprecation`].
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecation().is_some()
+    }
+
+    /// Whether the model accepts images in messages, assumed for [`Model::Other`].
+    pub fn supports_images(&self) -> bool {
+        self.modalities().image_input
+    }
+
+    /// Whether the model calls tools, assumed for [`Model::Other`].
+    pub fn supports_tools(&self) -> bool {
+        !matches!(self, Model::Gpt_4Turbo_Vision)
+    }
+
+    /// Returns the list prices of the model, those of its family for the snapshot names of a
+    /// [`Model::Other`], and zero for other models, see [`Model::known_pricing`].
+    pub fn pricing(&self) -> ModelPricing {
+        self.known_pricing().unwrap_or(ModelPricing {
+            input_per_million: 0.0,
+            cached_input_per_million: 0.0,
+            output_per_million: 0.0,
+        })
+    }
+
+    /// Returns the list prices of the model, those of its family for the snapshot names of a
+    /// [`Model::Other`], or `None` if this crate does not know them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chat_gpt_lib_rs::Model;
+    ///
+    /// let snapshot = Model::Other("gpt-4o-2024-08-06".to_string());
+    /// assert_eq!(snapshot.known_pricing(), Some(Model::Gpt_4o.pricing()));
+    /// assert_eq!(Model::Other("llama3.2".to_string()).known_pricing(), None);
+    /// ```
+    pub fn known_pricing(&self) -> Option<ModelPricing> {
+        // Models without prompt caching bill cached tokens at the regular input price.
+        let (input_per_million, cached_input_per_million, output_per_million) = match self {
+            Model::Gpt3_5Turbo => (0.50, 0.50, 1.50),
+            Model::Gpt_4 => (30.0, 30.0, 60.0),
+            Model::Gpt_4_32k => (60.0, 60.0, 120.0),
+            Model::Gpt_4Turbo => (10.0, 10.0, 30.0),
+            Model::Gpt_4o => (2.50, 1.25, 10.0),
+            Model::Gpt_4oMini => (0.15, 0.075, 0.60),
+            Model::Gpt_4Turbo_Vision => (10.0, 10.0, 30.0),
+            Model::Other(name) => return Model::family_of(name)?.known_pricing(),
+        };
+        Some(ModelPricing {
+            input_per_million,
+            cached_input_per_million,
+            output_per_million,
+        })
+    }
+}
+
+/// The month the training data of a model ends, displayed as e.g. `October 2023`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KnowledgeCutoff {
+    pub year: u16,
+    /// The month, from 1 for January to 12 for December.
+    pub month: u8,
+}
+
+impl Display for KnowledgeCutoff {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        const MONTHS: [&str; 12] = [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ];
+        let month = MONTHS
+            .get(usize::from(self.month).wrapping_sub(1))
+            .copied()
+            .unwrap_or("?");
+        write!(f, "{month} {}", self.year)
+    }
+}
+
+/// The retirement of a model, with the days as `YYYY-MM-DD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The day the deprecation was announced.
+    pub announced: &'static str,
+    /// The day the API stops serving the model.
+    pub shutdown: &'static str,
+    /// The model OpenAI recommends instead.
+    pub replacement: &'static str,
+}
+
+impl Deprecation {
+    /// The start of the [`shutdown`](Self::shutdown) day, in UTC.
+    pub fn shutdown_at(&self) -> SystemTime {
+        let mut parts = self
+            .shutdown
+            .split('-')
+            .map(|part| part.parse::<i64>().unwrap_or(1));
+        let mut next = || parts.next().unwrap_or(1);
+        let (year, month, day) = (next(), next(), next());
+        UNIX_EPOCH + Duration::from_secs(days_from_civil(year, month, day) as u64 * 86400)
+    }
+
+    /// Whether the model is no longer served.
+    pub fn is_shut_down(&self) -> bool {
+        SystemTime::now() >= self.shutdown_at()
+    }
+}
+
+impl Display for Deprecation {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let verb = if self.is_shut_down() {
+            "was shut down"
+        } else {
+            "shuts down"
+        };
+        write!(
+            f,
+            "{verb} on {}, use {} instead",
+            self.shutdown, self.replacement
+        )
+    }
+}
+
+/// The days since the Unix epoch of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// What a model takes and produces besides text, which every chat model handles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modalities {
+    /// Whether messages may contain [images](crate::content::ContentPart::ImageUrl).
+    pub image_input: bool,
+    /// Whether messages may contain [audio](crate::content::ContentPart::InputAudio).
+    pub audio_input: bool,
+    /// Whether messages may contain [documents](crate::content::ContentPart::File).
+    pub file_input: bool,
+    /// Whether the model speaks its answers, see [`Modality::Audio`](crate::audio::Modality).
+    pub audio_output: bool,
+}
+
+/// `ModelPricing` holds the list prices of a model in US dollars per one million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub cached_input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPricing {
+    /// Computes the total cost in US dollars of the given token usage.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        self.cost_breakdown(usage).total
+    }
+
+    /// Computes the cost in US dollars of the given token usage, split by billing dimension.
+    ///
+    /// Cached prompt tokens are billed at the cached input price and reasoning tokens, which
+    /// are part of the completion tokens, at the output price. Audio tokens are billed at the
+    /// text rates.
+    pub fn cost_breakdown(&self, usage: &Usage) -> CostBreakdown {
+        let per_token = |tokens: i64, per_million: f64| tokens.max(0) as f64 * per_million / 1e6;
+        let cached_tokens = usage.cached_tokens().clamp(0, usage.prompt_tokens.max(0));
+
+        let input = per_token(usage.prompt_tokens - cached_tokens, self.input_per_million);
+        let cached_input = per_token(cached_tokens, self.cached_input_per_million);
+        let output = per_token(usage.completion_tokens, self.output_per_million);
+        let reasoning = per_token(usage.reasoning_tokens(), self.output_per_million);
+        CostBreakdown {
+            input,
+            cached_input,
+            output,
+            reasoning,
+            total: input + cached_input + output,
         }
     }
 }
@@ -50,30 +336,159 @@ impl Display for Model {
             Model::Gpt_4 => "gpt-4",
             Model::Gpt_4_32k => "gpt-4-32k",
             Model::Gpt_4o => "gpt-4o",
+            Model::Gpt_4oMini => "gpt-4o-mini",
             Model::Gpt_4Turbo => "gpt-4-1106-preview",
             Model::Gpt_4Turbo_Vision => "gpt-4-vision-preview",
+            Model::Other(name) => name,
         };
         write!(f, "{model_name}")
     }
 }
 
+impl Model {
+    /// Returns the pinned snapshot a rolling alias such as `gpt-4o`, `gpt-4-turbo` or
+    /// `o3-mini` currently points to, so that requests keep getting the same model when OpenAI
+    /// moves the alias. Other names are returned as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chat_gpt_lib_rs::Model;
+    ///
+    /// assert_eq!(Model::Gpt_4o.resolve_alias(), "gpt-4o-2024-08-06");
+    /// assert_eq!(Model::Other("o3-mini".to_string()).resolve_alias(), "o3-mini-2025-01-31");
+    /// assert_eq!(Model::Other("llama3.2".to_string()).resolve_alias(), "llama3.2");
+    /// ```
+    pub fn resolve_alias(&self) -> String {
+        let name = self.to_string();
+        let snapshot = match name.as_str() {
+            "gpt-3.5-turbo" => "gpt-3.5-turbo-0125",
+            "gpt-4" => "gpt-4-0613",
+            "gpt-4-32k" => "gpt-4-32k-0613",
+            "gpt-4-turbo" => "gpt-4-turbo-2024-04-09",
+            "gpt-4-turbo-preview" => "gpt-4-0125-preview",
+            "gpt-4-vision-preview" => "gpt-4-1106-vision-preview",
+            "gpt-4o" => "gpt-4o-2024-08-06",
+            "gpt-4o-latest" => "gpt-4o-2024-11-20",
+            "gpt-4o-mini" => "gpt-4o-mini-2024-07-18",
+            "o1" => "o1-2024-12-17",
+            "o1-mini" => "o1-mini-2024-09-12",
+            "o3-mini" => "o3-mini-2025-01-31",
+            _ => return name,
+        };
+        snapshot.to_string()
+    }
+
+    /// The known model named exactly `name`.
+    pub(crate) fn named(name: &str) -> Option<Self> {
+        match name {
+            "gpt-3.5-turbo" => Some(Model::Gpt3_5Turbo),
+            "gpt-4" => Some(Model::Gpt_4),
+            "gpt-4-32k" => Some(Model::Gpt_4_32k),
+            "gpt-4o" => Some(Model::Gpt_4o),
+            "gpt-4o-mini" => Some(Model::Gpt_4oMini),
+            "gpt-4-1106-preview" => Some(Model::Gpt_4Turbo),
+            "gpt-4-vision-preview" => Some(Model::Gpt_4Turbo_Vision),
+            _ => None,
+        }
+    }
+
+    /// The known model `name` names or is a snapshot of, e.g. [`Model::Gpt_4o`] for
+    /// `gpt-4o-2024-08-06`.
+    pub(crate) fn family_of(name: &str) -> Option<Self> {
+        Model::named(name).or_else(|| Model::named(without_snapshot(name)))
+    }
+}
+
+/// Strips the snapshot suffix, `-2024-08-06` or `-0613`, off a model name.
+fn without_snapshot(name: &str) -> &str {
+    for shape in ["-dddd-dd-dd", "-dddd"] {
+        let Some(family) = name
+            .len()
+            .checked_sub(shape.len())
+            .and_then(|start| name.get(..start))
+        else {
+            continue;
+        };
+        let matches = name[family.len()..]
+            .bytes()
+            .zip(shape.bytes())
+            .all(|(c, expected)| match expected {
+                b'd' => c.is_ascii_digit(),
+                _ => c == expected,
+            });
+        if matches {
+            return family;
+        }
+    }
+    name
+}
+
 /// Implement `FromStr` to enable parsing the enum from a string representation.
+///
+/// Snapshot names of known models parse as [`Model::Other`], e.g. `gpt-4o-2024-08-06`, so
+/// that the pinned snapshot is sent rather than the alias of its family. Their pricing is still
+/// that of the family, see [`Model::known_pricing`].
 impl FromStr for Model {
     type Err = ModelError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "gpt-3.5-turbo" => Ok(Model::Gpt3_5Turbo),
-            "gpt-4" => Ok(Model::Gpt_4),
-            "gpt-4-32k" => Ok(Model::Gpt_4_32k),
-            "gpt-4o" => Ok(Model::Gpt_4o),
-            "gpt-4-1106-preview" => Ok(Model::Gpt_4Turbo),
-            "gpt-4-vision-preview" => Ok(Model::Gpt_4Turbo_Vision),
-            _ => Err(ModelError::UnsupportedModel(s.into())),
+        match Model::named(s) {
+            Some(model) => Ok(model),
+            None if Model::family_of(s).is_some() => Ok(Model::Other(s.to_string())),
+            None => Err(ModelError::UnsupportedModel(s.into())),
         }
     }
 }
 
+impl Serialize for Model {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Names that match none of the known models deserialize to [`Model::Other`], including
+/// snapshot names, so that they serialize back unchanged.
+impl<'de> Deserialize<'de> for Model {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Model::named(&name).unwrap_or(Model::Other(name)))
+    }
+}
+
+/// `EmbeddingModel` enum represents the available OpenAI embedding models.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum EmbeddingModel {
+    #[serde(rename = "text-embedding-3-small")]
+    TextEmbedding3Small,
+    #[serde(rename = "text-embedding-3-large")]
+    TextEmbedding3Large,
+    #[serde(rename = "text-embedding-ada-002")]
+    TextEmbeddingAda002,
+}
+
+impl EmbeddingModel {
+    /// Returns the list price of the model in US dollars per one million input tokens.
+    pub fn price_per_million(&self) -> f64 {
+        match self {
+            EmbeddingModel::TextEmbedding3Small => 0.02,
+            EmbeddingModel::TextEmbedding3Large => 0.13,
+            EmbeddingModel::TextEmbeddingAda002 => 0.10,
+        }
+    }
+}
+
+impl Display for EmbeddingModel {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let model_name = match self {
+            EmbeddingModel::TextEmbedding3Small => "text-embedding-3-small",
+            EmbeddingModel::TextEmbedding3Large => "text-embedding-3-large",
+            EmbeddingModel::TextEmbeddingAda002 => "text-embedding-ada-002",
+        };
+        write!(f, "{model_name}")
+    }
+}
+
 /// A model parsing issues.
 #[derive(Error, Debug)]
 pub enum ModelError {
@@ -84,26 +499,170 @@ pub enum ModelError {
 
 /// `LogitBias` struct represents the logit bias used in API calls.
 ///
-/// The struct contains a HashMap where keys are token IDs and values are biases.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// The struct contains a HashMap where keys are token IDs and values are biases, from `-100`
+/// (the token is never chosen) to `100` (only the token is chosen). It is sent as that map,
+/// `{"42": 2.5}`.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::LogitBias;
+///
+/// // Any `Fn(&str) -> Vec<u32>` encodes, e.g. the `encode_ordinary` of a BPE tokenizer.
+/// let encode = |text: &str| match text {
+///     "Paris" => vec![59604],
+///     " Paris" => vec![12366],
+///     _ => vec![],
+/// };
+/// let bias = LogitBias::from_words(&encode, [("Paris", -100.0)]).unwrap();
+/// assert_eq!(bias.biases[&12366], -100.0);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct LogitBias {
     pub biases: HashMap<u32, f64>,
 }
 
+/// A bias outside the range of `-100` to `100` the API accepts.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Bias {bias} of token {token} is outside the range of -100 to 100")]
+pub struct BiasOutOfRange {
+    pub token: u32,
+    pub bias: f64,
+}
+
+impl LogitBias {
+    /// The lowest and highest bias the API accepts.
+    pub const RANGE: std::ops::RangeInclusive<f64> = -100.0..=100.0;
+
+    /// A logit bias without biases.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The logit bias with the given biases of token IDs.
+    pub fn from_biases(
+        biases: impl IntoIterator<Item = (u32, f64)>,
+    ) -> Result<Self, BiasOutOfRange> {
+        let mut logit_bias = Self::new();
+        for (token, bias) in biases {
+            logit_bias.insert(token, bias)?;
+        }
+        Ok(logit_bias)
+    }
+
+    /// The logit bias giving every token of the given words their bias, as encoded by
+    /// `encoder` with the tokenizer of the model the bias is sent to.
+    ///
+    /// Words are biased both on their own and with a leading space, as they are tokenized
+    /// within a sentence.
+    pub fn from_words<S: AsRef<str>>(
+        encoder: &impl TokenEncoder,
+        words: impl IntoIterator<Item = (S, f64)>,
+    ) -> Result<Self, BiasOutOfRange> {
+        let mut logit_bias = Self::new();
+        for (word, bias) in words {
+            let word = word.as_ref();
+            for text in [word.to_string(), format!(" {word}")] {
+                for token in encoder.encode(&text) {
+                    logit_bias.insert(token, bias)?;
+                }
+            }
+        }
+        Ok(logit_bias)
+    }
+
+    /// Sets the bias of `token`, unless it is out of range.
+    pub fn insert(&mut self, token: u32, bias: f64) -> Result<(), BiasOutOfRange> {
+        if !Self::RANGE.contains(&bias) {
+            return Err(BiasOutOfRange { token, bias });
+        }
+        self.biases.insert(token, bias);
+        Ok(())
+    }
+
+    /// Checks that all biases are in range, e.g. after setting them on `biases` directly.
+    pub fn validate(&self) -> Result<(), BiasOutOfRange> {
+        match self
+            .biases
+            .iter()
+            .find(|(_, bias)| !Self::RANGE.contains(*bias))
+        {
+            Some((&token, &bias)) => Err(BiasOutOfRange { token, bias }),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Represents the role of a message in the Chat API call.
 ///
-/// The `Role` enum has three variants:
+/// The `Role` enum has five variants:
 /// - `System`: Represents a system message, usually to provide instructions to the assistant.
+/// - `Developer`: Represents instructions to the assistant, replacing `System` for o-series
+///   reasoning models.
 /// - `User`: Represents a user message, which is the input or question the user provides.
 /// - `Assistant`: Represents an assistant message, which is the response generated by the Chat API.
+/// - `Tool`: Represents the result of a tool call requested by the assistant.
+/// - `Function`: Represents the result of a legacy function call, with the `legacy-functions`
+///   feature.
+/// - `Other`: A role this crate does not know yet, kept by name.
 ///
 /// The role is used to differentiate between different types of messages in the chat conversation.
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub enum Role {
     System,
+    Developer,
+    #[default]
     User,
     Assistant,
+    Tool,
+    #[cfg(feature = "legacy-functions")]
+    Function,
+    Other(String),
+}
+
+impl Role {
+    /// Whether messages of this role instruct the assistant, i.e. are `System` or `Developer`.
+    pub fn is_instructions(&self) -> bool {
+        matches!(self, Role::System | Role::Developer)
+    }
+
+    /// The name of the role in the API, e.g. `assistant`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::System => "system",
+            Role::Developer => "developer",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+            #[cfg(feature = "legacy-functions")]
+            Role::Function => "function",
+            Role::Other(name) => name,
+        }
+    }
+}
+
+impl Serialize for Role {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Names that match none of the known roles deserialize to [`Role::Other`].
+impl<'de> Deserialize<'de> for Role {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(match name.as_str() {
+            "system" => Role::System,
+            "developer" => Role::Developer,
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            "tool" => Role::Tool,
+            #[cfg(feature = "legacy-functions")]
+            "function" => Role::Function,
+            _ => Role::Other(name),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -111,11 +670,62 @@ mod tests {
     use super::*;
     use serde_json;
 
+    #[test]
+    fn test_is_reasoning() {
+        assert!(Model::Other("o1".to_string()).is_reasoning());
+        assert!(Model::Other("o4-mini".to_string()).is_reasoning());
+        assert!(!Model::Gpt_4o.is_reasoning());
+        assert!(!Model::Other("ollama".to_string()).is_reasoning());
+    }
+
+    #[test]
+    fn test_knowledge_cutoff_and_modalities() {
+        let cutoff = Model::Gpt_4o.knowledge_cutoff().unwrap();
+        assert_eq!(cutoff.to_string(), "October 2023");
+        assert!(Model::Gpt_4.knowledge_cutoff().unwrap() < cutoff);
+        assert_eq!(
+            Model::Other("llama3.2".to_string()).knowledge_cutoff(),
+            None
+        );
+
+        assert!(Model::Gpt_4o.modalities().file_input);
+        assert!(Model::Gpt_4Turbo_Vision.modalities().image_input);
+        assert_eq!(Model::Gpt_4.modalities(), Modalities::default());
+    }
+
+    #[test]
+    fn test_deprecation() {
+        let deprecation = Model::Gpt_4Turbo_Vision.deprecation().unwrap();
+        assert_eq!(deprecation.replacement, "gpt-4o");
+        assert_eq!(
+            deprecation.shutdown_at(),
+            UNIX_EPOCH + Duration::from_secs(1_733_443_200)
+        );
+        assert!(deprecation.is_shut_down());
+        assert_eq!(
+            deprecation.to_string(),
+            "was shut down on 2024-12-06, use gpt-4o instead"
+        );
+        assert!(Model::Other("gpt-4-32k-0613".to_string()).is_deprecated());
+        assert!(!Model::Gpt_4o.is_deprecated());
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        assert_eq!(Model::Gpt_4o.max_output_tokens(), 16384);
+        assert_eq!(Model::Gpt_4.max_output_tokens(), 8192);
+        assert_eq!(Model::Gpt3_5Turbo.max_output_tokens(), 4096);
+        assert_eq!(
+            Model::Other("llama3.2".to_string()).max_output_tokens(),
+            4096
+        );
+    }
+
     // Test the conversion of a valid model string to a `Model` enum variant for Gpt3_5Turbo.
     #[test]
     fn test_from_str_gpt3_5turbo() {
         let input = "gpt-3.5-turbo";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(
             model.is_ok(),
             "Failed to parse the gpt-3.5-turbo model name"
@@ -127,16 +737,63 @@ mod tests {
     #[test]
     fn test_from_str_gpt4() {
         let input = "gpt-4";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(model.is_ok(), "Failed to parse the gpt-4 model name");
         assert_eq!(model.unwrap(), Model::Gpt_4);
     }
 
+    #[test]
+    fn test_other_model_serde() {
+        let model: Model = serde_json::from_str("\"llama3.2\"").unwrap();
+        assert_eq!(model, Model::Other("llama3.2".to_string()));
+        assert_eq!(serde_json::to_string(&model).unwrap(), "\"llama3.2\"");
+        assert_eq!(model.to_string(), "llama3.2");
+        assert_eq!(model.pricing().cost(&Usage::default()), 0.0);
+
+        let known: Model = serde_json::from_str("\"gpt-4o\"").unwrap();
+        assert_eq!(known, Model::Gpt_4o);
+    }
+
+    #[test]
+    fn test_from_str_snapshot() {
+        for (name, family) in [
+            ("gpt-4o-2024-08-06", Model::Gpt_4o),
+            ("gpt-4o-mini-2024-07-18", Model::Gpt_4oMini),
+            ("gpt-4-32k-0613", Model::Gpt_4_32k),
+            ("gpt-3.5-turbo-0125", Model::Gpt3_5Turbo),
+        ] {
+            let model = Model::from_str(name).unwrap();
+            assert_eq!(model, Model::Other(name.to_string()));
+            assert_eq!(model.to_string(), name);
+            assert_eq!(model.known_pricing(), Some(family.pricing()));
+            assert_eq!(Model::family_of(name), Some(family));
+        }
+        assert!(Model::from_str("gpt-4o-audio-preview").is_err());
+        assert!(Model::from_str("o3-mini-2025-01-31").is_err());
+
+        let model: Model = serde_json::from_str("\"gpt-4o-2024-08-06\"").unwrap();
+        assert_eq!(model, Model::Other("gpt-4o-2024-08-06".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_alias() {
+        assert_eq!(Model::Gpt_4oMini.resolve_alias(), "gpt-4o-mini-2024-07-18");
+        assert_eq!(Model::Gpt_4Turbo.resolve_alias(), "gpt-4-1106-preview");
+        assert_eq!(
+            Model::Other("gpt-4-turbo".to_string()).resolve_alias(),
+            "gpt-4-turbo-2024-04-09"
+        );
+        assert_eq!(
+            Model::Other("gpt-4o-latest".to_string()).resolve_alias(),
+            "gpt-4o-2024-11-20"
+        );
+    }
+
     // Test the conversion of an invalid model string to a `Model` enum variant.
     #[test]
     fn test_from_str_invalid() {
         let input = "invalid-model";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(model.is_err(), "Parsed an invalid model name");
     }
 
@@ -184,7 +841,7 @@ mod tests {
     #[test]
     fn test_from_str_gpt4_32k() {
         let input = "gpt-4-32k";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(model.is_ok(), "Failed to parse the gpt-4-32k model name");
         assert_eq!(model.unwrap(), Model::Gpt_4_32k);
     }
@@ -243,24 +900,59 @@ mod tests {
             None,
             "Bias for token 999 should not be set"
         );
+        assert_eq!(logit_bias.validate(), Ok(()));
     }
 
     #[test]
-    fn test_max_tokens_gpt3_5turbo() {
+    fn test_logit_bias_validation_and_serialization() {
+        let logit_bias = LogitBias::from_biases([(42, 100.0), (7, -100.0)]).unwrap();
+        assert_eq!(
+            serde_json::to_value(&logit_bias).unwrap(),
+            serde_json::json!({"42": 100.0, "7": -100.0})
+        );
+        let parsed: LogitBias = serde_json::from_str(r#"{"42": 100.0, "7": -100.0}"#).unwrap();
+        assert_eq!(parsed, logit_bias);
+
+        let err = LogitBias::from_biases([(42, 100.5)]).unwrap_err();
+        assert_eq!(
+            err,
+            BiasOutOfRange {
+                token: 42,
+                bias: 100.5
+            }
+        );
+        let mut logit_bias = LogitBias::new();
+        assert!(logit_bias.insert(1, f64::NAN).is_err());
+        logit_bias.biases.insert(2, -101.0);
+        assert!(logit_bias.validate().is_err());
+    }
+
+    #[test]
+    fn test_logit_bias_from_words() {
+        let encode = |text: &str| -> Vec<u32> { text.bytes().map(u32::from).collect() };
+        let logit_bias = LogitBias::from_words(&encode, [("hi", 5.0)]).unwrap();
+        let mut tokens: Vec<_> = logit_bias.biases.keys().copied().collect();
+        tokens.sort();
+        assert_eq!(tokens, [u32::from(b' '), u32::from(b'h'), u32::from(b'i')]);
+        assert!(LogitBias::from_words(&encode, [("hi", -200.0)]).is_err());
+    }
+
+    #[test]
+    fn test_context_window_gpt3_5turbo() {
         let model = Model::Gpt3_5Turbo;
-        assert_eq!(model.max_tokens(), 4096);
+        assert_eq!(model.context_window(), 16385);
     }
 
     #[test]
-    fn test_max_tokens_gpt_4() {
+    fn test_context_window_gpt_4() {
         let model = Model::Gpt_4;
-        assert_eq!(model.max_tokens(), 8192);
+        assert_eq!(model.context_window(), 8192);
     }
 
     #[test]
-    fn test_max_tokens_gpt_4_32k() {
+    fn test_context_window_gpt_4_32k() {
         let model = Model::Gpt_4_32k;
-        assert_eq!(model.max_tokens(), 32768);
+        assert_eq!(model.context_window(), 32768);
     }
 
     // Test the conversion of a Model enum variant to its string representation for Gpt_4Turbo.
@@ -275,7 +967,7 @@ mod tests {
     #[test]
     fn test_from_str_gpt_4turbo() {
         let input = "gpt-4-1106-preview";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(
             model.is_ok(),
             "Failed to parse the gpt-4-1106-preview model name"
@@ -311,7 +1003,7 @@ mod tests {
     #[test]
     fn test_from_str_gpt_4turbo_vision() {
         let input = "gpt-4-vision-preview";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(
             model.is_ok(),
             "Failed to parse the gpt-4-vision-preview model name"
@@ -337,16 +1029,16 @@ mod tests {
 
     // Test the max tokens for Gpt_4Turbo.
     #[test]
-    fn test_max_tokens_gpt_4turbo() {
+    fn test_context_window_gpt_4turbo() {
         let model = Model::Gpt_4Turbo;
-        assert_eq!(model.max_tokens(), 128000);
+        assert_eq!(model.context_window(), 128000);
     }
 
     // Test the max tokens for Gpt_4Turbo_Vision.
     #[test]
-    fn test_max_tokens_gpt_4turbo_vision() {
+    fn test_context_window_gpt_4turbo_vision() {
         let model = Model::Gpt_4Turbo_Vision;
-        assert_eq!(model.max_tokens(), 128000);
+        assert_eq!(model.context_window(), 128000);
     }
 
     // Test the conversion of a Model enum variant to its string representation for Gpt_4o.
@@ -361,7 +1053,7 @@ mod tests {
     #[test]
     fn test_from_str_gpt_4o() {
         let input = "gpt-4o";
-        let model: Result<Model, ()> = Model::from_str(input);
+        let model: Result<Model, ModelError> = Model::from_str(input);
         assert!(model.is_ok(), "Failed to parse the gpt-4o model name");
         assert_eq!(model.unwrap(), Model::Gpt_4o);
     }
@@ -384,8 +1076,62 @@ mod tests {
 
     // Test the max tokens for Gpt_4o.
     #[test]
-    fn test_max_tokens_gpt_4o() {
+    fn test_context_window_gpt_4o() {
         let model = Model::Gpt_4o;
-        assert_eq!(model.max_tokens(), 128000);
+        assert_eq!(model.context_window(), 128000);
+    }
+
+    // Test the conversion of a valid model string to a Model enum variant for Gpt_4oMini.
+    #[test]
+    fn test_from_str_gpt_4o_mini() {
+        let model: Result<Model, ModelError> = Model::from_str("gpt-4o-mini");
+        assert_eq!(model.unwrap(), Model::Gpt_4oMini);
+        assert_eq!(Model::Gpt_4oMini.to_string(), "gpt-4o-mini");
+    }
+
+    // Test the serialization and deserialization of Gpt_4oMini.
+    #[test]
+    fn test_serde_gpt_4o_mini() {
+        let serialized_model = serde_json::to_string(&Model::Gpt_4oMini).unwrap();
+        assert_eq!(serialized_model, "\"gpt-4o-mini\"");
+        let deserialized_model: Model = serde_json::from_str(&serialized_model).unwrap();
+        assert_eq!(deserialized_model, Model::Gpt_4oMini);
+    }
+
+    // Test the cost computation for Gpt_4o.
+    #[test]
+    fn test_pricing_cost_gpt_4o() {
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+            total_tokens: 1_500_000,
+            ..Default::default()
+        };
+        assert_eq!(Model::Gpt_4o.pricing().cost(&usage), 7.5);
+    }
+
+    // Test the cost breakdown with cached and reasoning tokens for Gpt_4o.
+    #[test]
+    fn test_pricing_cost_breakdown_gpt_4o() {
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 100_000,
+            total_tokens: 1_100_000,
+            prompt_tokens_details: Some(crate::client::PromptTokensDetails {
+                cached_tokens: Some(400_000),
+                ..Default::default()
+            }),
+            completion_tokens_details: Some(crate::client::CompletionTokensDetails {
+                reasoning_tokens: Some(50_000),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let breakdown = Model::Gpt_4o.pricing().cost_breakdown(&usage);
+        assert_eq!(breakdown.input, 1.5);
+        assert_eq!(breakdown.cached_input, 0.5);
+        assert_eq!(breakdown.output, 1.0);
+        assert_eq!(breakdown.reasoning, 0.5);
+        assert_eq!(breakdown.total, 3.0);
     }
 }
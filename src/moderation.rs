@@ -0,0 +1,302 @@
+//! Types of the moderations API.
+//!
+//! [`ChatGPTClient::moderations`](crate::ChatGPTClient::moderations) classifies text and, with
+//! the `omni-moderation` models, images by the content policy categories. The flags and scores
+//! of each result are typed as [`ModerationCategories`], so policies can set their own
+//! threshold per category:
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::moderation::{ModerationCategories, ModerationInput};
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let response = client.moderations(ModerationInput::text("Some user input")).await?;
+//! let thresholds = ModerationCategories {
+//!     violence: 0.2,
+//!     ..ModerationCategories::splat(0.5)
+//! };
+//! if response.results[0].is_flagged_above(&thresholds) {
+//!     println!("rejected: {:?}", response.results[0].categories_above(&thresholds));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::content::Content;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The moderation model used unless set otherwise, which also classifies images.
+pub const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+
+/// Represents the input for the moderations API call.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct ModerationInput {
+    /// The moderation model, e.g. `omni-moderation-latest` or `text-moderation-latest`.
+    pub model: String,
+    /// The text, or text and image parts, to classify.
+    pub input: Content,
+    /// Additional request fields, sent alongside the standard ones, like
+    /// [`ChatInput::extra`](crate::ChatInput::extra).
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ModerationInput {
+    /// Classifies `text` with the [`DEFAULT_MODERATION_MODEL`].
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            model: DEFAULT_MODERATION_MODEL.to_string(),
+            input: Content::Text(text.into()),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Represents the response from the moderations API call.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct ModerationResponse {
+    pub id: String,
+    pub model: String,
+    pub results: Vec<ModerationResult>,
+    /// Fields of the response this crate does not model.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// The classification of one input.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct ModerationResult {
+    /// Whether the input violates any category by OpenAI's own thresholds.
+    pub flagged: bool,
+    /// Whether the input violates each category by OpenAI's own thresholds.
+    pub categories: ModerationCategories<bool>,
+    /// The confidence of the model that the input violates each category, in `[0, 1]`.
+    pub category_scores: ModerationCategories<f64>,
+    /// Which kinds of input each category was scored on; only sent by the `omni-moderation`
+    /// models.
+    #[serde(default)]
+    pub category_applied_input_types: Option<ModerationCategories<Vec<ModerationInputType>>>,
+    /// Fields of the result this crate does not model.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl ModerationResult {
+    /// Whether the score of any category exceeds its threshold in `thresholds`.
+    pub fn is_flagged_above(&self, thresholds: &ModerationCategories<f64>) -> bool {
+        !self.categories_above(thresholds).is_empty()
+    }
+
+    /// The categories whose scores exceed their thresholds in `thresholds`.
+    pub fn categories_above(
+        &self,
+        thresholds: &ModerationCategories<f64>,
+    ) -> Vec<ModerationCategory> {
+        self.category_scores
+            .iter()
+            .zip(thresholds.iter())
+            .filter(|((_, score), (_, threshold))| score > threshold)
+            .map(|((category, _), _)| category)
+            .collect()
+    }
+}
+
+/// A kind of moderated input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationInputType {
+    Text,
+    Image,
+    /// A kind of input this crate does not know yet.
+    #[serde(other)]
+    Other,
+}
+
+/// A content policy category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModerationCategory {
+    Harassment,
+    HarassmentThreatening,
+    Hate,
+    HateThreatening,
+    Illicit,
+    IllicitViolent,
+    SelfHarm,
+    SelfHarmIntent,
+    SelfHarmInstructions,
+    Sexual,
+    SexualMinors,
+    Violence,
+    ViolenceGraphic,
+}
+
+impl ModerationCategory {
+    /// The name of the category in the API, e.g. `self-harm/intent`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Harassment => "harassment",
+            Self::HarassmentThreatening => "harassment/threatening",
+            Self::Hate => "hate",
+            Self::HateThreatening => "hate/threatening",
+            Self::Illicit => "illicit",
+            Self::IllicitViolent => "illicit/violent",
+            Self::SelfHarm => "self-harm",
+            Self::SelfHarmIntent => "self-harm/intent",
+            Self::SelfHarmInstructions => "self-harm/instructions",
+            Self::Sexual => "sexual",
+            Self::SexualMinors => "sexual/minors",
+            Self::Violence => "violence",
+            Self::ViolenceGraphic => "violence/graphic",
+        }
+    }
+}
+
+/// A value per content policy category, e.g. a flag, a score or a threshold.
+///
+/// Categories a model does not classify, such as `illicit` for the `text-moderation` models,
+/// are read as the default value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationCategories<T> {
+    pub harassment: T,
+    #[serde(rename = "harassment/threatening")]
+    pub harassment_threatening: T,
+    pub hate: T,
+    #[serde(rename = "hate/threatening")]
+    pub hate_threatening: T,
+    pub illicit: T,
+    #[serde(rename = "illicit/violent")]
+    pub illicit_violent: T,
+    #[serde(rename = "self-harm")]
+    pub self_harm: T,
+    #[serde(rename = "self-harm/intent")]
+    pub self_harm_intent: T,
+    #[serde(rename = "self-harm/instructions")]
+    pub self_harm_instructions: T,
+    pub sexual: T,
+    #[serde(rename = "sexual/minors")]
+    pub sexual_minors: T,
+    pub violence: T,
+    #[serde(rename = "violence/graphic")]
+    pub violence_graphic: T,
+}
+
+impl<T: Clone> ModerationCategories<T> {
+    /// The same `value` for every category, e.g. a uniform threshold.
+    pub fn splat(value: T) -> Self {
+        Self {
+            harassment: value.clone(),
+            harassment_threatening: value.clone(),
+            hate: value.clone(),
+            hate_threatening: value.clone(),
+            illicit: value.clone(),
+            illicit_violent: value.clone(),
+            self_harm: value.clone(),
+            self_harm_intent: value.clone(),
+            self_harm_instructions: value.clone(),
+            sexual: value.clone(),
+            sexual_minors: value.clone(),
+            violence: value.clone(),
+            violence_graphic: value,
+        }
+    }
+}
+
+impl<T> ModerationCategories<T> {
+    /// Every category with its value.
+    pub fn iter(&self) -> impl Iterator<Item = (ModerationCategory, &T)> {
+        [
+            (ModerationCategory::Harassment, &self.harassment),
+            (
+                ModerationCategory::HarassmentThreatening,
+                &self.harassment_threatening,
+            ),
+            (ModerationCategory::Hate, &self.hate),
+            (ModerationCategory::HateThreatening, &self.hate_threatening),
+            (ModerationCategory::Illicit, &self.illicit),
+            (ModerationCategory::IllicitViolent, &self.illicit_violent),
+            (ModerationCategory::SelfHarm, &self.self_harm),
+            (ModerationCategory::SelfHarmIntent, &self.self_harm_intent),
+            (
+                ModerationCategory::SelfHarmInstructions,
+                &self.self_harm_instructions,
+            ),
+            (ModerationCategory::Sexual, &self.sexual),
+            (ModerationCategory::SexualMinors, &self.sexual_minors),
+            (ModerationCategory::Violence, &self.violence),
+            (ModerationCategory::ViolenceGraphic, &self.violence_graphic),
+        ]
+        .into_iter()
+    }
+
+    /// The value of `category`.
+    pub fn get(&self, category: ModerationCategory) -> &T {
+        self.iter()
+            .find(|(candidate, _)| *candidate == category)
+            .map(|(_, value)| value)
+            .expect("every category has a value")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_moderation_result() {
+        let response: ModerationResponse = serde_json::from_value(json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"violence": true, "self-harm/intent": false},
+                "category_scores": {"violence": 0.86, "self-harm/intent": 0.3, "hate": 0.01},
+                "category_applied_input_types": {"violence": ["text", "image"], "hate": ["text"]},
+            }],
+        }))
+        .unwrap();
+        let result = &response.results[0];
+        assert!(result.categories.violence);
+        assert_eq!(
+            result
+                .category_scores
+                .get(ModerationCategory::SelfHarmIntent),
+            &0.3
+        );
+        assert_eq!(
+            result
+                .category_applied_input_types
+                .as_ref()
+                .unwrap()
+                .violence,
+            [ModerationInputType::Text, ModerationInputType::Image]
+        );
+
+        assert!(!result.is_flagged_above(&ModerationCategories::splat(0.9)));
+        let thresholds = ModerationCategories {
+            self_harm_intent: 0.2,
+            ..ModerationCategories::splat(0.5)
+        };
+        assert_eq!(
+            result.categories_above(&thresholds),
+            [
+                ModerationCategory::SelfHarmIntent,
+                ModerationCategory::Violence
+            ]
+        );
+    }
+
+    #[test]
+    fn test_moderation_input_serialization() {
+        assert_eq!(
+            serde_json::to_value(ModerationInput::text("hello")).unwrap(),
+            json!({"model": "omni-moderation-latest", "input": "hello"})
+        );
+    }
+}
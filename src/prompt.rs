@@ -0,0 +1,509 @@
+//! Helpers for building prompts.
+//!
+//! A [`PromptTemplate`] is a prompt text with named `{placeholders}`, which can live in a file
+//! or a constant and is filled in at runtime. Rendering fails instead of sending a broken prompt
+//! when a variable is missing or unknown:
+//!
+//! ```
+//! use chat_gpt_lib_rs::prompt::PromptTemplate;
+//! use chat_gpt_lib_rs::Role;
+//!
+//! let template = PromptTemplate::new("Translate {text} into {language}.").unwrap();
+//! let message = template
+//!     .render_message(Role::User, [("text", "Guten Tag"), ("language", "English")])
+//!     .unwrap();
+//! assert_eq!(message.content, "Translate Guten Tag into English.");
+//! assert!(template.render([("text", "Guten Tag")]).is_err());
+//! ```
+//!
+//! A [`SystemPrompt`] composes a system message from sections: a persona, rules, the output
+//! format and context documents. Documents that would exceed the token limit of the context
+//! are left out:
+//!
+//! ```
+//! use chat_gpt_lib_rs::prompt::SystemPrompt;
+//!
+//! let message = SystemPrompt::new()
+//!     .persona("You are the support assistant of Example Inc.")
+//!     .rule("Only answer questions about our products.")
+//!     .output_format("Plain text, at most three sentences.")
+//!     .document("Our stores open at 9am.")
+//!     .max_context_tokens(1000)
+//!     .message();
+//! assert!(message.content.text().starts_with("You are the support assistant"));
+//! ```
+//!
+//! [`FewShot`] turns example input and output pairs into the alternating `user` and
+//! `assistant` messages that show the model what is expected, placed after the system prompt.
+//!
+//! ```
+//! use chat_gpt_lib_rs::prompt::FewShot;
+//! use chat_gpt_lib_rs::Message;
+//!
+//! let mut messages = vec![
+//!     Message::system("Classify the sentiment of the review."),
+//!     Message::user("The battery died after a week."),
+//! ];
+//! FewShot::new()
+//!     .example("Works great, would buy again.", "positive")
+//!     .example("Arrived broken.", "negative")
+//!     .insert_into(&mut messages);
+//! assert_eq!(messages.len(), 6);
+//! assert_eq!(messages[1].content, "Works great, would buy again.");
+//! ```
+
+use crate::client::Message;
+use crate::models::Role;
+use crate::tokenizer::count_tokens;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// An error parsing or rendering a [`PromptTemplate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A `{` or `}` that is not part of a placeholder or an escaped `{{` or `}}`.
+    #[error("Unmatched brace at byte {0} of the template")]
+    UnmatchedBrace(usize),
+    /// A placeholder whose name is empty or contains characters other than letters, digits
+    /// and `_`.
+    #[error("Invalid placeholder name: {0:?}")]
+    InvalidPlaceholder(String),
+    /// A placeholder without a value.
+    #[error("Missing value for placeholder {0:?}")]
+    MissingVariable(String),
+    /// A value for a name that is not a placeholder of the template.
+    #[error("Unknown template variable {0:?}")]
+    UnknownVariable(String),
+}
+
+/// A piece of a [`PromptTemplate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Placeholder(String),
+}
+
+/// A prompt text with named `{placeholders}`.
+///
+/// Literal braces are written as `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PromptTemplate {
+    /// Parses `template`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a TemplateError if a brace is unmatched or a placeholder name is invalid.
+    pub fn new(template: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, next)| next == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|&(_, next)| next == '}').is_some() => text.push('}'),
+                '{' => {
+                    let start = index + 1;
+                    let end = loop {
+                        match chars.next() {
+                            Some((end, '}')) => break end,
+                            Some((_, '{')) | None => {
+                                return Err(TemplateError::UnmatchedBrace(index))
+                            }
+                            Some(_) => {}
+                        }
+                    };
+                    let name = &template[start..end];
+                    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return Err(TemplateError::InvalidPlaceholder(name.to_string()));
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Placeholder(name.to_string()));
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace(index)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+
+    /// The names of the placeholders, in order of first appearance.
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Placeholder(name) = segment {
+                if !names.contains(&name.as_str()) {
+                    names.push(name.as_str());
+                }
+            }
+        }
+        names
+    }
+
+    /// Fills in the placeholders with `variables`, given as name and value pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns a TemplateError if a placeholder has no value or a variable is not a
+    /// placeholder of the template.
+    pub fn render<K, V>(
+        &self,
+        variables: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<String, TemplateError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let variables: BTreeMap<String, V> = variables
+            .into_iter()
+            .map(|(name, value)| (name.as_ref().to_string(), value))
+            .collect();
+        let placeholders = self.placeholders();
+        if let Some(name) = variables
+            .keys()
+            .find(|name| !placeholders.contains(&name.as_str()))
+        {
+            return Err(TemplateError::UnknownVariable(name.clone()));
+        }
+
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Placeholder(name) => match variables.get(name) {
+                    Some(value) => rendered.push_str(value.as_ref()),
+                    None => return Err(TemplateError::MissingVariable(name.clone())),
+                },
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// Renders the template, see [`PromptTemplate::render`], into a message of `role`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a TemplateError if a placeholder has no value or a variable is not a
+    /// placeholder of the template.
+    pub fn render_message<K, V>(
+        &self,
+        role: Role,
+        variables: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Message, TemplateError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        Ok(Message::new(role, self.render(variables)?))
+    }
+}
+
+/// A section of a [`SystemPrompt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Persona,
+    Rules,
+    OutputFormat,
+    Context,
+}
+
+/// The order of the sections unless set with [`SystemPrompt::order`].
+const DEFAULT_ORDER: [Section; 4] = [
+    Section::Persona,
+    Section::Rules,
+    Section::OutputFormat,
+    Section::Context,
+];
+
+/// A system prompt composed of sections.
+///
+/// Sections are separated by blank lines; empty sections are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemPrompt {
+    persona: Option<String>,
+    rules: Vec<String>,
+    output_format: Option<String>,
+    documents: Vec<String>,
+    max_context_tokens: Option<usize>,
+    order: Vec<Section>,
+}
+
+impl Default for SystemPrompt {
+    fn default() -> Self {
+        Self {
+            persona: None,
+            rules: Vec::new(),
+            output_format: None,
+            documents: Vec::new(),
+            max_context_tokens: None,
+            order: DEFAULT_ORDER.to_vec(),
+        }
+    }
+}
+
+impl SystemPrompt {
+    /// Creates a prompt without sections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets who the assistant is, e.g. `You are a helpful tutor.`
+    pub fn persona(mut self, persona: impl Into<String>) -> Self {
+        self.persona = Some(persona.into());
+        self
+    }
+
+    /// Adds a rule, listed under `Rules:`.
+    pub fn rule(mut self, rule: impl Into<String>) -> Self {
+        self.rules.push(rule.into());
+        self
+    }
+
+    /// Sets the format of the answers, described under `Output format:`.
+    pub fn output_format(mut self, format: impl Into<String>) -> Self {
+        self.output_format = Some(format.into());
+        self
+    }
+
+    /// Adds a context document, e.g. a retrieved passage, listed under `Context:`.
+    ///
+    /// Documents are added in order of relevance: when the context is trimmed, the last ones
+    /// are left out first.
+    pub fn document(mut self, document: impl Into<String>) -> Self {
+        self.documents.push(document.into());
+        self
+    }
+
+    /// Limits the context documents to `tokens` tokens, counted with [`count_tokens`].
+    ///
+    /// Documents that do not fit are left out, along with all documents after them.
+    pub fn max_context_tokens(mut self, tokens: usize) -> Self {
+        self.max_context_tokens = Some(tokens);
+        self
+    }
+
+    /// Sets the order of the sections; sections not in `order` are left out.
+    pub fn order(mut self, order: &[Section]) -> Self {
+        self.order = order.to_vec();
+        self
+    }
+
+    /// The context documents that fit into the token limit.
+    fn context_documents(&self) -> &[String] {
+        let Some(limit) = self.max_context_tokens else {
+            return &self.documents;
+        };
+        let mut tokens = 0;
+        let fitting = self
+            .documents
+            .iter()
+            .take_while(|document| {
+                tokens += count_tokens(document);
+                tokens <= limit
+            })
+            .count();
+        &self.documents[..fitting]
+    }
+
+    fn section(&self, section: Section) -> Option<String> {
+        match section {
+            Section::Persona => self.persona.clone(),
+            Section::Rules if !self.rules.is_empty() => {
+                let rules: Vec<String> =
+                    self.rules.iter().map(|rule| format!("- {rule}")).collect();
+                Some(format!("Rules:\n{}", rules.join("\n")))
+            }
+            Section::OutputFormat => self
+                .output_format
+                .as_ref()
+                .map(|format| format!("Output format:\n{format}")),
+            Section::Context if !self.context_documents().is_empty() => Some(format!(
+                "Context:\n{}",
+                self.context_documents().join("\n\n")
+            )),
+            Section::Rules | Section::Context => None,
+        }
+    }
+
+    /// The text of the prompt.
+    pub fn build(&self) -> String {
+        self.order
+            .iter()
+            .filter_map(|&section| self.section(section))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// The prompt as a `system` message.
+    pub fn message(&self) -> Message {
+        Message::system(self.build())
+    }
+}
+
+/// Example input and output pairs, expanded into `user` and `assistant` messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FewShot {
+    examples: Vec<(String, String)>,
+}
+
+impl FewShot {
+    /// Creates an empty set of examples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an example of the `output` the model should give for `input`.
+    pub fn example(mut self, input: impl Into<String>, output: impl Into<String>) -> Self {
+        self.examples.push((input.into(), output.into()));
+        self
+    }
+
+    /// Adds several examples.
+    pub fn examples<I, O>(mut self, examples: impl IntoIterator<Item = (I, O)>) -> Self
+    where
+        I: Into<String>,
+        O: Into<String>,
+    {
+        self.examples.extend(
+            examples
+                .into_iter()
+                .map(|(input, output)| (input.into(), output.into())),
+        );
+        self
+    }
+
+    /// The number of examples.
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    /// Whether there are no examples.
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+
+    /// The examples as alternating `user` and `assistant` messages.
+    pub fn messages(&self) -> Vec<Message> {
+        self.examples
+            .iter()
+            .flat_map(|(input, output)| {
+                [
+                    Message::user(input.as_str()),
+                    Message::assistant(output.as_str()),
+                ]
+            })
+            .collect()
+    }
+
+    /// Inserts the example messages into `messages`, after the leading `system` and
+    /// `developer` messages.
+    pub fn insert_into(&self, messages: &mut Vec<Message>) {
+        let position = messages
+            .iter()
+            .position(|message| !message.role.is_instructions())
+            .unwrap_or(messages.len());
+        messages.splice(position..position, self.messages());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_template() {
+        let template = PromptTemplate::new("{greeting}, {name}! {{literal}} {name}?").unwrap();
+        assert_eq!(template.placeholders(), ["greeting", "name"]);
+        assert_eq!(
+            template
+                .render([("greeting", "Hello"), ("name", "Ada")])
+                .unwrap(),
+            "Hello, Ada! {literal} Ada?"
+        );
+        assert_eq!(
+            template.render([("greeting", "Hello")]),
+            Err(TemplateError::MissingVariable("name".to_string()))
+        );
+        assert_eq!(
+            template.render([("greeting", "Hi"), ("name", "Ada"), ("extra", "x")]),
+            Err(TemplateError::UnknownVariable("extra".to_string()))
+        );
+
+        assert_eq!(
+            PromptTemplate::new("Hello {name"),
+            Err(TemplateError::UnmatchedBrace(6))
+        );
+        assert_eq!(
+            PromptTemplate::new("Hello }"),
+            Err(TemplateError::UnmatchedBrace(6))
+        );
+        assert_eq!(
+            PromptTemplate::new("Hello {first name}"),
+            Err(TemplateError::InvalidPlaceholder("first name".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_system_prompt() {
+        let prompt = SystemPrompt::new()
+            .persona("You are a tutor.")
+            .rule("Be patient.")
+            .rule("Never give away answers.")
+            .document("a".repeat(40))
+            .document("b".repeat(40))
+            .max_context_tokens(15);
+        assert_eq!(
+            prompt.build(),
+            format!(
+                "You are a tutor.\n\nRules:\n- Be patient.\n- Never give away answers.\n\nContext:\n{}",
+                "a".repeat(40)
+            )
+        );
+
+        let prompt = prompt
+            .output_format("JSON")
+            .order(&[Section::OutputFormat, Section::Persona]);
+        assert_eq!(prompt.build(), "Output format:\nJSON\n\nYou are a tutor.");
+        assert_eq!(prompt.message().role, Role::System);
+        assert_eq!(SystemPrompt::new().build(), "");
+    }
+
+    #[test]
+    fn test_few_shot() {
+        let few_shot = FewShot::new()
+            .example("2 + 2", "4")
+            .examples([("3 * 3", "9")]);
+        assert_eq!(few_shot.len(), 2);
+
+        let mut messages = vec![Message::system("Answer with a number.")];
+        few_shot.insert_into(&mut messages);
+        let roles: Vec<Role> = messages
+            .iter()
+            .map(|message| message.role.clone())
+            .collect();
+        assert_eq!(
+            roles,
+            [
+                Role::System,
+                Role::User,
+                Role::Assistant,
+                Role::User,
+                Role::Assistant
+            ]
+        );
+        assert_eq!(messages[4].content, "9");
+
+        let mut messages = vec![Message::user("5 - 1")];
+        few_shot.insert_into(&mut messages);
+        assert_eq!(messages[0].content, "2 + 2");
+        assert_eq!(messages[4].content, "5 - 1");
+    }
+}
@@ -0,0 +1,1067 @@
+//! Adapter for the [Anthropic Messages API](https://docs.anthropic.com/en/api/messages).
+//!
+//! [`AnthropicClient`] maps a [`ChatInput`] onto a Messages request:
+//!
+//! - System messages are joined into the top-level `system` prompt.
+//! - `max_tokens` is required by Anthropic; requests that do not set it use the client's
+//!   default (4096 unless changed with [`AnthropicClient::default_max_tokens`]).
+//! - `stop` is sent as `stop_sequences` and `user` as `metadata.user_id`.
+//! - Images and PDF documents are sent as `image` and `document` blocks.
+//! - The `tools` and `tool_choice` request fields, set with
+//!   [`ChatInputBuilder::extra`](crate::ChatInputBuilder::extra), are mapped onto Anthropic's
+//!   tools. The tool calls of assistant messages become `tool_use` blocks, and tool results
+//!   `tool_result` blocks of a user turn.
+//! - `n`, `presence_penalty`, `frequency_penalty`, `logit_bias` and `seed` have no
+//!   equivalent and are ignored.
+//!
+//! Requests with content Anthropic cannot take, such as audio input, files uploaded to OpenAI
+//! or legacy function calls, fail with `ChatGPTError::Unsupported` instead of losing it.
+//!
+//! Responses are mapped back onto a [`ChatResponse`] with a single choice, `tool_use` blocks
+//! onto [`ToolCall`]s, and Anthropic's stop reasons onto the OpenAI finish reasons (`end_turn`
+//! becomes `stop`, `max_tokens` becomes `length`, `tool_use` becomes `tool_calls`). Streamed
+//! responses are translated from Anthropic's event stream into [`ChatChunk`]s, with the
+//! arguments of tool calls arriving as [`ToolCallDelta`]s.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::providers::anthropic::AnthropicClient;
+//! use chat_gpt_lib_rs::{ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = AnthropicClient::new("your_anthropic_key");
+//! let response = client
+//!     .chat(
+//!         ChatInput::builder(Model::Other("claude-3-5-sonnet-latest".to_string()))
+//!             .message(Message::user("Hello!"))
+//!             .build(),
+//!     )
+//!     .await?;
+//! println!("{}", response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{
+    ChatGPTError, ChatInput, ChatResponse, Choice, Message, PromptTokensDetails, Usage,
+};
+use crate::content::{Content, ContentPart};
+use crate::models::{Model, Role};
+use crate::providers::{Capabilities, ChatProvider, ChatStream, ProviderFuture};
+use crate::stream::{sse_stream, ChatChunk, ChunkChoice, Delta};
+use crate::tools::{ToolCall, ToolCallDelta, ToolFunction, ToolFunctionDelta};
+use futures_util::Stream;
+use log::debug;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// The base URL of the Anthropic API.
+pub const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
+/// The version of the Anthropic API the adapter is written against.
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Path of the Messages endpoint, relative to the base URL.
+const MESSAGES_PATH: &str = "/v1/messages";
+
+/// `max_tokens` of requests that do not set it.
+const DEFAULT_MAX_TOKENS: usize = 4096;
+
+/// Client for the Anthropic Messages API.
+pub struct AnthropicClient {
+    base_url: String,
+    api_key: String,
+    client: Client,
+    default_max_tokens: usize,
+}
+
+impl AnthropicClient {
+    /// Creates a client for the Anthropic API with the given API key.
+    pub fn new(api_key: &str) -> Self {
+        Self::with_base_url(api_key, ANTHROPIC_BASE_URL)
+    }
+
+    /// Creates a client that sends its requests to `base_url`, e.g. a proxy.
+    pub fn with_base_url(api_key: &str, base_url: &str) -> Self {
+        let builder = Client::builder();
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.use_rustls_tls();
+        Self {
+            base_url: base_url.to_string(),
+            api_key: api_key.to_string(),
+            client: builder.build().expect("New client"),
+            default_max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+
+    /// Sets the `max_tokens` of requests whose [`ChatInput`] does not set it.
+    pub fn default_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.default_max_tokens = max_tokens;
+        self
+    }
+
+    /// Sends a chat request to the Messages API.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn chat(&self, input: ChatInput) -> Result<ChatResponse, ChatGPTError> {
+        let response = self.send(&input, false).await?;
+        let message: MessagesResponse = response.json().await?;
+        Ok(message.into_chat_response())
+    }
+
+    /// Sends a chat request to the Messages API and streams the answer as [`ChatChunk`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails. `error` events in the stream are yielded
+    /// as `ChatGPTError::RequestFailed` items.
+    pub async fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> Result<impl Stream<Item = Result<ChatChunk, ChatGPTError>>, ChatGPTError> {
+        let response = self.send(&input, true).await?;
+        let mut state = StreamState::default();
+        Ok(sse_stream(
+            response.bytes_stream(),
+            move |data| match serde_json::from_str(data) {
+                Ok(event) => stream_event_chunk(event, &mut state),
+                Err(err) => Some(Err(err.into())),
+            },
+        ))
+    }
+
+    async fn send(&self, input: &ChatInput, stream: bool) -> Result<Response, ChatGPTError> {
+        let request = MessagesRequest::new(input, self.default_max_tokens, stream)?;
+        let url = format!("{}{}", self.base_url, MESSAGES_PATH);
+        debug!(
+            "API call to url: {}\n with json payload: {:?}",
+            &url, request
+        );
+        let response = self
+            .client
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            let status_code = response.status();
+            let headers = response.headers().clone();
+            let body = response.text().await?;
+            Err(ChatGPTError::RequestFailed {
+                status_code,
+                headers,
+                body,
+            })
+        }
+    }
+}
+
+/// The body of a Messages request.
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a Model,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage<'a>>,
+    max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage<'a> {
+    role: &'a Role,
+    content: MessageContent<'a>,
+}
+
+/// The content of a message, plain text or a list of blocks.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum MessageContent<'a> {
+    Text(Cow<'a, str>),
+    Blocks(Vec<RequestBlock<'a>>),
+}
+
+/// A content block of a request message.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RequestBlock<'a> {
+    Text {
+        text: Cow<'a, str>,
+    },
+    Image {
+        source: Source<'a>,
+    },
+    Document {
+        source: Source<'a>,
+    },
+    ToolUse {
+        id: &'a str,
+        name: &'a str,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: &'a str,
+        content: MessageContent<'a>,
+    },
+}
+
+/// Where the data of an image or document block comes from.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Source<'a> {
+    Base64 { media_type: &'a str, data: &'a str },
+    Url { url: &'a str },
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata<'a> {
+    user_id: &'a str,
+}
+
+impl<'a> MessagesRequest<'a> {
+    fn new(
+        input: &'a ChatInput,
+        default_max_tokens: usize,
+        stream: bool,
+    ) -> Result<Self, ChatGPTError> {
+        let system = input
+            .messages
+            .iter()
+            .filter(|message| message.role.is_instructions())
+            .map(|message| match message_content(&message.content)? {
+                MessageContent::Text(text) => Ok(text),
+                MessageContent::Blocks(_) => Err(ChatGPTError::Unsupported(
+                    "instructions other than text".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, ChatGPTError>>()?;
+
+        let mut messages: Vec<AnthropicMessage> = Vec::new();
+        let mut after_tool_result = false;
+        for message in input
+            .messages
+            .iter()
+            .filter(|message| !message.role.is_instructions())
+        {
+            let is_tool_result = message.role == Role::Tool;
+            let converted = AnthropicMessage::new(message)?;
+            match messages.last_mut() {
+                // The results of parallel tool calls are answered in a single user turn.
+                Some(last) if is_tool_result && after_tool_result => {
+                    let content =
+                        std::mem::replace(&mut last.content, MessageContent::Blocks(Vec::new()));
+                    let mut blocks = content.into_blocks();
+                    blocks.extend(converted.content.into_blocks());
+                    last.content = MessageContent::Blocks(blocks);
+                }
+                _ => messages.push(converted),
+            }
+            after_tool_result = is_tool_result;
+        }
+
+        Ok(Self {
+            model: &input.model,
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages,
+            max_tokens: input.max_tokens.unwrap_or(default_max_tokens),
+            temperature: input.temperature,
+            top_p: input.top_p,
+            stop_sequences: input.stop.as_deref(),
+            stream,
+            metadata: input.user.as_deref().map(|user_id| Metadata { user_id }),
+            tools: tools(&input.extra)?,
+            tool_choice: tool_choice(&input.extra)?,
+        })
+    }
+}
+
+impl<'a> AnthropicMessage<'a> {
+    /// Maps a message other than instructions onto an Anthropic message.
+    fn new(message: &'a Message) -> Result<Self, ChatGPTError> {
+        #[cfg(feature = "legacy-functions")]
+        if message.role == Role::Function || message.function_call.is_some() {
+            return Err(ChatGPTError::Unsupported(
+                "legacy function calls".to_string(),
+            ));
+        }
+        if message.role == Role::Tool {
+            // Tool results are user turns for Anthropic.
+            let tool_use_id = message.tool_call_id.as_deref().ok_or_else(|| {
+                ChatGPTError::Unsupported("tool results without a tool_call_id".to_string())
+            })?;
+            return Ok(Self {
+                role: &Role::User,
+                content: MessageContent::Blocks(vec![RequestBlock::ToolResult {
+                    tool_use_id,
+                    content: message_content(&message.content)?,
+                }]),
+            });
+        }
+        let content = message_content(&message.content)?;
+        if message.tool_calls.is_empty() {
+            return Ok(Self {
+                role: &message.role,
+                content,
+            });
+        }
+        let mut blocks: Vec<RequestBlock> = content
+            .into_blocks()
+            .into_iter()
+            .filter(|block| !matches!(block, RequestBlock::Text { text } if text.is_empty()))
+            .collect();
+        for call in &message.tool_calls {
+            let input = match call.function.arguments.trim() {
+                "" => Value::Object(Map::new()),
+                arguments => serde_json::from_str(arguments)?,
+            };
+            blocks.push(RequestBlock::ToolUse {
+                id: &call.id,
+                name: &call.function.name,
+                input,
+            });
+        }
+        Ok(Self {
+            role: &message.role,
+            content: MessageContent::Blocks(blocks),
+        })
+    }
+}
+
+impl<'a> MessageContent<'a> {
+    fn into_blocks(self) -> Vec<RequestBlock<'a>> {
+        match self {
+            MessageContent::Text(text) => vec![RequestBlock::Text { text }],
+            MessageContent::Blocks(blocks) => blocks,
+        }
+    }
+}
+
+impl<'a> Source<'a> {
+    /// The source of an `https` URL, or of the data of a base64 `data:` URL.
+    fn from_url(url: &'a str) -> Self {
+        match url
+            .strip_prefix("data:")
+            .and_then(|data| data.split_once(";base64,"))
+        {
+            Some((media_type, data)) => Source::Base64 { media_type, data },
+            None => Source::Url { url },
+        }
+    }
+}
+
+/// Maps `content` onto plain text, or onto blocks if it has parts other than text.
+fn message_content(content: &Content) -> Result<MessageContent<'_>, ChatGPTError> {
+    let parts = match content {
+        Content::Parts(parts)
+            if parts
+                .iter()
+                .any(|part| !matches!(part, ContentPart::Text { .. })) =>
+        {
+            parts
+        }
+        _ => return Ok(MessageContent::Text(content.text())),
+    };
+    parts
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => Ok(RequestBlock::Text {
+                text: Cow::Borrowed(text),
+            }),
+            ContentPart::ImageUrl { image_url } => Ok(RequestBlock::Image {
+                source: Source::from_url(&image_url.url),
+            }),
+            ContentPart::File { file } => match &file.file_data {
+                Some(file_data) => Ok(RequestBlock::Document {
+                    source: Source::from_url(file_data),
+                }),
+                None => Err(ChatGPTError::Unsupported(
+                    "files uploaded to the OpenAI Files API".to_string(),
+                )),
+            },
+            ContentPart::InputAudio { .. } => {
+                Err(ChatGPTError::Unsupported("audio input".to_string()))
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(MessageContent::Blocks)
+}
+
+/// Maps the OpenAI `tools` among the extra request fields onto Anthropic tools.
+fn tools(extra: &Map<String, Value>) -> Result<Vec<Value>, ChatGPTError> {
+    let Some(tools) = extra.get("tools") else {
+        return Ok(Vec::new());
+    };
+    let unsupported = || ChatGPTError::Unsupported(format!("tools other than functions: {tools}"));
+    tools
+        .as_array()
+        .ok_or_else(unsupported)?
+        .iter()
+        .map(|tool| {
+            let function = tool
+                .get("function")
+                .filter(|_| tool["type"] == "function")
+                .ok_or_else(unsupported)?;
+            let mut anthropic = json!({
+                "name": function.get("name").ok_or_else(unsupported)?,
+                "input_schema": function
+                    .get("parameters")
+                    .cloned()
+                    .unwrap_or_else(|| json!({"type": "object"})),
+            });
+            if let Some(description) = function.get("description") {
+                anthropic["description"] = description.clone();
+            }
+            Ok(anthropic)
+        })
+        .collect()
+}
+
+/// Maps the OpenAI `tool_choice` among the extra request fields onto Anthropic's.
+fn tool_choice(extra: &Map<String, Value>) -> Result<Option<Value>, ChatGPTError> {
+    let Some(tool_choice) = extra.get("tool_choice") else {
+        return Ok(None);
+    };
+    let mapped = match tool_choice.as_str() {
+        Some("auto") => json!({"type": "auto"}),
+        Some("required") => json!({"type": "any"}),
+        Some("none") => json!({"type": "none"}),
+        _ => match tool_choice.pointer("/function/name") {
+            Some(name) => json!({"type": "tool", "name": name}),
+            None => {
+                return Err(ChatGPTError::Unsupported(format!(
+                    "tool_choice {tool_choice}"
+                )))
+            }
+        },
+    };
+    Ok(Some(mapped))
+}
+
+/// The response to a non-streaming Messages request.
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    id: String,
+    model: String,
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: i64,
+    #[serde(default)]
+    output_tokens: i64,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<i64>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<i64>,
+}
+
+impl From<&AnthropicUsage> for Usage {
+    /// Anthropic's `input_tokens` exclude the tokens read from or written to the prompt cache,
+    /// which OpenAI counts as prompt tokens.
+    fn from(usage: &AnthropicUsage) -> Self {
+        let cached_tokens = usage.cache_read_input_tokens.unwrap_or(0);
+        let prompt_tokens =
+            usage.input_tokens + cached_tokens + usage.cache_creation_input_tokens.unwrap_or(0);
+        Usage {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens + usage.output_tokens,
+            prompt_tokens_details: usage.cache_read_input_tokens.map(|cached_tokens| {
+                PromptTokensDetails {
+                    cached_tokens: Some(cached_tokens),
+                    ..Default::default()
+                }
+            }),
+            completion_tokens_details: None,
+            extensions: Default::default(),
+        }
+    }
+}
+
+impl MessagesResponse {
+    fn into_chat_response(self) -> ChatResponse {
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for block in self.content {
+            match block {
+                ContentBlock::Text { text } => content.push_str(&text),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    kind: "function".to_string(),
+                    function: ToolFunction {
+                        name,
+                        arguments: input.to_string(),
+                    },
+                }),
+                ContentBlock::Other => {}
+            }
+        }
+        // Like OpenAI, messages that only call tools have no content.
+        let content = (!content.is_empty() || tool_calls.is_empty()).then_some(content);
+        let message = Message {
+            tool_calls,
+            ..Message::assistant(content)
+        };
+        ChatResponse {
+            id: self.id,
+            object: "chat.completion".to_string(),
+            created: now(),
+            model: self.model,
+            usage: Usage::from(&self.usage),
+            choices: vec![Choice::new(
+                message,
+                finish_reason(self.stop_reason.as_deref()),
+            )],
+            extensions: Default::default(),
+            request_id: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// An event of a streamed Messages response.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: StartedMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+    },
+    Error {
+        error: StreamError,
+    },
+    /// `ping`, `content_block_stop` and `message_stop`.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct StartedMessage {
+    id: String,
+    model: String,
+}
+
+/// What the events of a stream told so far.
+#[derive(Debug, Default)]
+struct StreamState {
+    message: StartedMessage,
+    /// The position among the tool calls of the message of each `tool_use` block, by the
+    /// index of the block.
+    tool_calls: HashMap<usize, usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct StreamError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+/// Translates a stream event into a chunk, remembering the message and tool calls it belongs
+/// to.
+fn stream_event_chunk(
+    event: StreamEvent,
+    state: &mut StreamState,
+) -> Option<Result<ChatChunk, ChatGPTError>> {
+    let (delta, finish_reason) = match event {
+        StreamEvent::MessageStart { message } => {
+            state.message = message;
+            let delta = Delta {
+                role: Some(Role::Assistant),
+                content: Some(String::new()),
+                ..Default::default()
+            };
+            (delta, None)
+        }
+        StreamEvent::ContentBlockStart {
+            index,
+            content_block: ContentBlock::ToolUse { id, name, .. },
+        } => {
+            let tool_index = state.tool_calls.len();
+            state.tool_calls.insert(index, tool_index);
+            let delta = Delta {
+                tool_calls: vec![ToolCallDelta {
+                    index: tool_index,
+                    id: Some(id),
+                    kind: Some("function".to_string()),
+                    function: Some(ToolFunctionDelta {
+                        name: Some(name),
+                        arguments: Some(String::new()),
+                    }),
+                }],
+                ..Default::default()
+            };
+            (delta, None)
+        }
+        StreamEvent::ContentBlockDelta {
+            delta: BlockDelta::TextDelta { text },
+            ..
+        } => {
+            let delta = Delta {
+                role: None,
+                content: Some(text),
+                ..Default::default()
+            };
+            (delta, None)
+        }
+        StreamEvent::ContentBlockDelta {
+            index,
+            delta: BlockDelta::InputJsonDelta { partial_json },
+        } => {
+            let delta = Delta {
+                tool_calls: vec![ToolCallDelta {
+                    index: *state.tool_calls.get(&index)?,
+                    function: Some(ToolFunctionDelta {
+                        name: None,
+                        arguments: Some(partial_json),
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            (delta, None)
+        }
+        StreamEvent::MessageDelta { delta } => (
+            Delta::default(),
+            Some(finish_reason(delta.stop_reason.as_deref())),
+        ),
+        StreamEvent::Error { error } => {
+            let body = json!({ "type": "error", "error": error }).to_string();
+            return Some(Err(ChatGPTError::RequestFailed {
+                status_code: error_status(&error.kind),
+                headers: HeaderMap::new(),
+                body,
+            }));
+        }
+        StreamEvent::ContentBlockStart { .. }
+        | StreamEvent::ContentBlockDelta { .. }
+        | StreamEvent::Other => return None,
+    };
+    Some(Ok(ChatChunk {
+        id: state.message.id.clone(),
+        object: "chat.completion.chunk".to_string(),
+        created: now(),
+        model: state.message.model.clone(),
+        choices: vec![ChunkChoice {
+            index: 0,
+            delta,
+            finish_reason,
+            extensions: Default::default(),
+        }],
+        usage: None,
+        extensions: Default::default(),
+    }))
+}
+
+/// Maps an Anthropic stop reason onto the corresponding OpenAI finish reason.
+fn finish_reason(stop_reason: Option<&str>) -> String {
+    match stop_reason {
+        None | Some("end_turn") | Some("stop_sequence") => "stop",
+        Some("max_tokens") => "length",
+        Some("tool_use") => "tool_calls",
+        Some(other) => other,
+    }
+    .to_string()
+}
+
+/// The HTTP status Anthropic uses for errors of the given type.
+fn error_status(kind: &str) -> StatusCode {
+    match kind {
+        "invalid_request_error" => StatusCode::BAD_REQUEST,
+        "authentication_error" => StatusCode::UNAUTHORIZED,
+        "permission_error" => StatusCode::FORBIDDEN,
+        "not_found_error" => StatusCode::NOT_FOUND,
+        "rate_limit_error" => StatusCode::TOO_MANY_REQUESTS,
+        "overloaded_error" => StatusCode::from_u16(529).unwrap(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Seconds since the Unix epoch, for the `created` field Anthropic does not return.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+impl ChatProvider for AnthropicClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            embeddings: false,
+        }
+    }
+
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+        Box::pin(AnthropicClient::chat(self, input))
+    }
+
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+        Box::pin(async move {
+            let chunks = AnthropicClient::chat_stream(self, input).await?;
+            Ok(Box::pin(chunks) as ChatStream<'_>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn input() -> ChatInput {
+        ChatInput {
+            model: Model::Other("claude-3-5-haiku-latest".to_string()),
+            messages: vec![
+                Message {
+                    role: Role::System,
+                    content: "Be brief.".into(),
+                    ..Default::default()
+                },
+                Message {
+                    role: Role::User,
+                    content: "Hi".into(),
+                    ..Default::default()
+                },
+            ],
+            stop: Some(vec!["\n\n".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_messages_request() {
+        let input = input();
+        let request = MessagesRequest::new(&input, 1024, false).unwrap();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "claude-3-5-haiku-latest",
+                "system": "Be brief.",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_tokens": 1024,
+                "stop_sequences": ["\n\n"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_messages_request_with_tools_and_images() {
+        let input = ChatInput::builder(Model::Other("claude-3-5-haiku-latest".to_string()))
+            .message(Message::user(vec![
+                ContentPart::text("Weather here?"),
+                ContentPart::image_url("data:image/png;base64,iVBORw0KGgo="),
+            ]))
+            .message(Message {
+                tool_calls: vec![
+                    ToolCall {
+                        id: "toolu_1".to_string(),
+                        kind: "function".to_string(),
+                        function: ToolFunction {
+                            name: "weather".to_string(),
+                            arguments: r#"{"city":"Paris"}"#.to_string(),
+                        },
+                    },
+                    ToolCall {
+                        id: "toolu_2".to_string(),
+                        kind: "function".to_string(),
+                        function: ToolFunction {
+                            name: "time".to_string(),
+                            arguments: String::new(),
+                        },
+                    },
+                ],
+                ..Message::assistant(None)
+            })
+            .message(Message::tool_result("toolu_1", "Sunny"))
+            .message(Message::tool_result("toolu_2", "Noon"))
+            .extra(
+                "tools",
+                json!([{"type": "function", "function": {
+                    "name": "weather",
+                    "description": "The weather in a city.",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+                }}]),
+            )
+            .extra("tool_choice", "required")
+            .build();
+        let request = MessagesRequest::new(&input, 1024, false).unwrap();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "model": "claude-3-5-haiku-latest",
+                "messages": [
+                    {"role": "user", "content": [
+                        {"type": "text", "text": "Weather here?"},
+                        {"type": "image", "source": {
+                            "type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo=",
+                        }},
+                    ]},
+                    {"role": "assistant", "content": [
+                        {"type": "tool_use", "id": "toolu_1", "name": "weather",
+                         "input": {"city": "Paris"}},
+                        {"type": "tool_use", "id": "toolu_2", "name": "time", "input": {}},
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "tool_result", "tool_use_id": "toolu_1", "content": "Sunny"},
+                        {"type": "tool_result", "tool_use_id": "toolu_2", "content": "Noon"},
+                    ]},
+                ],
+                "max_tokens": 1024,
+                "tools": [{
+                    "name": "weather",
+                    "description": "The weather in a city.",
+                    "input_schema": {
+                        "type": "object", "properties": {"city": {"type": "string"}},
+                    },
+                }],
+                "tool_choice": {"type": "any"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_messages_request_rejects_unsupported_content() {
+        use crate::audio::AudioFormat;
+
+        let input = ChatInput::builder(Model::Other("claude-3-5-haiku-latest".to_string()))
+            .message(Message::user(vec![ContentPart::input_audio(
+                b"RIFF",
+                AudioFormat::Wav,
+            )]))
+            .build();
+        assert!(matches!(
+            MessagesRequest::new(&input, 1024, false),
+            Err(ChatGPTError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_tool_use_response() {
+        let response: MessagesResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "model": "claude-3-5-haiku-20241022",
+            "content": [
+                {"type": "tool_use", "id": "toolu_1", "name": "weather",
+                 "input": {"city": "Paris"}},
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 3},
+        }))
+        .unwrap();
+        let response = response.into_chat_response();
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason, "tool_calls");
+        assert!(choice.message.content.is_none());
+        assert_eq!(choice.message.tool_calls[0].id, "toolu_1");
+        assert_eq!(choice.message.tool_calls[0].function.name, "weather");
+        assert_eq!(
+            choice.message.tool_calls[0].function.arguments,
+            r#"{"city":"Paris"}"#
+        );
+    }
+
+    #[test]
+    fn test_stream_tool_use() {
+        use crate::stream::ResponseAccumulator;
+
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","model":"claude-3-5-haiku-20241022"}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me check."}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"Paris\"}"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"}}"#,
+        ];
+        let mut state = StreamState::default();
+        let mut accumulator = ResponseAccumulator::new();
+        for event in events {
+            let event = serde_json::from_str(event).unwrap();
+            if let Some(chunk) = stream_event_chunk(event, &mut state) {
+                accumulator.push(&chunk.unwrap());
+            }
+        }
+        let response = accumulator.finish();
+        let message = &response.choices[0].message;
+        assert_eq!(message.content, "Let me check.");
+        assert_eq!(message.tool_calls[0].id, "toolu_1");
+        assert_eq!(message.tool_calls[0].function.name, "weather");
+        assert_eq!(
+            message.tool_calls[0].function.arguments,
+            r#"{"city": "Paris"}"#
+        );
+        assert_eq!(response.choices[0].finish_reason, "tool_calls");
+    }
+
+    #[tokio::test]
+    async fn test_chat() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", "sk-ant"))
+            .and(header("anthropic-version", ANTHROPIC_VERSION))
+            .and(body_json(serde_json::json!({
+                "model": "claude-3-5-haiku-latest",
+                "system": "Be brief.",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_tokens": 4096,
+                "stop_sequences": ["\n\n"],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-5-haiku-20241022",
+                "content": [{"type": "text", "text": "Hello!"}],
+                "stop_reason": "max_tokens",
+                "usage": {"input_tokens": 10, "output_tokens": 3, "cache_read_input_tokens": 5},
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::with_base_url("sk-ant", &server.uri());
+        let response = client.chat(input()).await.unwrap();
+        assert_eq!(response.id, "msg_1");
+        assert_eq!(response.model, "claude-3-5-haiku-20241022");
+        assert_eq!(response.choices[0].message.content, "Hello!");
+        assert_eq!(response.choices[0].finish_reason, "length");
+        assert_eq!(response.usage.prompt_tokens, 15);
+        assert_eq!(response.usage.cached_tokens(), 5);
+        assert_eq!(response.usage.total_tokens, 18);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let body = [
+            r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-3-5-haiku-20241022","content":[],"usage":{"input_tokens":10,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"ping"}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]
+        .iter()
+        .map(|data| format!("event: x\ndata: {data}\n\n"))
+        .collect::<String>();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::with_base_url("sk-ant", &server.uri());
+        let chunks: Vec<ChatChunk> = client
+            .chat_stream(input())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks.iter().all(|chunk| chunk.id == "msg_1"));
+        assert_eq!(chunks[0].choices[0].delta.role, Some(Role::Assistant));
+        let content: String = chunks
+            .iter()
+            .filter_map(|chunk| chunk.choices[0].delta.content.as_deref())
+            .collect();
+        assert_eq!(content, "Hello");
+        assert_eq!(chunks[3].choices[0].finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn test_stream_error_event() {
+        let event = serde_json::from_str(
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        )
+        .unwrap();
+        let result = stream_event_chunk(event, &mut StreamState::default());
+        assert!(matches!(
+            result,
+            Some(Err(ChatGPTError::RequestFailed { status_code, .. })) if status_code.as_u16() == 529
+        ));
+    }
+}
@@ -0,0 +1,507 @@
+//! Adapter for the [Google Gemini API](https://ai.google.dev/gemini-api/docs).
+//!
+//! [`GeminiClient::chat`] and [`GeminiClient::chat_stream`] go through Gemini's
+//! [OpenAI compatibility endpoint](https://ai.google.dev/gemini-api/docs/openai), using a
+//! [`ChatGPTClient`] with all its features. Settings the compatibility endpoint does not
+//! support, such as safety settings, are available through the native `generateContent`
+//! endpoint with [`GeminiClient::generate_content`], which maps a [`ChatInput`] onto a
+//! `generateContent` request and its response back onto a [`ChatResponse`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::providers::gemini::{GeminiClient, GeminiOptions, SafetySetting};
+//! use chat_gpt_lib_rs::{ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = GeminiClient::new("your_gemini_key");
+//! let input = ChatInput::builder(Model::Other("gemini-2.0-flash".to_string()))
+//!     .message(Message::user("Hello!"))
+//!     .build();
+//! let options = GeminiOptions {
+//!     safety_settings: vec![SafetySetting {
+//!         category: "HARM_CATEGORY_HARASSMENT".to_string(),
+//!         threshold: "BLOCK_ONLY_HIGH".to_string(),
+//!     }],
+//! };
+//! let response = client.generate_content(input, &options).await?;
+//! println!("{}", response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{
+    ChatGPTClient, ChatGPTError, ChatInput, ChatResponse, Choice, Message, PromptTokensDetails,
+    Usage,
+};
+use crate::models::Role;
+use crate::providers::{Capabilities, ChatProvider, ChatStream, ProviderFuture};
+use crate::stream::ChatChunk;
+use futures_util::Stream;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// The base URL of the Gemini API.
+pub const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+
+/// Path prefix of the OpenAI compatibility endpoint, relative to the base URL.
+const OPENAI_COMPAT_PREFIX: &str = "/v1beta/openai";
+
+/// Path prefix of the native model endpoints, relative to the base URL.
+const MODELS_PREFIX: &str = "/v1beta/models";
+
+/// Client for the Gemini API.
+pub struct GeminiClient {
+    base_url: String,
+    api_key: String,
+    client: Client,
+    openai_compat: ChatGPTClient,
+}
+
+/// Settings of a native `generateContent` request that have no OpenAI equivalent.
+#[derive(Debug, Clone, Default)]
+pub struct GeminiOptions {
+    /// Blocking thresholds per harm category, replacing Gemini's defaults.
+    pub safety_settings: Vec<SafetySetting>,
+}
+
+/// The blocking threshold for one harm category.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetySetting {
+    /// The harm category, e.g. `HARM_CATEGORY_HATE_SPEECH`.
+    pub category: String,
+    /// The threshold, e.g. `BLOCK_MEDIUM_AND_ABOVE` or `BLOCK_NONE`.
+    pub threshold: String,
+}
+
+impl GeminiClient {
+    /// Creates a client for the Gemini API with the given API key.
+    pub fn new(api_key: &str) -> Self {
+        Self::with_base_url(api_key, GEMINI_BASE_URL)
+    }
+
+    /// Creates a client that sends its requests to `base_url`, e.g. a proxy.
+    pub fn with_base_url(api_key: &str, base_url: &str) -> Self {
+        let openai_compat = ChatGPTClient::builder(api_key, base_url)
+            .path_prefix(OPENAI_COMPAT_PREFIX)
+            .build()
+            .expect("New client");
+        let builder = Client::builder();
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.use_rustls_tls();
+        Self {
+            base_url: base_url.to_string(),
+            api_key: api_key.to_string(),
+            client: builder.build().expect("New client"),
+            openai_compat,
+        }
+    }
+
+    /// The client for the OpenAI compatibility endpoint, e.g. for its embeddings.
+    pub fn openai_compat(&self) -> &ChatGPTClient {
+        &self.openai_compat
+    }
+
+    /// Sends a chat request to the OpenAI compatibility endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn chat(&self, input: ChatInput) -> Result<ChatResponse, ChatGPTError> {
+        self.openai_compat.chat(input).await
+    }
+
+    /// Sends a chat request to the OpenAI compatibility endpoint and streams the answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails.
+    pub async fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> Result<impl Stream<Item = Result<ChatChunk, ChatGPTError>>, ChatGPTError> {
+        self.openai_compat.chat_stream(input).await
+    }
+
+    /// Sends a chat request to the native `generateContent` endpoint.
+    ///
+    /// System messages become the `systemInstruction` and assistant messages turns of the
+    /// `model` role. `logit_bias` and `user` have no equivalent and are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn generate_content(
+        &self,
+        input: ChatInput,
+        options: &GeminiOptions,
+    ) -> Result<ChatResponse, ChatGPTError> {
+        let request = GenerateContentRequest::new(&input, options);
+        let url = format!(
+            "{}{}/{}:generateContent",
+            self.base_url, MODELS_PREFIX, input.model
+        );
+        debug!(
+            "API call to url: {}\n with json payload: {:?}",
+            &url, request
+        );
+        let response = self
+            .client
+            .post(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status_code = response.status();
+            let headers = response.headers().clone();
+            let body = response.text().await?;
+            return Err(ChatGPTError::RequestFailed {
+                status_code,
+                headers,
+                body,
+            });
+        }
+        let generated: GenerateContentResponse = response.json().await?;
+        Ok(generated.into_chat_response(&input))
+    }
+}
+
+/// The body of a `generateContent` request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content<'a>>,
+    contents: Vec<Content<'a>>,
+    generation_config: GenerationConfig<'a>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    safety_settings: &'a [SafetySetting],
+}
+
+#[derive(Debug, Serialize)]
+struct Content<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Part<'a> {
+    text: Cow<'a, str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidate_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl<'a> GenerateContentRequest<'a> {
+    fn new(input: &'a ChatInput, options: &'a GeminiOptions) -> Self {
+        let system: Vec<Part<'a>> = input
+            .messages
+            .iter()
+            .filter(|message| message.role.is_instructions())
+            .map(|message| Part {
+                text: message.content.text(),
+            })
+            .collect();
+        Self {
+            system_instruction: (!system.is_empty()).then_some(Content {
+                role: None,
+                parts: system,
+            }),
+            contents: input
+                .messages
+                .iter()
+                .filter_map(|message| {
+                    let role = match message.role {
+                        Role::System | Role::Developer => return None,
+                        #[cfg(feature = "legacy-functions")]
+                        Role::Function => "user",
+                        Role::User | Role::Tool | Role::Other(_) => "user",
+                        Role::Assistant => "model",
+                    };
+                    Some(Content {
+                        role: Some(role),
+                        parts: vec![Part {
+                            text: message.content.text(),
+                        }],
+                    })
+                })
+                .collect(),
+            generation_config: GenerationConfig {
+                temperature: input.temperature,
+                top_p: input.top_p,
+                candidate_count: input.n,
+                max_output_tokens: input.max_tokens,
+                stop_sequences: input.stop.as_deref(),
+                presence_penalty: input.presence_penalty,
+                frequency_penalty: input.frequency_penalty,
+                seed: input.seed,
+            },
+            safety_settings: &options.safety_settings,
+        }
+    }
+}
+
+/// The response to a `generateContent` request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: UsageMetadata,
+    #[serde(default)]
+    model_version: Option<String>,
+    #[serde(default)]
+    response_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<CandidateContent>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<CandidatePart>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CandidatePart {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: i64,
+    #[serde(default)]
+    candidates_token_count: i64,
+    #[serde(default)]
+    total_token_count: i64,
+    #[serde(default)]
+    cached_content_token_count: Option<i64>,
+}
+
+impl GenerateContentResponse {
+    fn into_chat_response(self, input: &ChatInput) -> ChatResponse {
+        let choices = self
+            .candidates
+            .into_iter()
+            .map(|candidate| {
+                let text: String = candidate
+                    .content
+                    .into_iter()
+                    .flat_map(|content| content.parts)
+                    .filter_map(|part| part.text)
+                    .collect();
+                Choice::new(
+                    Message::assistant(text),
+                    finish_reason(candidate.finish_reason.as_deref()),
+                )
+            })
+            .collect();
+        let usage = self.usage_metadata;
+        ChatResponse {
+            id: self.response_id.unwrap_or_default(),
+            object: "chat.completion".to_string(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64),
+            model: self
+                .model_version
+                .unwrap_or_else(|| input.model.to_string()),
+            usage: Usage {
+                prompt_tokens: usage.prompt_token_count,
+                completion_tokens: usage.candidates_token_count,
+                total_tokens: usage.total_token_count,
+                prompt_tokens_details: usage.cached_content_token_count.map(|cached_tokens| {
+                    PromptTokensDetails {
+                        cached_tokens: Some(cached_tokens),
+                        ..Default::default()
+                    }
+                }),
+                completion_tokens_details: None,
+                extensions: Default::default(),
+            },
+            choices,
+            extensions: Default::default(),
+            request_id: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Maps a Gemini finish reason onto the corresponding OpenAI finish reason.
+fn finish_reason(finish_reason: Option<&str>) -> String {
+    match finish_reason {
+        None | Some("STOP") => "stop".to_string(),
+        Some("MAX_TOKENS") => "length".to_string(),
+        Some("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII") => {
+            "content_filter".to_string()
+        }
+        Some(other) => other.to_ascii_lowercase(),
+    }
+}
+
+impl ChatProvider for GeminiClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            embeddings: false,
+        }
+    }
+
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+        Box::pin(GeminiClient::chat(self, input))
+    }
+
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+        Box::pin(async move {
+            let chunks = GeminiClient::chat_stream(self, input).await?;
+            Ok(Box::pin(chunks) as ChatStream<'_>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Model;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn input() -> ChatInput {
+        ChatInput {
+            model: Model::Other("gemini-2.0-flash".to_string()),
+            messages: vec![
+                Message {
+                    role: Role::System,
+                    content: "Be brief.".into(),
+                    ..Default::default()
+                },
+                Message {
+                    role: Role::User,
+                    content: "Hi".into(),
+                    ..Default::default()
+                },
+                Message {
+                    role: Role::Assistant,
+                    content: "Hello!".into(),
+                    ..Default::default()
+                },
+                Message {
+                    role: Role::User,
+                    content: "Bye".into(),
+                    ..Default::default()
+                },
+            ],
+            max_tokens: Some(100),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_content() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1beta/models/gemini-2.0-flash:generateContent"))
+            .and(header("x-goog-api-key", "gemini-key"))
+            .and(body_json(serde_json::json!({
+                "systemInstruction": {"parts": [{"text": "Be brief."}]},
+                "contents": [
+                    {"role": "user", "parts": [{"text": "Hi"}]},
+                    {"role": "model", "parts": [{"text": "Hello!"}]},
+                    {"role": "user", "parts": [{"text": "Bye"}]},
+                ],
+                "generationConfig": {"maxOutputTokens": 100},
+                "safetySettings": [{"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"}],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "Good"}, {"text": "bye!"}]},
+                    "finishReason": "MAX_TOKENS",
+                }],
+                "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 3, "totalTokenCount": 15},
+                "modelVersion": "gemini-2.0-flash-001",
+                "responseId": "resp-1",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = GeminiClient::with_base_url("gemini-key", &server.uri());
+        let options = GeminiOptions {
+            safety_settings: vec![SafetySetting {
+                category: "HARM_CATEGORY_HARASSMENT".to_string(),
+                threshold: "BLOCK_NONE".to_string(),
+            }],
+        };
+        let response = client.generate_content(input(), &options).await.unwrap();
+        assert_eq!(response.id, "resp-1");
+        assert_eq!(response.model, "gemini-2.0-flash-001");
+        assert_eq!(response.choices[0].message.content, "Goodbye!");
+        assert_eq!(response.choices[0].finish_reason, "length");
+        assert_eq!(response.usage.total_tokens, 15);
+    }
+
+    #[tokio::test]
+    async fn test_chat_uses_openai_compat_endpoint() {
+        use crate::test_util::chat_completion;
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1beta/openai/chat/completions"))
+            .and(header("authorization", "Bearer gemini-key"))
+            .respond_with(chat_completion("Hi!"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = GeminiClient::with_base_url("gemini-key", &server.uri());
+        let response = client.chat(input()).await.unwrap();
+        assert_eq!(response.choices[0].message.content, "Hi!");
+    }
+
+    #[test]
+    fn test_finish_reason() {
+        assert_eq!(finish_reason(Some("STOP")), "stop");
+        assert_eq!(finish_reason(Some("SAFETY")), "content_filter");
+        assert_eq!(
+            finish_reason(Some("MALFORMED_FUNCTION_CALL")),
+            "malformed_function_call"
+        );
+    }
+}
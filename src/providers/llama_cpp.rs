@@ -0,0 +1,85 @@
+//! Constrained decoding on [llama.cpp](https://github.com/ggerganov/llama.cpp) servers.
+//!
+//! The llama.cpp server's OpenAI-compatible endpoint accepts two request fields the OpenAI
+//! API does not have, which restrict sampling to tokens that keep the output valid:
+//!
+//! - `grammar`: a grammar in llama.cpp's GBNF format.
+//! - `json_schema`: a JSON schema the output has to match, converted to a grammar by the server.
+//!
+//! [`Constraint`] adds either field to a request's [`ChatInput::extra`] fields. Other servers
+//! built on llama.cpp, such as LocalAI, accept the same fields. Use
+//! [`CompatMode::Lenient`](crate::compat::CompatMode::Lenient) to read their responses.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::providers::llama_cpp::Constraint;
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("", "http://localhost:8080");
+//! let mut input = ChatInput::builder(Model::Other("local".to_string()))
+//!     .message(Message::user("Is the sky blue?"))
+//!     .build();
+//! Constraint::grammar(r#"root ::= "yes" | "no""#).apply(&mut input);
+//! let response = client.chat(input).await?;
+//! println!("{}", response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::ChatInput;
+use serde_json::Value;
+
+/// A restriction on the output of a llama.cpp server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    /// A grammar in GBNF format, sent as `grammar`.
+    Grammar(String),
+    /// A JSON schema, sent as `json_schema`.
+    JsonSchema(Value),
+}
+
+impl Constraint {
+    /// Restricts the output to the GBNF grammar `gbnf`.
+    pub fn grammar(gbnf: impl Into<String>) -> Self {
+        Self::Grammar(gbnf.into())
+    }
+
+    /// Restricts the output to JSON matching `schema`.
+    pub fn json_schema(schema: Value) -> Self {
+        Self::JsonSchema(schema)
+    }
+
+    /// Adds the constraint to the extra fields of `input`, replacing any previous one.
+    pub fn apply(self, input: &mut ChatInput) {
+        input.extra.remove("grammar");
+        input.extra.remove("json_schema");
+        let (field, value) = match self {
+            Self::Grammar(gbnf) => ("grammar", Value::String(gbnf)),
+            Self::JsonSchema(schema) => ("json_schema", schema),
+        };
+        input.extra.insert(field.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_constraint() {
+        let mut input = ChatInput::default();
+        Constraint::grammar("root ::= [0-9]+").apply(&mut input);
+        let body = serde_json::to_value(&input).unwrap();
+        assert_eq!(body["grammar"], "root ::= [0-9]+");
+
+        let schema = json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+        Constraint::json_schema(schema.clone()).apply(&mut input);
+        let body = serde_json::to_value(&input).unwrap();
+        assert_eq!(body["json_schema"], schema);
+        assert!(body.get("grammar").is_none());
+        assert_eq!(body["model"], "gpt-4");
+    }
+}
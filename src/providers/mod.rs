@@ -0,0 +1,184 @@
+//! Adapters for chat APIs of other vendors.
+//!
+//! Each adapter accepts the crate's [`ChatInput`](crate::ChatInput) and returns
+//! [`ChatResponse`](crate::ChatResponse)s and [`ChatChunk`](crate::ChatChunk)s, mapping them
+//! onto the vendor's own API, so applications can switch vendors without changing call sites.
+//! Name the vendor's models with [`Model::Other`](crate::Model::Other).
+//!
+//! - [`anthropic`]: the Anthropic Messages API.
+//! - [`gemini`]: the Google Gemini API.
+//! - [`llama_cpp`]: constrained decoding on llama.cpp servers.
+//! - [`ollama`]: a local Ollama server.
+//! - [`openrouter`]: OpenRouter, with its provider routing fields.
+//!
+//! The adapters and [`ChatGPTClient`] implement [`ChatProvider`], so code can be generic over
+//! the vendor, and tests can substitute a fake.
+
+use crate::client::{ChatGPTClient, ChatGPTError, ChatInput, ChatResponse};
+use crate::embeddings::{EmbeddingsInput, EmbeddingsResponse};
+use crate::stream::ChatChunk;
+
+pub mod anthropic;
+pub mod gemini;
+pub mod llama_cpp;
+pub mod ollama;
+pub mod openrouter;
+
+/// The future returned by the methods of a [`ChatProvider`].
+///
+/// It is `Send` except on WebAssembly, where the HTTP client's futures are not.
+#[cfg(not(target_arch = "wasm32"))]
+pub type ProviderFuture<'a, T> = futures_util::future::BoxFuture<'a, T>;
+/// The future returned by the methods of a [`ChatProvider`].
+///
+/// It is `Send` except on WebAssembly, where the HTTP client's futures are not.
+#[cfg(target_arch = "wasm32")]
+pub type ProviderFuture<'a, T> = futures_util::future::LocalBoxFuture<'a, T>;
+
+/// The stream of chunks returned by [`ChatProvider::chat_stream`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type ChatStream<'a> = futures_util::stream::BoxStream<'a, Result<ChatChunk, ChatGPTError>>;
+/// The stream of chunks returned by [`ChatProvider::chat_stream`].
+#[cfg(target_arch = "wasm32")]
+pub type ChatStream<'a> = futures_util::stream::LocalBoxStream<'a, Result<ChatChunk, ChatGPTError>>;
+
+/// What a [`ChatProvider`] supports besides non-streaming chat requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether [`ChatProvider::chat_stream`] streams the answer.
+    pub streaming: bool,
+    /// Whether [`ChatProvider::embeddings`] is supported.
+    pub embeddings: bool,
+}
+
+/// A chat API that accepts the crate's request types.
+///
+/// # Examples
+///
+/// A fake provider for unit tests of code that is generic over the provider:
+///
+/// ```
+/// use chat_gpt_lib_rs::client::{ChatGPTError, Choice};
+/// use chat_gpt_lib_rs::providers::{Capabilities, ChatProvider, ChatStream, ProviderFuture};
+/// use chat_gpt_lib_rs::{ChatInput, ChatResponse, Message};
+///
+/// struct Echo;
+///
+/// impl ChatProvider for Echo {
+///     fn capabilities(&self) -> Capabilities {
+///         Capabilities::default()
+///     }
+///
+///     fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+///         let content = input.messages.last().map(|m| m.content.clone()).unwrap_or_default();
+///         let mut response = ChatResponse::default();
+///         response.id = "echo".to_string();
+///         response.object = "chat.completion".to_string();
+///         response.model = input.model.to_string();
+///         response
+///             .choices
+///             .push(Choice::new(Message::assistant(content), "stop"));
+///         Box::pin(async move { Ok(response) })
+///     }
+///
+///     fn chat_stream(
+///         &self,
+///         _input: ChatInput,
+///     ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+///         Box::pin(async { Err(ChatGPTError::Unsupported("streaming".to_string())) })
+///     }
+/// }
+/// ```
+pub trait ChatProvider {
+    /// What the provider supports.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Sends a chat request and returns the complete answer.
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>>;
+
+    /// Sends a chat request and streams the answer.
+    ///
+    /// Providers without streaming fail with `ChatGPTError::Unsupported`.
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>>;
+
+    /// Creates embeddings for the texts of `input`.
+    ///
+    /// The default implementation fails with `ChatGPTError::Unsupported`.
+    fn embeddings(
+        &self,
+        input: EmbeddingsInput,
+    ) -> ProviderFuture<'_, Result<EmbeddingsResponse, ChatGPTError>> {
+        let _ = input;
+        Box::pin(async { Err(ChatGPTError::Unsupported("embeddings".to_string())) })
+    }
+}
+
+impl ChatProvider for ChatGPTClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            embeddings: true,
+        }
+    }
+
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+        Box::pin(ChatGPTClient::chat(self, input))
+    }
+
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+        Box::pin(async move {
+            let chunks = ChatGPTClient::chat_stream(self, input).await?;
+            Ok(Box::pin(chunks) as ChatStream<'_>)
+        })
+    }
+
+    fn embeddings(
+        &self,
+        input: EmbeddingsInput,
+    ) -> ProviderFuture<'_, Result<EmbeddingsResponse, ChatGPTError>> {
+        Box::pin(ChatGPTClient::embeddings(self, input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{chat_completion, chat_input, mock_chat_completions};
+    use wiremock::MockServer;
+
+    /// Generic code under test.
+    async fn answer(provider: &impl ChatProvider) -> Result<String, ChatGPTError> {
+        let response = provider.chat(chat_input()).await?;
+        Ok(response.choices[0].message.content.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_providers_are_interchangeable() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .respond_with(chat_completion("Hi!"))
+            .mount(&server)
+            .await;
+
+        let openai = ChatGPTClient::new("sk-test", &server.uri());
+        let ollama = ollama::OllamaClient::with_base_url(&server.uri());
+        assert_eq!(answer(&openai).await.unwrap(), "Hi!");
+        assert_eq!(answer(&ollama).await.unwrap(), "Hi!");
+
+        let providers: Vec<Box<dyn ChatProvider>> = vec![
+            Box::new(openai),
+            Box::new(anthropic::AnthropicClient::new("sk-ant")),
+        ];
+        assert!(providers[0].capabilities().embeddings);
+        assert!(matches!(
+            providers[1].embeddings(EmbeddingsInput::default()).await,
+            Err(ChatGPTError::Unsupported(_))
+        ));
+    }
+}
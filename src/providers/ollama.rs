@@ -0,0 +1,310 @@
+//! Integration with a local [Ollama](https://ollama.com) server.
+//!
+//! [`OllamaClient`] sends chat requests to Ollama's OpenAI-compatible endpoint at
+//! `http://localhost:11434/v1`, so local development can use the same code path as production.
+//! Local models are named with [`Model::Other`], e.g. `Model::Other("llama3.2".into())`, and
+//! listed with [`OllamaClient::local_models`].
+//!
+//! Ollama's responses differ from OpenAI's in a few places: fields such as `usage` may be
+//! missing, and finish reasons may be absent or one of Ollama's own (`load`, `unload`). The
+//! client fills in the missing fields and reports such finish reasons as `stop`.
+//!
+//! How long a model stays loaded after a request is controlled with
+//! [`OllamaClient::keep_alive`], which uses Ollama's native API because the OpenAI-compatible
+//! endpoint does not support it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::providers::ollama::OllamaClient;
+//! use chat_gpt_lib_rs::{ChatInput, Message, Model};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = OllamaClient::new();
+//! let model = Model::Other("llama3.2".to_string());
+//! client.keep_alive(&model, Duration::from_secs(30 * 60)).await?;
+//! let response = client
+//!     .chat(
+//!         ChatInput::builder(model)
+//!             .message(Message::user("Hello!"))
+//!             .build(),
+//!     )
+//!     .await?;
+//! println!("{}", response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{ChatGPTClient, ChatGPTError, ChatInput, ChatResponse};
+use crate::models::Model;
+use crate::providers::{Capabilities, ChatProvider, ChatStream, ProviderFuture};
+use crate::stream::ChatChunk;
+use futures_util::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The base URL of a local Ollama server.
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434";
+
+/// Ollama ignores the API key, but OpenAI clients have to send one.
+const OLLAMA_API_KEY: &str = "ollama";
+
+/// Client for an Ollama server.
+pub struct OllamaClient {
+    base_url: String,
+    client: Client,
+    openai_compat: ChatGPTClient,
+}
+
+/// A model available on the Ollama server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LocalModel {
+    /// The name to use in [`Model::Other`], e.g. `llama3.2:latest`.
+    pub name: String,
+    /// Size of the model on disk, in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// Digest of the model's weights.
+    #[serde(default)]
+    pub digest: String,
+    /// When the model was last modified, as an RFC 3339 timestamp.
+    #[serde(default)]
+    pub modified_at: String,
+}
+
+impl Default for OllamaClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OllamaClient {
+    /// Creates a client for the Ollama server on `localhost:11434`.
+    pub fn new() -> Self {
+        Self::with_base_url(OLLAMA_BASE_URL)
+    }
+
+    /// Creates a client for the Ollama server at `base_url`, e.g. `http://gpu-box:11434`.
+    pub fn with_base_url(base_url: &str) -> Self {
+        let builder = Client::builder();
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.use_rustls_tls();
+        Self {
+            base_url: base_url.to_string(),
+            client: builder.build().expect("New client"),
+            openai_compat: ChatGPTClient::new(OLLAMA_API_KEY, base_url),
+        }
+    }
+
+    /// The client for the OpenAI-compatible endpoint, e.g. for requests with options.
+    pub fn openai_compat(&self) -> &ChatGPTClient {
+        &self.openai_compat
+    }
+
+    /// Sends a chat request to the OpenAI-compatible endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn chat(&self, input: ChatInput) -> Result<ChatResponse, ChatGPTError> {
+        let mut response = self.openai_compat.chat(input).await?;
+        for choice in &mut response.choices {
+            normalize_finish_reason(&mut choice.finish_reason);
+        }
+        Ok(response)
+    }
+
+    /// Sends a chat request to the OpenAI-compatible endpoint and streams the answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails.
+    pub async fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> Result<impl Stream<Item = Result<ChatChunk, ChatGPTError>>, ChatGPTError> {
+        let chunks = self.openai_compat.chat_stream(input).await?;
+        Ok(chunks.map(|chunk| {
+            chunk.map(|mut chunk| {
+                for choice in &mut chunk.choices {
+                    if let Some(finish_reason) = &mut choice.finish_reason {
+                        normalize_finish_reason(finish_reason);
+                    }
+                }
+                chunk
+            })
+        }))
+    }
+
+    /// Lists the models available on the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn local_models(&self) -> Result<Vec<LocalModel>, ChatGPTError> {
+        #[derive(Deserialize)]
+        struct Tags {
+            models: Vec<LocalModel>,
+        }
+
+        let request = self.client.get(format!("{}/api/tags", self.base_url));
+        let tags: Tags = send(request).await?.json().await?;
+        Ok(tags.models)
+    }
+
+    /// Loads `model` and keeps it in memory for `duration` after its last request, instead of
+    /// Ollama's default of five minutes.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails, e.g. because the model is not available.
+    pub async fn keep_alive(&self, model: &Model, duration: Duration) -> Result<(), ChatGPTError> {
+        let body = serde_json::json!({
+            "model": model,
+            "keep_alive": duration.as_secs(),
+        });
+        let request = self
+            .client
+            .post(format!("{}/api/generate", self.base_url))
+            .json(&body);
+        send(request).await.map(drop)
+    }
+
+    /// Unloads `model` from memory right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails.
+    pub async fn unload(&self, model: &Model) -> Result<(), ChatGPTError> {
+        self.keep_alive(model, Duration::ZERO).await
+    }
+}
+
+/// Sends a request to the native API, failing on non-successful responses.
+async fn send(request: RequestBuilder) -> Result<reqwest::Response, ChatGPTError> {
+    let response = request.send().await?;
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status_code = response.status();
+    let headers = response.headers().clone();
+    let body = response.text().await?;
+    Err(ChatGPTError::RequestFailed {
+        status_code,
+        headers,
+        body,
+    })
+}
+
+/// Reports missing finish reasons and Ollama's model (un)loading ones as `stop`.
+fn normalize_finish_reason(finish_reason: &mut String) {
+    if matches!(finish_reason.as_str(), "" | "load" | "unload") {
+        *finish_reason = "stop".to_string();
+    }
+}
+
+impl ChatProvider for OllamaClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            embeddings: false,
+        }
+    }
+
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+        Box::pin(OllamaClient::chat(self, input))
+    }
+
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+        Box::pin(async move {
+            let chunks = OllamaClient::chat_stream(self, input).await?;
+            Ok(Box::pin(chunks) as ChatStream<'_>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Message;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_chat_tolerates_missing_fields() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hi!"},
+                    "finish_reason": null,
+                }],
+            })))
+            .mount(&server)
+            .await;
+
+        let client = OllamaClient::with_base_url(&server.uri());
+        let response = client
+            .chat(
+                ChatInput::builder(Model::Other("llama3.2".to_string()))
+                    .message(Message::user("Hi"))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.choices[0].message.content, "Hi!");
+        assert_eq!(response.choices[0].finish_reason, "stop");
+        assert_eq!(response.usage.total_tokens, 0);
+    }
+
+    #[tokio::test]
+    async fn test_local_models_and_keep_alive() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/tags"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "models": [{
+                    "name": "llama3.2:latest",
+                    "model": "llama3.2:latest",
+                    "modified_at": "2024-10-01T12:00:00Z",
+                    "size": 2019393189u64,
+                    "digest": "a80c4f17acd5",
+                    "details": {"family": "llama"},
+                }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/generate"))
+            .and(body_json(
+                serde_json::json!({"model": "llama3.2", "keep_alive": 0}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2",
+                "done": true,
+                "done_reason": "unload",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OllamaClient::with_base_url(&server.uri());
+        let models = client.local_models().await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "llama3.2:latest");
+        assert_eq!(models[0].size, 2019393189);
+
+        client
+            .unload(&Model::Other("llama3.2".to_string()))
+            .await
+            .unwrap();
+    }
+}
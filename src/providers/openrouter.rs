@@ -0,0 +1,341 @@
+//! Support for [OpenRouter](https://openrouter.ai), which routes requests to many vendors.
+//!
+//! OpenRouter speaks the OpenAI chat completions protocol with a few extra request fields,
+//! collected in [`OpenRouterOptions`]:
+//!
+//! - `models`: models to try in order when the requested one is unavailable.
+//! - `provider`: which upstream providers may serve the request, see [`ProviderPreferences`].
+//! - `transforms`: prompt transforms such as `middle-out`, which compresses prompts that
+//!   exceed the context window.
+//!
+//! These fields are only sent by [`OpenRouterClient`], so they never reach APIs that would
+//! reject them. Its responses additionally report the provider that served the request and
+//! the finish reasons that provider returned, see [`OpenRouterResponse`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::providers::openrouter::{
+//!     OpenRouterClient, OpenRouterOptions, ProviderPreferences, ProviderSort,
+//! };
+//! use chat_gpt_lib_rs::{ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = OpenRouterClient::new("your_openrouter_key");
+//! let options = OpenRouterOptions {
+//!     models: vec![Model::Other("mistralai/mistral-large".to_string())],
+//!     provider: Some(ProviderPreferences {
+//!         sort: Some(ProviderSort::Throughput),
+//!         ..Default::default()
+//!     }),
+//!     ..Default::default()
+//! };
+//! let response = client
+//!     .chat(
+//!         ChatInput::builder(Model::Other("anthropic/claude-3.5-sonnet".to_string()))
+//!             .message(Message::user("Hello!"))
+//!             .build(),
+//!         &options,
+//!     )
+//!     .await?;
+//! println!("{:?}: {}", response.provider, response.response.choices[0].message.content);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{
+    ChatCompletion, ChatGPTClient, ChatGPTError, ChatInput, ChatResponse, RequestOptions,
+};
+use crate::models::Model;
+use crate::providers::{Capabilities, ChatProvider, ChatStream, ProviderFuture};
+use crate::stream::{chunk_stream, ChatChunk};
+use futures_util::Stream;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// The base URL of the OpenRouter API.
+pub const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api";
+
+/// OpenRouter's request fields that the OpenAI API does not have.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OpenRouterOptions {
+    /// Models to fall back to, in order, when the requested model is unavailable.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<Model>,
+    /// Preferences for the upstream providers serving the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderPreferences>,
+    /// Prompt transforms to apply, e.g. `middle-out`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<String>,
+}
+
+/// Which upstream providers may serve a request, and in which order they are tried.
+///
+/// Providers are named by their OpenRouter slug, e.g. `anthropic` or `together`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProviderPreferences {
+    /// Providers to try first, in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// The only providers that may serve the request.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub only: Vec<String>,
+    /// Providers that must not serve the request.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Whether providers outside `order` may be used when those in it fail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_fallbacks: Option<bool>,
+    /// Only use providers that support all parameters of the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_parameters: Option<bool>,
+    /// Whether providers that may store or train on the data may be used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_collection: Option<DataCollection>,
+    /// Orders the providers by this criterion instead of OpenRouter's load balancing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<ProviderSort>,
+}
+
+/// Whether providers that collect request data may serve a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataCollection {
+    Allow,
+    Deny,
+}
+
+/// The criterion providers are ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderSort {
+    Price,
+    Throughput,
+    Latency,
+}
+
+/// A chat response of OpenRouter, with the metadata it adds to the OpenAI format.
+#[derive(Debug, Clone)]
+pub struct OpenRouterResponse {
+    /// The response in the OpenAI format.
+    pub response: ChatResponse,
+    /// The upstream provider that served the request, e.g. `Anthropic`.
+    pub provider: Option<String>,
+    /// The finish reason of each choice as reported by the provider, before OpenRouter mapped
+    /// it onto the OpenAI finish reasons.
+    pub native_finish_reasons: Vec<Option<String>>,
+}
+
+impl<'de> Deserialize<'de> for OpenRouterResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let provider = value["provider"].as_str().map(str::to_string);
+        let native_finish_reasons = value["choices"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|choice| choice["native_finish_reason"].as_str().map(str::to_string))
+            .collect();
+        Ok(Self {
+            response: ChatResponse::deserialize(value).map_err(D::Error::custom)?,
+            provider,
+            native_finish_reasons,
+        })
+    }
+}
+
+impl ChatCompletion for OpenRouterResponse {
+    fn chat_response(&self) -> &ChatResponse {
+        &self.response
+    }
+
+    fn chat_response_mut(&mut self) -> &mut ChatResponse {
+        &mut self.response
+    }
+}
+
+/// A chat request with OpenRouter's fields.
+#[derive(Debug, Serialize)]
+struct OpenRouterRequest<'a> {
+    #[serde(flatten)]
+    input: &'a ChatInput,
+    #[serde(flatten)]
+    options: &'a OpenRouterOptions,
+}
+
+/// Client for the OpenRouter API.
+pub struct OpenRouterClient {
+    openai_compat: ChatGPTClient,
+}
+
+impl OpenRouterClient {
+    /// Creates a client for the OpenRouter API with the given API key.
+    pub fn new(api_key: &str) -> Self {
+        Self::with_base_url(api_key, OPENROUTER_BASE_URL)
+    }
+
+    /// Creates a client that sends its requests to `base_url`, e.g. a proxy.
+    pub fn with_base_url(api_key: &str, base_url: &str) -> Self {
+        Self::from_client(ChatGPTClient::new(api_key, base_url))
+    }
+
+    /// Wraps a client configured for OpenRouter, e.g. with the `HTTP-Referer` and `X-Title`
+    /// headers OpenRouter uses to attribute requests to an application.
+    pub fn from_client(client: ChatGPTClient) -> Self {
+        Self {
+            openai_compat: client,
+        }
+    }
+
+    /// The underlying client, for requests without OpenRouter's fields.
+    pub fn openai_compat(&self) -> &ChatGPTClient {
+        &self.openai_compat
+    }
+
+    /// Sends a chat request with OpenRouter's routing fields.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails or the response cannot be parsed.
+    pub async fn chat(
+        &self,
+        input: ChatInput,
+        options: &OpenRouterOptions,
+    ) -> Result<OpenRouterResponse, ChatGPTError> {
+        let body = OpenRouterRequest {
+            input: &input,
+            options,
+        };
+        self.openai_compat
+            .send_chat_body(&input, &body, &RequestOptions::default())
+            .await
+    }
+
+    /// Sends a chat request with OpenRouter's routing fields and streams the answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails.
+    pub async fn chat_stream(
+        &self,
+        mut input: ChatInput,
+        options: &OpenRouterOptions,
+    ) -> Result<impl Stream<Item = Result<ChatChunk, ChatGPTError>>, ChatGPTError> {
+        input.stream = Some(true);
+        let body = OpenRouterRequest {
+            input: &input,
+            options,
+        };
+        let response = self
+            .openai_compat
+            .send_chat_stream(&input, &body, &RequestOptions::default())
+            .await?;
+        Ok(chunk_stream(
+            response.bytes_stream(),
+            self.openai_compat.compat(),
+        ))
+    }
+}
+
+impl ChatProvider for OpenRouterClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            embeddings: false,
+        }
+    }
+
+    fn chat(&self, input: ChatInput) -> ProviderFuture<'_, Result<ChatResponse, ChatGPTError>> {
+        Box::pin(async move {
+            let response =
+                OpenRouterClient::chat(self, input, &OpenRouterOptions::default()).await?;
+            Ok(response.response)
+        })
+    }
+
+    fn chat_stream(
+        &self,
+        input: ChatInput,
+    ) -> ProviderFuture<'_, Result<ChatStream<'_>, ChatGPTError>> {
+        Box::pin(async move {
+            let chunks =
+                OpenRouterClient::chat_stream(self, input, &OpenRouterOptions::default()).await?;
+            Ok(Box::pin(chunks) as ChatStream<'_>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::mock_chat_completions;
+    use wiremock::matchers::body_partial_json;
+    use wiremock::{MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_chat_with_routing_fields() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .and(body_partial_json(serde_json::json!({
+                "model": "anthropic/claude-3.5-sonnet",
+                "models": ["openai/gpt-4o"],
+                "provider": {"order": ["anthropic"], "allow_fallbacks": false, "data_collection": "deny"},
+                "transforms": ["middle-out"],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "gen-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "anthropic/claude-3.5-sonnet",
+                "provider": "Anthropic",
+                "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7},
+                "choices": [{
+                    "message": {"role": "assistant", "content": "Hi!"},
+                    "finish_reason": "stop",
+                    "native_finish_reason": "end_turn",
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenRouterClient::with_base_url("sk-or", &server.uri());
+        let options = OpenRouterOptions {
+            models: vec![Model::Other("openai/gpt-4o".to_string())],
+            provider: Some(ProviderPreferences {
+                order: vec!["anthropic".to_string()],
+                allow_fallbacks: Some(false),
+                data_collection: Some(DataCollection::Deny),
+                ..Default::default()
+            }),
+            transforms: vec!["middle-out".to_string()],
+        };
+        let input = ChatInput {
+            model: Model::Other("anthropic/claude-3.5-sonnet".to_string()),
+            ..Default::default()
+        };
+        let response = client.chat(input, &options).await.unwrap();
+        assert_eq!(response.provider.as_deref(), Some("Anthropic"));
+        assert_eq!(
+            response.native_finish_reasons,
+            [Some("end_turn".to_string())]
+        );
+        assert_eq!(response.response.choices[0].message.content, "Hi!");
+        assert_eq!(response.response.usage.total_tokens, 7);
+    }
+
+    #[test]
+    fn test_empty_options_add_no_fields() {
+        let input = ChatInput::default();
+        let body = OpenRouterRequest {
+            input: &input,
+            options: &OpenRouterOptions::default(),
+        };
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            serde_json::to_value(&input).unwrap()
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Retrieval-augmented generation.
+//!
+//! [`Rag`] answers chat requests from a collection of documents: it embeds the last user
+//! message, searches a [`VectorSearch`] index for the most similar documents, and adds them to
+//! the prompt as a numbered list, asking the model to cite them with markers such as `[1]`.
+//! The retrieved documents are returned in citation order, so the markers in the answer can be
+//! resolved.
+//!
+//! [`VectorIndex`] is a simple in-memory index to retrieve from, which can be persisted with
+//! serde. It searches by brute force, which suits small collections; larger ones call for a
+//! vector database behind [`VectorSearch`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::rag::{Rag, VectorSearch};
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//!
+//! # async fn run(index: impl VectorSearch) -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let rag = Rag::new(index).top_k(3);
+//! let mut input = ChatInput::builder(Model::Gpt_4o)
+//!     .message(Message::user("When do the stores open?"))
+//!     .build();
+//! let sources = rag.augment(&client, &mut input).await?;
+//! let response = client.chat(input).await?;
+//! println!("{}", response.choices[0].message.content);
+//! for (number, source) in sources.iter().enumerate() {
+//!     println!("[{}] {}", number + 1, source.document.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{ChatGPTClient, ChatGPTError, ChatInput, Message};
+use crate::embeddings::{nearest_neighbors, EmbeddingsInput};
+use crate::models::{EmbeddingModel, Role};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The instructions preceding the retrieved documents in the prompt.
+const CONTEXT_INSTRUCTIONS: &str = "Answer using the following documents. Cite the documents \
+    you use by their number in square brackets, e.g. [1].";
+
+/// A text that can be retrieved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    /// Identifies the document to the application, e.g. a URL or a database key.
+    pub id: String,
+    pub text: String,
+    /// Attributes to filter searches by, e.g. a language or a category.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+}
+
+impl Document {
+    /// A document with the given ID and text.
+    pub fn new(id: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            text: text.into(),
+            metadata: Map::new(),
+        }
+    }
+
+    /// Sets the metadata attribute `key` to `value`.
+    pub fn with_metadata(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// A document found by a [`VectorSearch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub document: Document,
+    /// The similarity of the document to the query, higher is more similar.
+    pub score: f32,
+}
+
+/// An index of document embeddings that can be searched for the nearest neighbours of a query.
+pub trait VectorSearch: Send + Sync {
+    /// Returns up to `k` documents most similar to `embedding`, most similar first.
+    fn search(&self, embedding: &[f32], k: usize) -> Vec<SearchResult>;
+}
+
+/// A document with its embedding in a [`VectorIndex`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IndexEntry {
+    document: Document,
+    embedding: Vec<f32>,
+}
+
+/// An in-memory index of document embeddings, searched by cosine similarity.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::rag::{Document, VectorIndex};
+///
+/// let mut index = VectorIndex::new();
+/// let hours = Document::new("hours", "Stores open at 9am.").with_metadata("lang", "en");
+/// index.insert(hours, vec![1.0, 0.0]);
+/// let horaires = Document::new("horaires", "Ouverture à 9h.").with_metadata("lang", "fr");
+/// index.insert(horaires, vec![0.9, 0.1]);
+///
+/// let results = index.search_where(&[1.0, 0.0], 1, |document| document.metadata["lang"] == "fr");
+/// assert_eq!(results[0].document.id, "horaires");
+///
+/// let json = serde_json::to_string(&index).unwrap();
+/// let index: VectorIndex = serde_json::from_str(&json).unwrap();
+/// assert_eq!(index.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VectorIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl VectorIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of documents in the index.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the index has no documents.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds `document` with its `embedding`, replacing a document with the same ID.
+    pub fn insert(&mut self, document: Document, embedding: Vec<f32>) {
+        let entry = IndexEntry {
+            document,
+            embedding,
+        };
+        match self.position(&entry.document.id) {
+            Some(position) => self.entries[position] = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Removes the document with the given ID and returns it.
+    pub fn delete(&mut self, id: &str) -> Option<Document> {
+        let position = self.position(id)?;
+        Some(self.entries.remove(position).document)
+    }
+
+    /// The document with the given ID.
+    pub fn get(&self, id: &str) -> Option<&Document> {
+        self.position(id)
+            .map(|position| &self.entries[position].document)
+    }
+
+    /// Returns up to `k` of the documents accepted by `filter` that are most similar to
+    /// `embedding`, most similar first.
+    pub fn search_where(
+        &self,
+        embedding: &[f32],
+        k: usize,
+        filter: impl Fn(&Document) -> bool,
+    ) -> Vec<SearchResult> {
+        let candidates: Vec<&IndexEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| filter(&entry.document))
+            .collect();
+        let embeddings: Vec<&[f32]> = candidates
+            .iter()
+            .map(|entry| entry.embedding.as_slice())
+            .collect();
+        nearest_neighbors(embedding, &embeddings, k)
+            .into_iter()
+            .map(|(position, score)| SearchResult {
+                document: candidates[position].document.clone(),
+                score,
+            })
+            .collect()
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.document.id == id)
+    }
+}
+
+impl VectorSearch for VectorIndex {
+    fn search(&self, embedding: &[f32], k: usize) -> Vec<SearchResult> {
+        self.search_where(embedding, k, |_| true)
+    }
+}
+
+/// Default number of documents added to the prompt.
+const DEFAULT_TOP_K: usize = 4;
+
+/// Adds documents retrieved from an index to chat requests.
+#[derive(Debug, Clone)]
+pub struct Rag<I> {
+    index: I,
+    embedding_model: EmbeddingModel,
+    top_k: usize,
+}
+
+impl<I: VectorSearch> Rag<I> {
+    /// Retrieves from `index`, whose documents were embedded with
+    /// `text-embedding-3-small` unless set otherwise with [`Rag::embedding_model`].
+    pub fn new(index: I) -> Self {
+        Self {
+            index,
+            embedding_model: EmbeddingModel::TextEmbedding3Small,
+            top_k: DEFAULT_TOP_K,
+        }
+    }
+
+    /// Sets the model queries are embedded with, which has to be the one the documents of the
+    /// index were embedded with.
+    pub fn embedding_model(mut self, model: EmbeddingModel) -> Self {
+        self.embedding_model = model;
+        self
+    }
+
+    /// Sets how many documents are added to the prompt, 4 by default.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// The index documents are retrieved from.
+    pub fn index(&self) -> &I {
+        &self.index
+    }
+
+    /// Returns the documents most similar to `query`, most similar first.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if embedding the query fails.
+    pub async fn retrieve(
+        &self,
+        client: &ChatGPTClient,
+        query: &str,
+    ) -> Result<Vec<SearchResult>, ChatGPTError> {
+        let response = client
+            .embeddings(EmbeddingsInput {
+                model: self.embedding_model,
+                input: vec![query.to_string()],
+                ..Default::default()
+            })
+            .await?;
+        let embedding = response
+            .data
+            .into_iter()
+            .next()
+            .map(|embedding| embedding.embedding)
+            .unwrap_or_default();
+        Ok(self.index.search(&embedding, self.top_k))
+    }
+
+    /// Retrieves the documents most similar to the last user message of `input` and adds
+    /// them to its prompt, in a `system` message after the leading instruction messages.
+    ///
+    /// Returns the retrieved documents; the one cited as `[n]` is at index `n - 1`. Without a
+    /// user message, or when nothing is found, `input` is unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if embedding the query fails.
+    pub async fn augment(
+        &self,
+        client: &ChatGPTClient,
+        input: &mut ChatInput,
+    ) -> Result<Vec<SearchResult>, ChatGPTError> {
+        let Some(query) = input
+            .messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.text().into_owned())
+        else {
+            return Ok(Vec::new());
+        };
+        let results = self.retrieve(client, &query).await?;
+        if !results.is_empty() {
+            let position = input
+                .messages
+                .iter()
+                .position(|message| !message.role.is_instructions())
+                .unwrap_or(input.messages.len());
+            input
+                .messages
+                .insert(position, Message::system(context_prompt(&results)));
+        }
+        Ok(results)
+    }
+}
+
+/// The prompt listing the retrieved documents with their citation markers.
+fn context_prompt(results: &[SearchResult]) -> String {
+    let mut prompt = CONTEXT_INSTRUCTIONS.to_string();
+    for (number, result) in results.iter().enumerate() {
+        prompt.push_str(&format!("\n\n[{}] {}", number + 1, result.document.text));
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{embeddings, mock_embeddings};
+    use wiremock::MockServer;
+
+    #[test]
+    fn test_vector_index() {
+        let mut index = VectorIndex::new();
+        index.insert(
+            Document::new("a", "A").with_metadata("tag", "x"),
+            vec![1.0, 0.0],
+        );
+        index.insert(
+            Document::new("b", "B").with_metadata("tag", "y"),
+            vec![0.0, 1.0],
+        );
+        index.insert(
+            Document::new("c", "C").with_metadata("tag", "x"),
+            vec![0.6, 0.8],
+        );
+        index.insert(
+            Document::new("a", "A2").with_metadata("tag", "x"),
+            vec![0.8, 0.6],
+        );
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("a").unwrap().text, "A2");
+
+        let ids = |results: Vec<SearchResult>| -> Vec<String> {
+            results
+                .into_iter()
+                .map(|result| result.document.id)
+                .collect()
+        };
+        assert_eq!(ids(index.search(&[0.0, 1.0], 2)), ["b", "c"]);
+        let tagged_x = |document: &Document| document.metadata["tag"] == "x";
+        assert_eq!(
+            ids(index.search_where(&[0.0, 1.0], 5, tagged_x)),
+            ["c", "a"]
+        );
+
+        assert_eq!(index.delete("b").unwrap().text, "B");
+        assert!(index.delete("b").is_none());
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(serde_json::from_str::<VectorIndex>(&json).unwrap(), index);
+    }
+
+    #[tokio::test]
+    async fn test_augment() {
+        let server = MockServer::start().await;
+        mock_embeddings()
+            .respond_with(embeddings(&[&[1.0, 0.1]]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = ChatGPTClient::new("dummy_api_key", &server.uri());
+        let mut index = VectorIndex::new();
+        index.insert(Document::new("weather", "It rains a lot."), vec![0.0, 1.0]);
+        index.insert(
+            Document::new("hours", "Stores open at 9am."),
+            vec![1.0, 0.0],
+        );
+        index.insert(
+            Document::new("staff", "We have 12 employees."),
+            vec![0.7, 0.7],
+        );
+        let rag = Rag::new(index).top_k(2);
+
+        let mut input = ChatInput {
+            messages: vec![
+                Message::system("Be brief."),
+                Message::user("When do you open?"),
+            ],
+            ..Default::default()
+        };
+        let results = rag.augment(&client, &mut input).await.unwrap();
+        let ids: Vec<&str> = results
+            .iter()
+            .map(|result| result.document.id.as_str())
+            .collect();
+        assert_eq!(ids, ["hours", "staff"]);
+        assert_eq!(input.messages.len(), 3);
+        assert_eq!(input.messages[1].role, Role::System);
+        assert!(input.messages[1]
+            .content
+            .text()
+            .ends_with("[1] Stores open at 9am.\n\n[2] We have 12 employees."));
+    }
+}
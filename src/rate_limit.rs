@@ -0,0 +1,366 @@
+//! Client-side rate limiting.
+//!
+//! A [`RateLimit`] spaces out requests so they stay within the requests and tokens per minute
+//! of the account, instead of running into `429 Too Many Requests` responses. Each limit is a
+//! token bucket holding a minute's worth of capacity that refills continuously. A request
+//! takes one request and its estimated tokens from the buckets: the prompt tokens plus
+//! `max_tokens` for chat requests, the input tokens for embeddings. When the buckets run
+//! short, the request waits until they have refilled; requests waiting at the same time are
+//! sent in turn.
+//!
+//! Limits left unset can be learned from the `x-ratelimit-limit-requests` and
+//! `x-ratelimit-limit-tokens` headers OpenAI sends with every response, whose
+//! `x-ratelimit-remaining-*` counterparts also keep the buckets in step with the server,
+//! e.g. when other processes share the account.
+//!
+//! Requests that are retried after a `429 Too Many Requests` or `503 Service Unavailable`
+//! response wait as long as its `Retry-After` header, or the `x-ratelimit-reset-*` headers of
+//! the exhausted limit, say.
+
+use crate::client::{ChatGPTError, ChatInput};
+use crate::tokenizer::count_message_tokens;
+use reqwest::header::HeaderMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use web_time::Instant;
+
+/// Configuration of a client-side rate limiter.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::rate_limit::RateLimit;
+/// use chat_gpt_lib_rs::ChatGPTClient;
+///
+/// let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+///     .rate_limit(RateLimit {
+///         requests_per_minute: Some(500),
+///         tokens_per_minute: Some(200_000),
+///         ..Default::default()
+///     })
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of requests per minute, or `None` to leave them unlimited unless learned.
+    pub requests_per_minute: Option<u32>,
+    /// Number of tokens per minute, or `None` to leave them unlimited unless learned.
+    pub tokens_per_minute: Option<u32>,
+    /// Whether the limits left unset are taken from the rate limit headers of the responses,
+    /// and the buckets are kept in step with the remaining counts of the server.
+    pub learn_from_headers: bool,
+    /// Whether requests over the limit fail with `ChatGPTError::RateLimited` instead of
+    /// waiting. Always the case on `wasm32`, where there is no timer to wait with.
+    pub fail_fast: bool,
+}
+
+impl Default for RateLimit {
+    /// No limits of its own, learning them from the responses.
+    fn default() -> Self {
+        Self {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            learn_from_headers: true,
+            fail_fast: false,
+        }
+    }
+}
+
+/// How long to wait before retrying, as told by the `Retry-After` header, in seconds or as
+/// an HTTP date, or else by the `x-ratelimit-reset-requests` and `x-ratelimit-reset-tokens`
+/// headers of the exhausted limits.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| Some(headers.get(name)?.to_str().ok()?.trim());
+    if let Some(retry_after) = header("retry-after") {
+        return match retry_after.parse::<f64>() {
+            Ok(seconds) => Duration::try_from_secs_f64(seconds).ok(),
+            Err(_) => {
+                let date = httpdate::parse_http_date(retry_after).ok()?;
+                let since_epoch = date.duration_since(std::time::UNIX_EPOCH).ok()?;
+                let date = web_time::SystemTime::UNIX_EPOCH + since_epoch;
+                // A date in the past allows retrying right away.
+                Some(
+                    date.duration_since(web_time::SystemTime::now())
+                        .unwrap_or_default(),
+                )
+            }
+        };
+    }
+    let resets = [
+        (
+            "x-ratelimit-remaining-requests",
+            "x-ratelimit-reset-requests",
+        ),
+        ("x-ratelimit-remaining-tokens", "x-ratelimit-reset-tokens"),
+    ];
+    let reset = |(_, reset): &(&str, &str)| header(reset).and_then(parse_reset);
+    let exhausted = resets
+        .iter()
+        .filter(|(remaining, _)| header(remaining) == Some("0"))
+        .filter_map(reset)
+        .max();
+    exhausted.or_else(|| resets.iter().filter_map(reset).max())
+}
+
+/// Parses the durations of the `x-ratelimit-reset-*` headers, like `1s`, `6m0s`, `20ms` or
+/// `1h2m3.5s`.
+fn parse_reset(reset: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = reset;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, unit_and_rest) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = unit_and_rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(unit_and_rest.len());
+        let (unit, next) = unit_and_rest.split_at(unit_len);
+        total += number
+            * match unit {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = next;
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// The estimated tokens of a chat request: the prompt tokens plus `max_tokens`.
+pub(crate) fn chat_tokens(input: &ChatInput) -> usize {
+    count_message_tokens(&input.messages) + input.max_tokens.unwrap_or(0)
+}
+
+/// Tracks the requests and tokens taken against a [`RateLimit`].
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Bucket,
+    tokens: Bucket,
+}
+
+/// A token bucket refilling its `limit` once a minute.
+#[derive(Debug)]
+struct Bucket {
+    limit: Option<f64>,
+    /// The capacity left, negative while waiting requests have taken more than there is.
+    level: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: Option<u32>, now: Instant) -> Self {
+        let limit = limit.map(f64::from);
+        Self {
+            limit,
+            level: limit.unwrap_or(0.0),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(limit) = self.limit {
+            let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+            self.level = (self.level + elapsed * limit / 60.0).min(limit);
+        }
+        self.updated = now;
+    }
+
+    /// How long until `cost` can be taken; a cost above the limit only waits for a full
+    /// bucket.
+    fn wait(&self, cost: f64) -> Duration {
+        let Some(limit) = self.limit.filter(|limit| *limit > 0.0) else {
+            return Duration::ZERO;
+        };
+        let deficit = cost.min(limit) - self.level;
+        if deficit <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(deficit * 60.0 / limit)
+        }
+    }
+
+    fn take(&mut self, cost: f64) {
+        if let Some(limit) = self.limit {
+            self.level -= cost.min(limit);
+        }
+    }
+
+    /// Adopts the `limit` sent by the server unless one was configured, and lowers the
+    /// level to the `remaining` capacity.
+    fn learn(&mut self, configured: bool, limit: Option<f64>, remaining: Option<f64>) {
+        if let (false, Some(limit)) = (configured, limit) {
+            if self.limit.is_none() {
+                self.level = limit;
+            }
+            self.limit = Some(limit);
+        }
+        if let (Some(_), Some(remaining)) = (self.limit, remaining) {
+            self.level = self.level.min(remaining);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            buckets: Mutex::new(Buckets {
+                requests: Bucket::new(config.requests_per_minute, now),
+                tokens: Bucket::new(config.tokens_per_minute, now),
+            }),
+        }
+    }
+
+    /// Takes a request of `tokens` from the buckets and returns how long to wait before
+    /// sending it, or fails with `ChatGPTError::RateLimited` if it may not wait.
+    pub(crate) fn reserve(&self, tokens: usize) -> Result<Duration, ChatGPTError> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        buckets.requests.refill(now);
+        buckets.tokens.refill(now);
+        let tokens = tokens as f64;
+        let wait = buckets.requests.wait(1.0).max(buckets.tokens.wait(tokens));
+        if !wait.is_zero() && (self.config.fail_fast || cfg!(target_arch = "wasm32")) {
+            return Err(ChatGPTError::RateLimited { retry_in: wait });
+        }
+        buckets.requests.take(1.0);
+        buckets.tokens.take(tokens);
+        Ok(wait)
+    }
+
+    /// Learns from the rate limit headers of a response, if enabled.
+    pub(crate) fn record(&self, headers: &HeaderMap) {
+        if !self.config.learn_from_headers {
+            return;
+        }
+        let header = |name: &str| -> Option<f64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.requests.learn(
+            self.config.requests_per_minute.is_some(),
+            header("x-ratelimit-limit-requests"),
+            header("x-ratelimit-remaining-requests"),
+        );
+        buckets.tokens.learn(
+            self.config.tokens_per_minute.is_some(),
+            header("x-ratelimit-limit-tokens"),
+            header("x-ratelimit-remaining-tokens"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_spaces_out_requests() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: Some(2),
+            tokens_per_minute: Some(1000),
+            ..Default::default()
+        });
+        assert_eq!(limiter.reserve(100).unwrap(), Duration::ZERO);
+        assert_eq!(limiter.reserve(100).unwrap(), Duration::ZERO);
+        // The third request waits half a minute for a request to refill, the fourth one
+        // behind it a whole minute.
+        let wait = limiter.reserve(100).unwrap();
+        assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+        let wait = limiter.reserve(100).unwrap();
+        assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+
+        let limiter = RateLimiter::new(RateLimit {
+            tokens_per_minute: Some(1000),
+            fail_fast: true,
+            ..Default::default()
+        });
+        assert_eq!(limiter.reserve(5000).unwrap(), Duration::ZERO);
+        assert!(matches!(
+            limiter.reserve(500),
+            Err(ChatGPTError::RateLimited { retry_in }) if retry_in > Duration::from_secs(29)
+        ));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "1.5")])),
+            Some(Duration::from_millis(1500))
+        );
+        let in_a_minute =
+            httpdate::fmt_http_date(std::time::SystemTime::now() + Duration::from_secs(60));
+        let wait = retry_after(&headers(&[("retry-after", &in_a_minute)])).unwrap();
+        assert!(wait > Duration::from_secs(58) && wait <= Duration::from_secs(60));
+        assert_eq!(
+            retry_after(&headers(&[(
+                "retry-after",
+                "Wed, 21 Oct 2015 07:28:00 GMT"
+            )])),
+            Some(Duration::ZERO)
+        );
+
+        // The reset of the exhausted limit counts, otherwise the later one.
+        let resets = [
+            ("x-ratelimit-reset-requests", "120ms"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ];
+        assert_eq!(
+            retry_after(&headers(&resets)),
+            Some(Duration::from_secs(360))
+        );
+        let exhausted = [
+            resets[0],
+            resets[1],
+            ("x-ratelimit-remaining-requests", "0"),
+        ];
+        assert_eq!(
+            retry_after(&headers(&exhausted)),
+            Some(Duration::from_millis(120))
+        );
+        assert_eq!(
+            parse_reset("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(parse_reset("3 days"), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_rate_limiter_learns_from_headers() {
+        let limiter = RateLimiter::new(RateLimit::default());
+        assert_eq!(limiter.reserve(1_000_000).unwrap(), Duration::ZERO);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit-requests", "60".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-requests", "0".parse().unwrap());
+        headers.insert("x-ratelimit-limit-tokens", "150000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-tokens", "149000".parse().unwrap());
+        limiter.record(&headers);
+        let wait = limiter.reserve(10).unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: Some(1),
+            learn_from_headers: false,
+            ..Default::default()
+        });
+        limiter.record(&headers);
+        assert_eq!(limiter.reserve(10).unwrap(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,224 @@
+//! Redaction of sensitive data from outgoing messages.
+//!
+//! A [`Redactor`] registered with [`ChatGPTClientBuilder::redactor`] rewrites the text of every
+//! message of a chat request before anything else sees it, so sensitive data is scrubbed
+//! before it leaves the process, including towards the moderations and embeddings calls the
+//! client makes on its own. [`PatternRedactor`] replaces matches of regular expressions, by
+//! default email addresses, phone numbers and credit card numbers.
+//!
+//! # Examples
+//!
+//! ```
+//! use chat_gpt_lib_rs::redact::{PatternRedactor, Redactor};
+//!
+//! let redactor = PatternRedactor::default();
+//! assert_eq!(
+//!     redactor.redact("Mail jane@example.com or call +1 555-123-4567."),
+//!     "Mail [EMAIL] or call [PHONE]."
+//! );
+//! ```
+//!
+//! [`ChatGPTClientBuilder::redactor`]: crate::ChatGPTClientBuilder::redactor
+
+use crate::client::Message;
+use crate::content::{Content, ContentPart};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+/// Rewrites message text before it is sent.
+pub trait Redactor: Send + Sync {
+    /// The text with sensitive data replaced.
+    fn redact<'a>(&self, text: &'a str) -> Cow<'a, str>;
+}
+
+/// Applies `redactor` to the text and text parts of `messages`.
+pub(crate) fn redact_messages(redactor: &dyn Redactor, messages: &mut [Message]) {
+    let redact = |text: &mut String| {
+        if let Cow::Owned(redacted) = redactor.redact(text) {
+            *text = redacted;
+        }
+    };
+    for message in messages {
+        match &mut message.content {
+            Content::Text(text) => redact(text),
+            Content::Parts(parts) => {
+                for part in parts {
+                    if let ContentPart::Text { text } = part {
+                        redact(text);
+                    }
+                }
+            }
+            Content::None => {}
+        }
+    }
+}
+
+/// Matches email addresses.
+const EMAIL_PATTERN: &str = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b";
+
+/// Matches runs of 13 to 19 digits, optionally grouped by spaces or dashes.
+const CARD_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+/// Matches phone numbers of 10 or more digits, like `+1 (555) 123-4567` or `0301 2345678`.
+const PHONE_PATTERN: &str =
+    r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[ .-]?\d{3,4}[ .-]?\d{3,4}\b";
+
+/// A [`Redactor`] replacing matches of regular expressions.
+///
+/// The patterns are applied in the order they were added. The default redactor replaces
+/// email addresses with `[EMAIL]`, credit card numbers passing the Luhn check with
+/// `[CREDIT_CARD]` and phone numbers with `[PHONE]`.
+#[derive(Debug, Clone)]
+pub struct PatternRedactor {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Regex,
+    replacement: String,
+    /// Whether only digit runs passing the Luhn check are replaced.
+    luhn: bool,
+}
+
+impl Default for PatternRedactor {
+    fn default() -> Self {
+        Self::new().emails().credit_cards().phone_numbers()
+    }
+}
+
+impl PatternRedactor {
+    /// A redactor without patterns.
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Replaces email addresses with `[EMAIL]`.
+    pub fn emails(self) -> Self {
+        self.push(EMAIL_PATTERN, "[EMAIL]", false)
+    }
+
+    /// Replaces credit card numbers with `[CREDIT_CARD]`.
+    ///
+    /// Only digit runs passing the Luhn checksum are replaced, which spares most other long
+    /// numbers.
+    pub fn credit_cards(self) -> Self {
+        self.push(CARD_PATTERN, "[CREDIT_CARD]", true)
+    }
+
+    /// Replaces phone numbers with `[PHONE]`.
+    pub fn phone_numbers(self) -> Self {
+        self.push(PHONE_PATTERN, "[PHONE]", false)
+    }
+
+    /// Replaces matches of `pattern`, in the syntax of the `regex` crate, with `replacement`,
+    /// which may refer to capture groups as in [`Regex::replace_all`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regular expression.
+    pub fn pattern(mut self, pattern: &str, replacement: &str) -> Result<Self, regex::Error> {
+        self.rules.push(Rule {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+            luhn: false,
+        });
+        Ok(self)
+    }
+
+    fn push(mut self, pattern: &str, replacement: &str, luhn: bool) -> Self {
+        self.rules.push(Rule {
+            pattern: Regex::new(pattern).expect("valid pattern"),
+            replacement: replacement.to_string(),
+            luhn,
+        });
+        self
+    }
+}
+
+impl Redactor for PatternRedactor {
+    fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            let redacted = if rule.luhn {
+                rule.pattern.replace_all(&text, |captures: &Captures| {
+                    if passes_luhn(&captures[0]) {
+                        rule.replacement.clone()
+                    } else {
+                        captures[0].to_string()
+                    }
+                })
+            } else {
+                rule.pattern.replace_all(&text, rule.replacement.as_str())
+            };
+            if let Cow::Owned(redacted) = redacted {
+                text = Cow::Owned(redacted);
+            }
+        }
+        text
+    }
+}
+
+/// Whether the digits of `number` pass the Luhn checksum of card numbers.
+fn passes_luhn(number: &str) -> bool {
+    let sum: u32 = number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| match (index % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_redactor() {
+        let redactor = PatternRedactor::default();
+        assert_eq!(
+            redactor.redact("Card 4111 1111 1111 1111, order 1234567890123."),
+            "Card [CREDIT_CARD], order 1234567890123."
+        );
+        assert_eq!(
+            redactor.redact("Call (555) 123-4567 or 0301 2345678 at 10:30."),
+            "Call [PHONE] or [PHONE] at 10:30."
+        );
+        assert!(matches!(
+            redactor.redact("Nothing to hide in 2024."),
+            Cow::Borrowed(_)
+        ));
+
+        let redactor = PatternRedactor::new()
+            .pattern(r"\b(\d{3})-\d{2}-\d{4}\b", "$1-XX-XXXX")
+            .unwrap();
+        assert_eq!(redactor.redact("SSN 123-45-6789"), "SSN 123-XX-XXXX");
+    }
+
+    #[test]
+    fn test_redact_messages() {
+        let mut messages = vec![
+            Message::user(Content::Parts(vec![
+                ContentPart::text("I am bob@example.org"),
+                ContentPart::image_url("https://example.org/bob@example.org.png"),
+            ])),
+            Message::assistant("Hi bob@example.org"),
+        ];
+        redact_messages(&PatternRedactor::default(), &mut messages);
+        assert_eq!(messages[0].content.text(), "I am [EMAIL]");
+        assert_eq!(messages[1].content.text(), "Hi [EMAIL]");
+        match &messages[0].content {
+            Content::Parts(parts) => assert_eq!(
+                parts[1],
+                ContentPart::image_url("https://example.org/bob@example.org.png")
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! The models an endpoint serves, with what this crate knows about them.
+//!
+//! A [`ModelRegistry`] lists the models of `GET /v1/models` with
+//! [`ChatGPTClient::list_models`], pairs each with the static metadata of its
+//! [`Model`] family, such as the context window and deprecation, and caches the list for a
+//! time to live. Names sent as [`Model::Other`] can be checked against it before they fail a
+//! request with `model_not_found`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::registry::ModelRegistry;
+//! use chat_gpt_lib_rs::{ChatGPTClient, Model};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let registry = ModelRegistry::new().ttl(Duration::from_secs(600));
+//! registry
+//!     .validate(&client, &Model::Other("gpt-4o-2024-08-06".to_string()))
+//!     .await?;
+//! for model in registry.models(&client).await?.iter() {
+//!     let context_window = model.family.as_ref().map(Model::context_window);
+//!     println!("{} ({context_window:?} tokens)", model.info.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{ChatGPTClient, ChatGPTError};
+use crate::models::Model;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use web_time::Instant;
+
+/// How long a [`ModelRegistry`] keeps the model list, unless set otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Represents the response from the models API call.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct ModelList {
+    #[serde(default)]
+    pub object: String,
+    pub data: Vec<ModelInfo>,
+    /// Fields of the response this crate does not model.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// A model the endpoint serves.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct ModelInfo {
+    /// The name requests use, e.g. `gpt-4o-2024-08-06`.
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    /// When the model was created, as a Unix timestamp in seconds.
+    #[serde(default)]
+    pub created: i64,
+    /// The organization owning the model, e.g. `openai` or `system`.
+    #[serde(default)]
+    pub owned_by: String,
+    /// Fields of the model this crate does not model.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// A served model, paired with the known model whose metadata applies to it.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RegisteredModel {
+    /// The model as listed by the endpoint.
+    pub info: ModelInfo,
+    /// The model to request it with, by its exact name.
+    pub model: Model,
+    /// The known model `info.id` names or is a snapshot of, e.g. [`Model::Gpt_4o`] for
+    /// `gpt-4o-2024-08-06`, for its context window, pricing and deprecation. `None` for models
+    /// this crate does not know.
+    pub family: Option<Model>,
+}
+
+impl From<ModelInfo> for RegisteredModel {
+    fn from(info: ModelInfo) -> Self {
+        Self {
+            model: Model::named(&info.id).unwrap_or_else(|| Model::Other(info.id.clone())),
+            family: Model::family_of(&info.id),
+            info,
+        }
+    }
+}
+
+/// The models list of an endpoint, fetched when first needed and again once it is older
+/// than the time to live.
+///
+/// The registry does not hold a client, so one registry can serve several clients of the
+/// same endpoint.
+#[derive(Debug)]
+pub struct ModelRegistry {
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Arc<[RegisteredModel]>)>>,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelRegistry {
+    /// A registry that keeps the list for [`DEFAULT_TTL`].
+    pub fn new() -> Self {
+        Self {
+            ttl: DEFAULT_TTL,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Sets how long the list is kept before it is fetched again.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the served models, fetched with `client` if the list is missing or expired.
+    ///
+    /// Concurrent calls wait for a single fetch.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the list has to be fetched and the request fails.
+    pub async fn models(
+        &self,
+        client: &ChatGPTClient,
+    ) -> Result<Arc<[RegisteredModel]>, ChatGPTError> {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched, models)) = &*cached {
+            if fetched.elapsed() < self.ttl {
+                return Ok(models.clone());
+            }
+        }
+        let models = fetch(client).await?;
+        *cached = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Fetches the list again, regardless of its age.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the request fails; the previous list is kept then.
+    pub async fn refresh(
+        &self,
+        client: &ChatGPTClient,
+    ) -> Result<Arc<[RegisteredModel]>, ChatGPTError> {
+        let mut cached = self.cached.lock().await;
+        let models = fetch(client).await?;
+        *cached = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Returns the served model named `name`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a ChatGPTError if the list has to be fetched and the request fails.
+    pub async fn get(
+        &self,
+        client: &ChatGPTClient,
+        name: &str,
+    ) -> Result<Option<RegisteredModel>, ChatGPTError> {
+        let models = self.models(client).await?;
+        Ok(models.iter().find(|model| model.info.id == name).cloned())
+    }
+
+    /// Checks that the endpoint serves `model`, by its exact name.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatGPTError::ModelNotFound` if it does not, or a ChatGPTError if the list has
+    /// to be fetched and the request fails.
+    pub async fn validate(
+        &self,
+        client: &ChatGPTClient,
+        model: &Model,
+    ) -> Result<(), ChatGPTError> {
+        let name = model.to_string();
+        match self.get(client, &name).await? {
+            Some(_) => Ok(()),
+            None => Err(ChatGPTError::ModelNotFound {
+                message: format!("The model `{name}` is not listed by the endpoint"),
+                request_id: None,
+            }),
+        }
+    }
+}
+
+async fn fetch(client: &ChatGPTClient) -> Result<Arc<[RegisteredModel]>, ChatGPTError> {
+    let list = client.list_models().await?;
+    Ok(list.data.into_iter().map(RegisteredModel::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_registry_caches_and_validates() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    {"id": "gpt-4o-2024-08-06", "object": "model", "created": 1722814719, "owned_by": "system"},
+                    {"id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system"},
+                    {"id": "ft:gpt-4o-mini:acme::abc123", "object": "model", "created": 1, "owned_by": "acme"},
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = ChatGPTClient::new("dummy_api_key", &server.uri());
+        let registry = ModelRegistry::new();
+        let models = registry.models(&client).await.unwrap();
+        assert_eq!(models.len(), 3);
+        assert_eq!(
+            models[0].model,
+            Model::Other("gpt-4o-2024-08-06".to_string())
+        );
+        assert_eq!(models[0].family, Some(Model::Gpt_4o));
+        assert_eq!(models[1].model, Model::Gpt_4o);
+        assert_eq!(models[2].family, None);
+
+        registry
+            .validate(
+                &client,
+                &Model::Other("ft:gpt-4o-mini:acme::abc123".to_string()),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            registry.validate(&client, &Model::Gpt_4oMini).await,
+            Err(ChatGPTError::ModelNotFound { .. })
+        ));
+    }
+}
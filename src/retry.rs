@@ -0,0 +1,338 @@
+//! Which failed requests are retried, and when.
+//!
+//! A [`RetryPolicy`] classifies the errors of the requests the client retries, all of them
+//! with [`ChatGPTClientBuilder::max_retries`](crate::ChatGPTClientBuilder::max_retries) set,
+//! and those of [`ChatGPTClient::chat_many`](crate::ChatGPTClient::chat_many) in any case,
+//! and may say how long to wait before the retry. Only the errors of getting a response
+//! through reach it: transport failures, error statuses and the checks before sending. A
+//! reply that arrives but fails to read or parse, `ChatGPTError::Json` say, is returned
+//! without a retry.
+//!
+//! [`DefaultRetryPolicy`] follows OpenAI's guidance; custom policies can extend it, e.g. to
+//! also retry the `502 Bad Gateway` replies a proxy sends with `x-should-retry: false`:
+//!
+//! ```
+//! use chat_gpt_lib_rs::client::ChatGPTError;
+//! use chat_gpt_lib_rs::retry::{DefaultRetryPolicy, RetryPolicy};
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//! use reqwest::StatusCode;
+//!
+//! struct RetryBadGateway;
+//!
+//! impl RetryPolicy for RetryBadGateway {
+//!     fn is_retryable(&self, err: &ChatGPTError) -> bool {
+//!         DefaultRetryPolicy.is_retryable(err)
+//!             || matches!(
+//!                 err,
+//!                 ChatGPTError::RequestFailed { status_code, .. }
+//!                     if *status_code == StatusCode::BAD_GATEWAY
+//!             )
+//!     }
+//! }
+//!
+//! let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+//!     .max_retries(2)
+//!     .retry_policy(RetryBadGateway)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! When the policy does not tell how long to wait before a retry, a [`Backoff`] does: an
+//! [`ExponentialBackoff`], optionally with full jitter, a [`DecorrelatedJitter`] or a
+//! [`FixedBackoff`], set with
+//! [`ChatGPTClientBuilder::backoff`](crate::ChatGPTClientBuilder::backoff).
+
+use crate::client::ChatGPTError;
+use crate::rate_limit::retry_after;
+use reqwest::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use web_time::Instant;
+
+/// Decides which failed requests are retried.
+pub trait RetryPolicy: Send + Sync {
+    /// Whether a request that failed with `err` is worth retrying.
+    fn is_retryable(&self, err: &ChatGPTError) -> bool;
+
+    /// How long to wait before retrying after `err`, or `None` for the exponential backoff.
+    ///
+    /// By default the time the error tells: the `Retry-After` or rate limit reset headers of a
+    /// `429` or `503` response, or the wait of the client-side rate limiter or circuit breaker
+    /// for policies that retry those.
+    fn retry_after(&self, err: &ChatGPTError) -> Option<Duration> {
+        match err {
+            ChatGPTError::RequestFailed {
+                status_code,
+                headers,
+                ..
+            } if *status_code == StatusCode::TOO_MANY_REQUESTS
+                || *status_code == StatusCode::SERVICE_UNAVAILABLE =>
+            {
+                retry_after(headers)
+            }
+            ChatGPTError::RateLimited { retry_in } | ChatGPTError::CircuitOpen { retry_in } => {
+                Some(*retry_in)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The [`RetryPolicy`] recommended by OpenAI.
+///
+/// Retries connection errors and timeouts, `408 Request Timeout`, `409 Conflict`,
+/// `429 Too Many Requests` and `5xx` responses. `ChatGPTError::InsufficientQuota`, though sent
+/// with a `429`, is not retried, since it only passes once the account is topped up. An
+/// `x-should-retry` header of the response overrides the status.
+///
+/// Requests held back by an open circuit breaker or a client-side rate limit that fails fast
+/// are not retried either, so that they fail fast as configured.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRetryPolicy;
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn is_retryable(&self, err: &ChatGPTError) -> bool {
+        match err {
+            ChatGPTError::RequestFailed {
+                status_code,
+                headers,
+                ..
+            } => {
+                match headers.get("x-should-retry").and_then(|v| v.to_str().ok()) {
+                    Some("true") => return true,
+                    Some("false") => return false,
+                    _ => {}
+                }
+                match *status_code {
+                    StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::CONFLICT
+                    | StatusCode::TOO_MANY_REQUESTS => true,
+                    status => status.is_server_error(),
+                }
+            }
+            ChatGPTError::Reqwest(err) => is_transport_failure(err),
+            _ => false,
+        }
+    }
+}
+
+/// How long to wait between the attempts of a request.
+pub trait Backoff: Send + Sync {
+    /// The wait before retry number `retry`, counting from 1, after waiting `previous` before
+    /// the retry before (zero before the first retry).
+    fn delay(&self, retry: u32, previous: Duration) -> Duration;
+}
+
+/// Waits `base`, doubling with every retry up to `max`.
+///
+/// With `full_jitter`, a random wait between zero and that is taken instead, which spreads
+/// out the retries of many clients failing at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub full_jitter: bool,
+}
+
+impl Default for ExponentialBackoff {
+    /// Half a second, doubling up to a minute, without jitter.
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+            full_jitter: false,
+        }
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn delay(&self, retry: u32, _previous: Duration) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base.saturating_mul(factor).min(self.max);
+        if self.full_jitter {
+            delay.mul_f64(random_fraction())
+        } else {
+            delay
+        }
+    }
+}
+
+/// Waits a random time between `base` and three times the previous wait, up to `max`.
+///
+/// The "decorrelated jitter" of the AWS architecture blog, which grows about as fast as
+/// exponential backoff while keeping the waits of different clients apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecorrelatedJitter {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Backoff for DecorrelatedJitter {
+    fn delay(&self, _retry: u32, previous: Duration) -> Duration {
+        let upper = previous.saturating_mul(3).max(self.base);
+        let delay = self.base + (upper - self.base).mul_f64(random_fraction());
+        delay.min(self.max)
+    }
+}
+
+/// Waits the same time before every retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedBackoff(pub Duration);
+
+impl Backoff for FixedBackoff {
+    fn delay(&self, _retry: u32, _previous: Duration) -> Duration {
+        self.0
+    }
+}
+
+/// A random number in `[0, 1)`, good enough for jitter.
+fn random_fraction() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Tracks the retries of a client against its budget of retries per minute.
+#[derive(Debug)]
+pub(crate) struct RetryBudget {
+    per_minute: u32,
+    /// When the retries of the last minute were made, oldest first.
+    retries: Mutex<VecDeque<Instant>>,
+}
+
+impl RetryBudget {
+    pub(crate) fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            retries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Counts a retry, unless the budget of the last minute is used up.
+    pub(crate) fn try_spend(&self) -> bool {
+        let mut retries = self.retries.lock().unwrap();
+        let now = Instant::now();
+        while retries
+            .front()
+            .is_some_and(|retry| now.duration_since(*retry) >= Duration::from_secs(60))
+        {
+            retries.pop_front();
+        }
+        if retries.len() >= self.per_minute as usize {
+            return false;
+        }
+        retries.push_back(now);
+        true
+    }
+}
+
+/// Whether `err` failed to get a request through, rather than to build or read it.
+fn is_transport_failure(err: &reqwest::Error) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    if err.is_connect() {
+        return true;
+    }
+    err.is_timeout() || err.is_request()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+
+    fn request_failed(status: u16, headers: &[(&'static str, &str)], body: &str) -> ChatGPTError {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(*name, value.parse().unwrap());
+        }
+        ChatGPTError::RequestFailed {
+            status_code: StatusCode::from_u16(status).unwrap(),
+            headers: header_map,
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_retry_policy() {
+        let policy = DefaultRetryPolicy;
+        for status in [408, 409, 429, 500, 503] {
+            assert!(policy.is_retryable(&request_failed(status, &[], "")));
+        }
+        for status in [400, 401, 404] {
+            assert!(!policy.is_retryable(&request_failed(status, &[], "")));
+        }
+        assert!(!policy.is_retryable(&ChatGPTError::InsufficientQuota {
+            message: String::new(),
+            request_id: None,
+        }));
+        assert!(!policy.is_retryable(&request_failed(500, &[("x-should-retry", "false")], "")));
+        assert!(policy.is_retryable(&request_failed(400, &[("x-should-retry", "true")], "")));
+        assert!(!policy.is_retryable(&ChatGPTError::Cancelled));
+        assert!(!policy.is_retryable(&ChatGPTError::CircuitOpen {
+            retry_in: Duration::from_secs(30)
+        }));
+        assert!(!policy.is_retryable(&ChatGPTError::RateLimited {
+            retry_in: Duration::from_secs(30)
+        }));
+
+        assert_eq!(
+            policy.retry_after(&request_failed(429, &[("retry-after", "2")], "")),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            policy.retry_after(&request_failed(500, &[("retry-after", "2")], "")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let exponential = ExponentialBackoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            full_jitter: false,
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|retry| exponential.delay(retry, Duration::ZERO).as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10]);
+        let jittered = ExponentialBackoff {
+            full_jitter: true,
+            ..exponential
+        };
+        for retry in 1..=5 {
+            assert!(
+                jittered.delay(retry, Duration::ZERO) <= exponential.delay(retry, Duration::ZERO)
+            );
+        }
+
+        let decorrelated = DecorrelatedJitter {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(20),
+        };
+        let mut previous = Duration::ZERO;
+        for retry in 1..=10 {
+            let delay = decorrelated.delay(retry, previous);
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(20));
+            assert!(delay <= (previous * 3).max(Duration::from_secs(1)));
+            previous = delay;
+        }
+
+        let fixed = FixedBackoff(Duration::from_millis(250));
+        assert_eq!(fixed.delay(7, Duration::ZERO), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(2);
+        assert!(budget.try_spend());
+        assert!(budget.try_spend());
+        assert!(!budget.try_spend());
+        assert!(!RetryBudget::new(0).try_spend());
+    }
+}
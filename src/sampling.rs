@@ -0,0 +1,143 @@
+//! Sampling parameters checked against the ranges the API accepts.
+//!
+//! [`Temperature`], [`TopP`] and [`PenaltyValue`] can only hold values the API accepts, so an
+//! invalid setting fails where it is made instead of with a `400 Bad Request`. The setters of
+//! [`ChatInputBuilder`](crate::ChatInputBuilder) take them as well as plain numbers:
+//!
+//! ```
+//! use chat_gpt_lib_rs::sampling::{PenaltyValue, Temperature};
+//! use chat_gpt_lib_rs::{ChatInput, Model};
+//!
+//! let temperature = Temperature::new(0.7).unwrap();
+//! let input = ChatInput::builder(Model::Gpt_4o)
+//!     .temperature(temperature)
+//!     .presence_penalty(PenaltyValue::try_from(0.5_f32).unwrap())
+//!     .build();
+//! assert_eq!(input.temperature, Some(0.7));
+//!
+//! assert!(Temperature::new(2.5).is_err());
+//! ```
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::ops::RangeInclusive;
+use thiserror::Error;
+
+/// A sampling parameter set outside the range the API accepts.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{parameter} {value} is outside the range of {} to {}", range.start(), range.end())]
+pub struct OutOfRange {
+    pub parameter: &'static str,
+    pub value: f64,
+    pub range: RangeInclusive<f64>,
+}
+
+macro_rules! sampling_parameter {
+    ($(#[$doc:meta])* $name:ident, $parameter:literal, $range:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize)]
+        #[serde(transparent)]
+        pub struct $name(f64);
+
+        impl $name {
+            /// The values the API accepts.
+            pub const RANGE: RangeInclusive<f64> = $range;
+
+            /// The parameter set to `value`, unless it is out of range.
+            pub fn new(value: f64) -> Result<Self, OutOfRange> {
+                if Self::RANGE.contains(&value) {
+                    Ok(Self(value))
+                } else {
+                    Err(OutOfRange {
+                        parameter: $parameter,
+                        value,
+                        range: Self::RANGE,
+                    })
+                }
+            }
+
+            /// The value of the parameter.
+            pub fn get(self) -> f64 {
+                self.0
+            }
+        }
+
+        impl TryFrom<f64> for $name {
+            type Error = OutOfRange;
+
+            fn try_from(value: f64) -> Result<Self, OutOfRange> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<f32> for $name {
+            type Error = OutOfRange;
+
+            fn try_from(value: f32) -> Result<Self, OutOfRange> {
+                Self::new(value.into())
+            }
+        }
+
+        impl From<$name> for f64 {
+            fn from(value: $name) -> f64 {
+                value.0
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Self::new(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+sampling_parameter!(
+    /// The sampling temperature, from `0` (nearly deterministic) to `2` (most random).
+    Temperature,
+    "temperature",
+    0.0..=2.0
+);
+
+sampling_parameter!(
+    /// The probability mass of nucleus sampling, from `0` to `1`; `0.1` samples only from the
+    /// tokens making up the top 10% of the probability.
+    TopP,
+    "top_p",
+    0.0..=1.0
+);
+
+sampling_parameter!(
+    /// A presence or frequency penalty, from `-2` to `2`; positive values make the model less
+    /// likely to repeat itself.
+    PenaltyValue,
+    "penalty",
+    -2.0..=2.0
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_parameters() {
+        assert_eq!(Temperature::new(0.0).unwrap().get(), 0.0);
+        assert_eq!(Temperature::new(2.0).unwrap().get(), 2.0);
+        assert_eq!(TopP::try_from(0.5_f32).unwrap().get(), 0.5);
+        assert_eq!(f64::from(PenaltyValue::new(-2.0).unwrap()), -2.0);
+
+        let err = Temperature::new(2.5).unwrap_err();
+        assert_eq!(err.parameter, "temperature");
+        assert_eq!(
+            err.to_string(),
+            "temperature 2.5 is outside the range of 0 to 2"
+        );
+        assert!(TopP::new(-0.1).is_err());
+        assert!(PenaltyValue::new(f64::NAN).is_err());
+
+        assert_eq!(
+            serde_json::to_string(&TopP::new(0.9).unwrap()).unwrap(),
+            "0.9"
+        );
+        assert!(serde_json::from_str::<Temperature>("3").is_err());
+    }
+}
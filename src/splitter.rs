@@ -0,0 +1,419 @@
+//! Splitting long texts into chunks, e.g. to embed them for retrieval.
+//!
+//! A [`TextSplitter`] cuts text into chunks of at most a given number of tokens, estimated with
+//! [`count_tokens`] like elsewhere in the crate, or counted exactly by the model's tokenizer
+//! set with [`TextSplitter::tokenizer`]. It prefers to cut between paragraphs or sentences, see
+//! [`Boundary`], and can repeat the end of each chunk at the start of the next one, so no
+//! passage loses its context at a cut.
+//! [`TextSplitter::split_markdown`] additionally follows the sections of a Markdown document,
+//! and reports the headings each chunk is under.
+//!
+//! # Examples
+//!
+//! ```
+//! use chat_gpt_lib_rs::splitter::{Boundary, TextSplitter};
+//!
+//! let splitter = TextSplitter::new(10).boundary(Boundary::Sentence);
+//! let chunks = splitter.split("The first sentence is here. The second one follows it.");
+//! assert_eq!(chunks, ["The first sentence is here.", "The second one follows it."]);
+//! ```
+
+use crate::tokenizer::{count_tokens, TokenEncoder, CHARS_PER_TOKEN};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// Where a [`TextSplitter`] prefers to cut.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Anywhere, into chunks of exactly the chunk size.
+    None,
+    /// Between sentences.
+    Sentence,
+    /// Between paragraphs, separated by blank lines; paragraphs longer than the chunk size are
+    /// cut between sentences.
+    #[default]
+    Paragraph,
+}
+
+/// A chunk of a Markdown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownChunk {
+    /// The headings of the sections the chunk is in, outermost first, without the `#`s.
+    pub headings: Vec<String>,
+    pub text: String,
+}
+
+/// Splits texts into chunks of at most a given number of tokens.
+#[derive(Clone)]
+pub struct TextSplitter {
+    chunk_tokens: usize,
+    overlap_tokens: usize,
+    boundary: Boundary,
+    /// Counts the tokens of a text exactly; they are estimated without it.
+    tokenizer: Option<Arc<dyn TokenEncoder + Send + Sync>>,
+}
+
+impl Debug for TextSplitter {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("TextSplitter")
+            .field("chunk_tokens", &self.chunk_tokens)
+            .field("overlap_tokens", &self.overlap_tokens)
+            .field("boundary", &self.boundary)
+            .field("tokenizer", &self.tokenizer.is_some())
+            .finish()
+    }
+}
+
+impl TextSplitter {
+    /// A splitter into chunks of at most `chunk_tokens` tokens, cutting between paragraphs
+    /// without overlap.
+    pub fn new(chunk_tokens: usize) -> Self {
+        Self {
+            chunk_tokens: chunk_tokens.max(1),
+            overlap_tokens: 0,
+            boundary: Boundary::default(),
+            tokenizer: None,
+        }
+    }
+
+    /// Repeats up to `tokens` tokens of the end of each chunk at the start of the next one.
+    ///
+    /// The overlap is capped at half the chunk size.
+    pub fn overlap(mut self, tokens: usize) -> Self {
+        self.overlap_tokens = tokens.min(self.chunk_tokens / 2);
+        self
+    }
+
+    /// Sets where chunks are preferably cut.
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Counts tokens by encoding the text with `encoder`, the tokenizer of the model the chunks
+    /// are meant for, instead of estimating them with [`count_tokens`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chat_gpt_lib_rs::splitter::{Boundary, TextSplitter};
+    ///
+    /// // Any `Fn(&str) -> Vec<u32>` encodes, e.g. the `encode_ordinary` of a BPE tokenizer.
+    /// let words = |text: &str| text.split_whitespace().map(|_| 0).collect::<Vec<u32>>();
+    /// let splitter = TextSplitter::new(4)
+    ///     .boundary(Boundary::Sentence)
+    ///     .tokenizer(words);
+    /// assert_eq!(splitter.split("One two. Three four five."), ["One two.", "Three four five."]);
+    /// ```
+    pub fn tokenizer(mut self, encoder: impl TokenEncoder + Send + Sync + 'static) -> Self {
+        self.tokenizer = Some(Arc::new(encoder));
+        self
+    }
+
+    /// Splits `text` into chunks, trimmed of surrounding whitespace.
+    pub fn split(&self, text: &str) -> Vec<String> {
+        match self.boundary {
+            Boundary::None => self.split_fixed(text.trim()),
+            Boundary::Sentence => self.merge(sentences(text), " "),
+            Boundary::Paragraph => self.merge(paragraphs(text), "\n\n"),
+        }
+    }
+
+    /// Splits a Markdown document into chunks that do not cross section boundaries.
+    ///
+    /// Lines starting with one to six `#` followed by a space are headings, except inside
+    /// fenced code blocks. Each section is split as by [`TextSplitter::split`].
+    pub fn split_markdown(&self, markdown: &str) -> Vec<MarkdownChunk> {
+        let mut chunks = Vec::new();
+        let mut headings: Vec<(usize, String)> = Vec::new();
+        let mut section = String::new();
+        let mut in_code = false;
+        let mut flush = |headings: &[(usize, String)], section: &mut String| {
+            for text in self.split(section) {
+                chunks.push(MarkdownChunk {
+                    headings: headings.iter().map(|(_, title)| title.clone()).collect(),
+                    text,
+                });
+            }
+            section.clear();
+        };
+        for line in markdown.lines() {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+            }
+            match heading(line).filter(|_| !in_code) {
+                Some((level, title)) => {
+                    flush(&headings, &mut section);
+                    headings.retain(|(outer, _)| *outer < level);
+                    headings.push((level, title.to_string()));
+                }
+                None => {
+                    section.push_str(line);
+                    section.push('\n');
+                }
+            }
+        }
+        flush(&headings, &mut section);
+        chunks
+    }
+
+    /// Cuts `text` into chunks of exactly the chunk size, except the last one.
+    fn split_fixed(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let (end, next) = self.fixed_chunk(&chars, start);
+            chunks.push(
+                chars[start..end]
+                    .iter()
+                    .collect::<String>()
+                    .trim()
+                    .to_string(),
+            );
+            if end == chars.len() {
+                break;
+            }
+            start = next;
+        }
+        chunks.retain(|chunk| !chunk.is_empty());
+        chunks
+    }
+
+    /// The end of the chunk of `chars` starting at `start`, and the start of the next chunk.
+    fn fixed_chunk(&self, chars: &[char], start: usize) -> (usize, usize) {
+        let Some(tokenizer) = &self.tokenizer else {
+            let size = self.chunk_tokens * CHARS_PER_TOKEN;
+            let step = size - self.overlap_tokens * CHARS_PER_TOKEN;
+            return ((start + size).min(chars.len()), start + step);
+        };
+        let tokens = |from: usize, to: usize| {
+            tokenizer
+                .encode(&chars[from..to].iter().collect::<String>())
+                .len()
+        };
+        // The longest chunk within the chunk size, of at least one character.
+        let (mut low, mut high) = (start + 1, chars.len());
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if tokens(start, mid) <= self.chunk_tokens {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let end = low;
+        // The longest end of the chunk within the overlap, leaving at least one character.
+        let (mut low, mut high) = (start + 1, end);
+        while low < high {
+            let mid = (low + high) / 2;
+            if tokens(mid, end) <= self.overlap_tokens {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        (end, low)
+    }
+
+    /// The tokens of `text`, counted by the tokenizer or else estimated.
+    fn count(&self, text: &str) -> usize {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer.encode(text).len(),
+            None => count_tokens(text),
+        }
+    }
+
+    /// Packs consecutive `units` into chunks, joined by `separator`.
+    fn merge(&self, units: Vec<&str>, separator: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current: Vec<String> = Vec::new();
+        for unit in units {
+            let parts = if self.count(unit) <= self.chunk_tokens {
+                vec![unit.to_string()]
+            } else if separator == "\n\n" {
+                // A paragraph over the chunk size is cut between its sentences.
+                self.merge(sentences(unit), " ")
+            } else {
+                self.split_fixed(unit)
+            };
+            for part in parts {
+                if current.is_empty() || self.fits(&current, &part, separator) {
+                    current.push(part);
+                    continue;
+                }
+                chunks.push(current.join(separator));
+                current = self.overlap_of(&current, separator);
+                while !current.is_empty() && !self.fits(&current, &part, separator) {
+                    current.remove(0);
+                }
+                current.push(part);
+            }
+        }
+        if !current.is_empty() {
+            chunks.push(current.join(separator));
+        }
+        chunks
+    }
+
+    /// Whether `units` and `next`, joined by `separator`, fit into a chunk.
+    fn fits(&self, units: &[String], next: &str, separator: &str) -> bool {
+        let joined = units
+            .iter()
+            .map(String::as_str)
+            .chain([next])
+            .collect::<Vec<_>>()
+            .join(separator);
+        self.count(&joined) <= self.chunk_tokens
+    }
+
+    /// The trailing units of a finished chunk that are repeated at the start of the next one.
+    fn overlap_of(&self, units: &[String], separator: &str) -> Vec<String> {
+        let mut overlap = Vec::new();
+        for unit in units.iter().rev() {
+            let candidate = [unit.as_str()]
+                .into_iter()
+                .chain(overlap.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(separator);
+            if self.count(&candidate) > self.overlap_tokens {
+                break;
+            }
+            overlap.insert(0, unit.clone());
+        }
+        overlap
+    }
+}
+
+/// The paragraphs of `text`, separated by blank lines.
+fn paragraphs(text: &str) -> Vec<&str> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            paragraphs.push(text[start..offset].trim());
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    paragraphs.push(text[start..].trim());
+    paragraphs.retain(|paragraph| !paragraph.is_empty());
+    paragraphs
+}
+
+/// The sentences of `text`, which end with `.`, `!` or `?` followed by whitespace.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if matches!(c, '.' | '!' | '?')
+            && chars.peek().is_some_and(|(_, next)| next.is_whitespace())
+        {
+            let end = index + c.len_utf8();
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// The level and title of a Markdown heading line.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then(|| (level, title.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_fixed_with_overlap() {
+        let splitter = TextSplitter::new(2).overlap(1).boundary(Boundary::None);
+        assert_eq!(
+            splitter.split("abcdefghijklmnop"),
+            ["abcdefgh", "efghijkl", "ijklmnop"]
+        );
+        assert!(splitter.split("   ").is_empty());
+    }
+
+    #[test]
+    fn test_split_paragraphs() {
+        let text = "First paragraph here.\n\nSecond one.\n\n\nA third, somewhat longer paragraph. \
+                    It has two sentences.";
+        let chunks = TextSplitter::new(10).split(text);
+        assert_eq!(
+            chunks,
+            [
+                "First paragraph here.\n\nSecond one.",
+                "A third, somewhat longer paragraph.",
+                "It has two sentences.",
+            ]
+        );
+
+        let chunks = TextSplitter::new(8)
+            .overlap(4)
+            .boundary(Boundary::Sentence)
+            .split("One two three. Four five six. Seven eight nine. Ten.");
+        assert_eq!(
+            chunks,
+            [
+                "One two three. Four five six.",
+                "Four five six. Seven eight nine.",
+                "Seven eight nine. Ten.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_with_tokenizer() {
+        // One token per character, so chunk sizes differ from the estimate of four.
+        let chars = |text: &str| text.chars().map(|c| c as u32).collect::<Vec<u32>>();
+        let splitter = TextSplitter::new(4)
+            .overlap(2)
+            .boundary(Boundary::None)
+            .tokenizer(chars);
+        assert_eq!(
+            splitter.split("abcdefghij"),
+            ["abcd", "cdef", "efgh", "ghij"]
+        );
+
+        let splitter = TextSplitter::new(12)
+            .boundary(Boundary::Sentence)
+            .tokenizer(chars);
+        assert_eq!(
+            splitter.split("One two. Three. Four five."),
+            ["One two.", "Three.", "Four five."]
+        );
+    }
+
+    #[test]
+    fn test_split_markdown() {
+        let markdown = "Intro.\n\n# Guide\n\nSetup text.\n\n## Install\n\nRun it.\n\n```\n# not a heading\n```\n\n# FAQ\n\nAsk.";
+        let chunks = TextSplitter::new(100).split_markdown(markdown);
+        let sections: Vec<(Vec<&str>, &str)> = chunks
+            .iter()
+            .map(|chunk| {
+                let headings = chunk.headings.iter().map(String::as_str).collect();
+                (headings, chunk.text.as_str())
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (vec![], "Intro."),
+                (vec!["Guide"], "Setup text."),
+                (
+                    vec!["Guide", "Install"],
+                    "Run it.\n\n```\n# not a heading\n```"
+                ),
+                (vec!["FAQ"], "Ask."),
+            ]
+        );
+    }
+}
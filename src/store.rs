@@ -0,0 +1,312 @@
+//! Persistence of conversations across restarts.
+//!
+//! A [`ConversationStore`] saves [`Conversation`]s, with all their branches, under a session
+//! ID, so chat applications can resume a session after a restart. [`MemoryStore`] keeps them
+//! in memory, e.g. for tests; on native targets [`JsonFileStore`] writes one JSON file per
+//! session into a directory, and with the `sqlite` feature `SqliteStore` keeps them in a
+//! SQLite database. Other backends implement the trait.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::store::{ConversationStore, JsonFileStore};
+//! use chat_gpt_lib_rs::{Conversation, Message};
+//!
+//! let store = JsonFileStore::new("sessions").unwrap();
+//! let mut conversation = store.load("alice").unwrap().unwrap_or_default();
+//! conversation.push(Message::user("Where were we?"));
+//! store.save("alice", &conversation).unwrap();
+//! ```
+
+use crate::conversation::Conversation;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A store for conversations, keyed by session ID.
+pub trait ConversationStore: Send + Sync {
+    /// Saves `conversation` under `session`, replacing an earlier one.
+    fn save(&self, session: &str, conversation: &Conversation) -> io::Result<()>;
+    /// Loads the conversation saved under `session`, if any.
+    fn load(&self, session: &str) -> io::Result<Option<Conversation>>;
+    /// Lists the IDs of all saved sessions, in alphabetical order.
+    fn list(&self) -> io::Result<Vec<String>>;
+    /// Deletes the conversation saved under `session`, returning whether there was one.
+    fn delete(&self, session: &str) -> io::Result<bool>;
+}
+
+/// A [`ConversationStore`] keeping the conversations in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    sessions: Mutex<BTreeMap<String, Conversation>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConversationStore for MemoryStore {
+    fn save(&self, session: &str, conversation: &Conversation) -> io::Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(session.to_string(), conversation.clone());
+        Ok(())
+    }
+
+    fn load(&self, session: &str) -> io::Result<Option<Conversation>> {
+        Ok(self.sessions.lock().unwrap().get(session).cloned())
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(self.sessions.lock().unwrap().keys().cloned().collect())
+    }
+
+    fn delete(&self, session: &str) -> io::Result<bool> {
+        Ok(self.sessions.lock().unwrap().remove(session).is_some())
+    }
+}
+
+/// A [`ConversationStore`] writing every conversation as a JSON file named after its session.
+///
+/// Session IDs may only contain ASCII letters, digits, `-` and `_`, so they are valid file
+/// names on every platform.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl JsonFileStore {
+    /// Opens the store in `dir`, creating the directory if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the directory cannot be created.
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The directory holding the conversations.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, session: &str) -> io::Result<PathBuf> {
+        let valid = !session.is_empty()
+            && session
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid session ID {session:?}"),
+            ));
+        }
+        Ok(self.dir.join(format!("{session}.json")))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ConversationStore for JsonFileStore {
+    fn save(&self, session: &str, conversation: &Conversation) -> io::Result<()> {
+        let path = self.path(session)?;
+        // Write to a temporary file first, so a crash never leaves a partial conversation.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(conversation)?)?;
+        fs::rename(&tmp, &path)
+    }
+
+    fn load(&self, session: &str) -> io::Result<Option<Conversation>> {
+        match fs::read(self.path(session)?) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(session) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    sessions.push(session.to_string());
+                }
+            }
+        }
+        sessions.sort();
+        Ok(sessions)
+    }
+
+    fn delete(&self, session: &str) -> io::Result<bool> {
+        match fs::remove_file(self.path(session)?) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// A [`ConversationStore`] keeping the conversations as JSON in a table of a SQLite database.
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+impl SqliteStore {
+    /// Opens the store in the database file at `path`, creating the file and the
+    /// `conversations` table if they do not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the database cannot be opened or the table cannot be created.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_connection(rusqlite::Connection::open(path).map_err(io::Error::other)?)
+    }
+
+    /// Opens a store in a database that lives in memory, e.g. for tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the database cannot be created.
+    pub fn open_in_memory() -> io::Result<Self> {
+        Self::with_connection(rusqlite::Connection::open_in_memory().map_err(io::Error::other)?)
+    }
+
+    /// Keeps the conversations in the database of `connection`, creating the `conversations`
+    /// table if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the table cannot be created.
+    pub fn with_connection(connection: rusqlite::Connection) -> io::Result<Self> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS conversations (
+                    session TEXT PRIMARY KEY NOT NULL,
+                    conversation TEXT NOT NULL
+                )",
+                [],
+            )
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+impl ConversationStore for SqliteStore {
+    fn save(&self, session: &str, conversation: &Conversation) -> io::Result<()> {
+        let conversation = serde_json::to_string(conversation)?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO conversations (session, conversation) VALUES (?1, ?2)",
+                (session, conversation),
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn load(&self, session: &str) -> io::Result<Option<Conversation>> {
+        let connection = self.connection.lock().unwrap();
+        let conversation = connection.query_row(
+            "SELECT conversation FROM conversations WHERE session = ?1",
+            [session],
+            |row| row.get::<_, String>(0),
+        );
+        match conversation {
+            Ok(conversation) => Ok(Some(serde_json::from_str(&conversation)?)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT session FROM conversations ORDER BY session")
+            .map_err(io::Error::other)?;
+        let sessions = statement
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .map_err(io::Error::other)?;
+        Ok(sessions)
+    }
+
+    fn delete(&self, session: &str) -> io::Result<bool> {
+        let deleted = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM conversations WHERE session = ?1", [session])
+            .map_err(io::Error::other)?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Message;
+
+    fn round_trip(store: &dyn ConversationStore) {
+        let mut conversation = Conversation::new();
+        conversation.push(Message::user("Hi"));
+        conversation.branch("retry");
+        conversation.push(Message::assistant("Hello!"));
+
+        assert!(store.load("alice").unwrap().is_none());
+        store.save("alice", &conversation).unwrap();
+        store.save("bob", &Conversation::new()).unwrap();
+        assert_eq!(store.list().unwrap(), ["alice", "bob"]);
+
+        let loaded = store.load("alice").unwrap().unwrap();
+        assert_eq!(loaded.current_branch(), "retry");
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.branch_messages("main").unwrap().len(), 1);
+
+        assert!(store.delete("bob").unwrap());
+        assert!(!store.delete("bob").unwrap());
+        assert_eq!(store.list().unwrap(), ["alice"]);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        round_trip(&MemoryStore::new());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_json_file_store() {
+        let dir = std::env::temp_dir().join(format!("chat-gpt-store-{}", std::process::id()));
+        let store = JsonFileStore::new(&dir).unwrap();
+        round_trip(&store);
+        let err = store.save("../escape", &Conversation::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_sqlite_store() {
+        round_trip(&SqliteStore::open_in_memory().unwrap());
+    }
+}
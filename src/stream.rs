@@ -0,0 +1,849 @@
+//! Streamed chat responses.
+//!
+//! [`ChatGPTClient::chat_stream`](crate::ChatGPTClient::chat_stream) yields the response as
+//! [`ChatChunk`]s, each carrying a [`Delta`] of the message. A [`ResponseAccumulator`] merges
+//! the chunks back into the complete [`ChatResponse`] while they are shown, and
+//! [`collect_response`] does so for a whole stream. Applications parsing the SSE stream
+//! themselves can merge deltas into a message with [`merge_delta`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::stream::ResponseAccumulator;
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//! use futures_util::StreamExt;
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let input = ChatInput::builder(Model::Gpt_4o)
+//!     .message(Message::user("Tell me a story."))
+//!     .build();
+//! let mut chunks = Box::pin(client.chat_stream(input).await?);
+//! let mut accumulator = ResponseAccumulator::new();
+//! while let Some(chunk) = chunks.next().await {
+//!     let chunk = chunk?;
+//!     if let Some(content) = chunk.choices.first().and_then(|c| c.delta.content.as_deref()) {
+//!         print!("{content}");
+//!     }
+//!     accumulator.push(&chunk);
+//! }
+//! let response = accumulator.finish();
+//! println!("\n{} tokens", response.usage.total_tokens);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::audio::MessageAudio;
+use crate::client::{
+    null_as_default, unix_time, ChatGPTError, ChatResponse, Choice, Message, Usage,
+};
+use crate::compat::{normalize_chunk, CompatMode};
+use crate::content::{Content, ContentPart};
+#[cfg(feature = "legacy-functions")]
+use crate::functions::FunctionCall;
+use crate::models::Role;
+use crate::tools::{ToolCall, ToolCallDelta};
+use futures_util::future::{self, Either};
+use futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use web_time::SystemTime;
+
+/// Represents a single chunk of a streamed chat API response.
+#[derive(Debug, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct ChatChunk {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChunkChoice>,
+    /// The usage of the whole request, sent in a last chunk without choices if requested
+    /// with `stream_options: {"include_usage": true}`.
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    /// Fields of the chunk this crate does not model, e.g. `system_fingerprint`.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl ChatChunk {
+    /// When the completion was created, the same for every chunk of it.
+    pub fn created_at(&self) -> SystemTime {
+        unix_time(self.created)
+    }
+}
+
+/// Represents a choice in a streamed chat API response chunk.
+#[derive(Debug, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct ChunkChoice {
+    #[serde(default)]
+    pub index: usize,
+    pub delta: Delta,
+    pub finish_reason: Option<String>,
+    /// Fields of the choice this crate does not model, e.g. `logprobs`.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// Represents the incremental part of a message carried by a chunk.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[non_exhaustive]
+pub struct Delta {
+    #[serde(default)]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub content: Option<String>,
+    /// A piece of the spoken answer, when audio output was requested.
+    #[serde(default)]
+    pub audio: Option<MessageAudio>,
+    /// A piece of a legacy function call, with the `legacy-functions` feature.
+    #[cfg(feature = "legacy-functions")]
+    #[serde(default)]
+    pub function_call: Option<FunctionCall>,
+    /// A piece of the model's refusal to answer, see [`Message::refusal`](crate::Message::refusal).
+    #[serde(default)]
+    pub refusal: Option<String>,
+    /// Pieces of the tool calls of the message.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tool_calls: Vec<ToolCallDelta>,
+    /// Fields of the delta this crate does not model, e.g. the `reasoning_content` of some
+    /// OpenAI-compatible servers.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+/// Merges streamed [`ChatChunk`]s into the complete [`ChatResponse`].
+#[derive(Debug, Clone, Default)]
+pub struct ResponseAccumulator {
+    response: Option<ChatResponse>,
+}
+
+impl ResponseAccumulator {
+    /// An accumulator that has not seen any chunk.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges `chunk` into the response.
+    pub fn push(&mut self, chunk: &ChatChunk) {
+        let response = self.response.get_or_insert_with(|| ChatResponse {
+            id: chunk.id.clone(),
+            object: "chat.completion".to_string(),
+            created: chunk.created,
+            model: chunk.model.clone(),
+            usage: Usage::default(),
+            choices: Vec::new(),
+            extensions: Default::default(),
+            request_id: None,
+            warnings: Vec::new(),
+        });
+        for choice in &chunk.choices {
+            while response.choices.len() <= choice.index {
+                response.choices.push(Choice::new(
+                    Message::new(Role::Assistant, Content::None),
+                    String::new(),
+                ));
+            }
+            let merged = &mut response.choices[choice.index];
+            merge_delta(&mut merged.message, &choice.delta);
+            if let Some(finish_reason) = &choice.finish_reason {
+                merged.finish_reason.clone_from(finish_reason);
+            }
+        }
+        if let Some(usage) = &chunk.usage {
+            response.usage = usage.clone();
+        }
+        response.extensions.extend(chunk.extensions.clone());
+    }
+
+    /// The response merged from the chunks so far.
+    pub fn response(&self) -> Option<&ChatResponse> {
+        self.response.as_ref()
+    }
+
+    /// The response merged from all chunks; empty if there were none.
+    pub fn finish(self) -> ChatResponse {
+        self.response.unwrap_or_else(|| ChatResponse {
+            id: String::new(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: String::new(),
+            usage: Usage::default(),
+            choices: Vec::new(),
+            extensions: Default::default(),
+            request_id: None,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// Consumes `chunks` and merges them into the complete [`ChatResponse`], with the content,
+/// tool calls and finish reason of every choice and the usage, if the stream reported it.
+///
+/// # Errors
+///
+/// Returns the first error of the stream.
+pub async fn collect_response<S>(chunks: S) -> Result<ChatResponse, ChatGPTError>
+where
+    S: Stream<Item = Result<ChatChunk, ChatGPTError>>,
+{
+    let mut chunks = std::pin::pin!(chunks);
+    let mut accumulator = ResponseAccumulator::new();
+    while let Some(chunk) = chunks.next().await {
+        accumulator.push(&chunk?);
+    }
+    Ok(accumulator.finish())
+}
+
+/// Appends the pieces of `delta` to `message`, as [`ResponseAccumulator`] does for every
+/// choice.
+///
+/// The role is replaced; content, refusal, audio data and transcript are appended; tool calls
+/// are merged by their index, appending to the name and arguments of the call at that index.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::stream::{merge_delta, Delta};
+/// use chat_gpt_lib_rs::{Content, Message, Role};
+///
+/// let mut message = Message::new(Role::Assistant, Content::None);
+/// for piece in ["Hel", "lo"] {
+///     let mut delta = Delta::default();
+///     delta.content = Some(piece.to_string());
+///     merge_delta(&mut message, &delta);
+/// }
+/// assert_eq!(message.content, "Hello");
+/// ```
+pub fn merge_delta(message: &mut Message, delta: &Delta) {
+    if let Some(role) = &delta.role {
+        message.role = role.clone();
+    }
+    if let Some(content) = &delta.content {
+        match &mut message.content {
+            Content::Text(text) => text.push_str(content),
+            Content::Parts(parts) => parts.push(ContentPart::text(content)),
+            Content::None => message.content = Content::Text(content.clone()),
+        }
+    }
+    if let Some(refusal) = &delta.refusal {
+        message
+            .refusal
+            .get_or_insert_with(String::new)
+            .push_str(refusal);
+    }
+    if let Some(audio) = &delta.audio {
+        let merged = message.audio.get_or_insert_with(MessageAudio::default);
+        if !audio.id.is_empty() {
+            merged.id.clone_from(&audio.id);
+        }
+        merged.data.push_str(&audio.data);
+        merged.transcript.push_str(&audio.transcript);
+        if audio.expires_at.is_some() {
+            merged.expires_at = audio.expires_at;
+        }
+    }
+    #[cfg(feature = "legacy-functions")]
+    if let Some(function_call) = &delta.function_call {
+        let merged = message
+            .function_call
+            .get_or_insert_with(FunctionCall::default);
+        merged.name.push_str(&function_call.name);
+        merged.arguments.push_str(&function_call.arguments);
+    }
+    for tool_call in &delta.tool_calls {
+        while message.tool_calls.len() <= tool_call.index {
+            message.tool_calls.push(ToolCall::default());
+        }
+        let merged = &mut message.tool_calls[tool_call.index];
+        if let Some(id) = &tool_call.id {
+            merged.id.clone_from(id);
+        }
+        if let Some(kind) = &tool_call.kind {
+            merged.kind.clone_from(kind);
+        }
+        if let Some(function) = &tool_call.function {
+            if let Some(name) = &function.name {
+                merged.function.name.push_str(name);
+            }
+            if let Some(arguments) = &function.arguments {
+                merged.function.arguments.push_str(arguments);
+            }
+        }
+    }
+}
+
+/// A complete event of a server-sent events stream.
+#[derive(Debug, PartialEq)]
+enum SseEvent {
+    /// The `data:` fields of the event, joined by newlines.
+    Data(String),
+    /// The `[DONE]` marker OpenAI ends its streams with.
+    Done,
+}
+
+/// Assembles the lines of a server-sent events stream into events, following the HTML
+/// specification of the format.
+///
+/// Comments (lines starting with `:`, which servers send as keep-alives) and fields other than
+/// `data` are ignored. Several `data:` lines of one event are joined by newlines; the event is
+/// complete at the following blank line.
+#[derive(Debug, Default)]
+struct SseParser {
+    data: String,
+    has_data: bool,
+}
+
+impl SseParser {
+    /// Feeds one line, without its line ending, returning the event it completes.
+    fn line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        if field == "data" {
+            if self.has_data {
+                self.data.push('\n');
+            }
+            self.data.push_str(value);
+            self.has_data = true;
+        }
+        None
+    }
+
+    /// Completes the event of the lines fed since the last blank line, if it has data.
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        if !std::mem::take(&mut self.has_data) {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data);
+        if data.trim() == "[DONE]" {
+            Some(SseEvent::Done)
+        } else if data.trim().is_empty() {
+            None
+        } else {
+            Some(SseEvent::Data(data))
+        }
+    }
+}
+
+/// Removes the next line from `buffer`, ending in `\n`, `\r\n` or a lone `\r`, and returns it
+/// without its line ending.
+///
+/// A trailing `\r` is only taken as a line ending at the end of the stream, since it might be
+/// followed by a `\n` that has not been received yet; at the end of the stream, the rest of
+/// the buffer is the last line.
+fn next_line(buffer: &mut Vec<u8>, eof: bool) -> Option<String> {
+    let Some(pos) = buffer.iter().position(|b| matches!(b, b'\n' | b'\r')) else {
+        return (eof && !buffer.is_empty())
+            .then(|| String::from_utf8_lossy(&std::mem::take(buffer)).into_owned());
+    };
+    let ending = match (buffer[pos], buffer.get(pos + 1)) {
+        (b'\r', Some(b'\n')) => 2,
+        (b'\r', None) if !eof => return None,
+        _ => 1,
+    };
+    let line = String::from_utf8_lossy(&buffer[..pos]).into_owned();
+    buffer.drain(..pos + ending);
+    Some(line)
+}
+
+struct SseStreamState<S, T, F> {
+    bytes: S,
+    parse: F,
+    buffer: Vec<u8>,
+    parser: SseParser,
+    pending: VecDeque<Result<T, ChatGPTError>>,
+    eof: bool,
+    /// Whether the stream started, so a byte order mark at the start has been skipped.
+    started: bool,
+}
+
+/// Converts a stream of raw response bytes into a stream of parsed [`ChatChunk`]s.
+pub(crate) fn chunk_stream<S, B, E>(
+    bytes: S,
+    compat: CompatMode,
+) -> impl Stream<Item = Result<ChatChunk, ChatGPTError>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Into<ChatGPTError>,
+{
+    sse_stream(bytes, move |data| Some(parse_chunk(data, compat)))
+}
+
+fn parse_chunk(data: &str, compat: CompatMode) -> Result<ChatChunk, ChatGPTError> {
+    match compat {
+        CompatMode::Strict => Ok(serde_json::from_str(data)?),
+        CompatMode::Lenient => {
+            let mut value = serde_json::from_str(data)?;
+            normalize_chunk(&mut value);
+            Ok(serde_json::from_value(value)?)
+        }
+    }
+}
+
+/// Converts a stream of raw server-sent events into the items `parse` makes of their `data:`
+/// fields, skipping the events for which it returns `None`.
+pub(crate) fn sse_stream<S, B, E, T, F>(
+    bytes: S,
+    parse: F,
+) -> impl Stream<Item = Result<T, ChatGPTError>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Into<ChatGPTError>,
+    F: FnMut(&str) -> Option<Result<T, ChatGPTError>>,
+{
+    let state = SseStreamState {
+        bytes: Box::pin(bytes),
+        parse,
+        buffer: Vec::new(),
+        parser: SseParser::default(),
+        pending: VecDeque::new(),
+        eof: false,
+        started: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+
+            let line = next_line(&mut state.buffer, state.eof);
+            let event = match &line {
+                Some(line) => state.parser.line(line),
+                // Complete an event that was not terminated by a blank line.
+                None if state.eof => match state.parser.dispatch() {
+                    Some(event) => Some(event),
+                    None => return None,
+                },
+                None => None,
+            };
+            match event {
+                Some(SseEvent::Data(data)) => {
+                    if let Some(item) = (state.parse)(&data) {
+                        state.pending.push_back(item);
+                    }
+                    continue;
+                }
+                Some(SseEvent::Done) => return None,
+                None if line.is_some() => continue,
+                None => {}
+            }
+
+            match state.bytes.next().await {
+                Some(Ok(bytes)) => {
+                    let mut bytes = bytes.as_ref();
+                    if !state.started {
+                        state.started = !bytes.is_empty();
+                        bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+                    }
+                    state.buffer.extend_from_slice(bytes);
+                }
+                Some(Err(err)) => {
+                    state.eof = true;
+                    state.buffer.clear();
+                    state.parser = SseParser::default();
+                    return Some((Err(err.into()), state));
+                }
+                None => state.eof = true,
+            }
+        }
+    })
+}
+
+/// Fails `bytes` with `ChatGPTError::StreamStalled` and ends it when no bytes arrive for
+/// `timeout`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn idle_timeout<S, B>(
+    bytes: S,
+    timeout: Option<Duration>,
+) -> impl Stream<Item = Result<B, ChatGPTError>>
+where
+    S: Stream<Item = Result<B, reqwest::Error>>,
+{
+    stream::unfold(Some(Box::pin(bytes)), move |bytes| async move {
+        let mut bytes = bytes?;
+        let Some(timeout) = timeout else {
+            let item = bytes.next().await?;
+            return Some((item.map_err(ChatGPTError::from), Some(bytes)));
+        };
+        match tokio::time::timeout(timeout, bytes.next()).await {
+            Ok(item) => Some((item?.map_err(ChatGPTError::from), Some(bytes))),
+            Err(_) => Some((Err(ChatGPTError::StreamStalled { timeout }), None)),
+        }
+    })
+}
+
+/// Passes `bytes` on unchanged; there is no timer to detect stalls with on `wasm32`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn idle_timeout<S, B>(
+    bytes: S,
+    _timeout: Option<Duration>,
+) -> impl Stream<Item = Result<B, ChatGPTError>>
+where
+    S: Stream<Item = Result<B, reqwest::Error>>,
+{
+    bytes.map(|item| item.map_err(ChatGPTError::from))
+}
+
+/// Turns transport errors of `chunks`, which break off the response body, into
+/// `ChatGPTError::StreamInterrupted` errors carrying the content received before.
+pub(crate) fn interruptible<S>(chunks: S) -> impl Stream<Item = Result<ChatChunk, ChatGPTError>>
+where
+    S: Stream<Item = Result<ChatChunk, ChatGPTError>>,
+{
+    let mut partial = String::new();
+    chunks.map(move |item| match item {
+        Ok(chunk) => {
+            if let Some(content) = chunk.choices.first().and_then(|c| c.delta.content.as_ref()) {
+                partial.push_str(content);
+            }
+            Ok(chunk)
+        }
+        Err(ChatGPTError::Reqwest(source)) => Err(ChatGPTError::StreamInterrupted {
+            partial: std::mem::take(&mut partial),
+            source,
+        }),
+        Err(err) => Err(err),
+    })
+}
+
+/// The finish reason of the choices of a stream ended by its cancellation token.
+pub const CANCELLED_FINISH_REASON: &str = "cancelled";
+
+/// Ends `chunks` as soon as `token` is cancelled, with a last chunk finishing the unfinished
+/// choices with [`CANCELLED_FINISH_REASON`].
+///
+/// Dropping the inner stream closes the HTTP connection, so the server stops generating.
+pub(crate) fn cancellable<S>(
+    chunks: S,
+    token: Option<CancellationToken>,
+) -> impl Stream<Item = Result<ChatChunk, ChatGPTError>>
+where
+    S: Stream<Item = Result<ChatChunk, ChatGPTError>>,
+{
+    let state = Some((Box::pin(chunks), token, Cancellation::default()));
+    stream::unfold(state, |state| async move {
+        let (mut chunks, token, mut cancellation) = state?;
+        let Some(token) = token else {
+            let item = chunks.next().await?;
+            return Some((item, Some((chunks, None, cancellation))));
+        };
+        let next = {
+            let cancelled = std::pin::pin!(token.cancelled());
+            match future::select(chunks.next(), cancelled).await {
+                Either::Left((item, _)) => Some(item),
+                Either::Right(_) => None,
+            }
+        };
+        match next {
+            Some(Some(item)) => {
+                if let Ok(chunk) = &item {
+                    cancellation.push(chunk);
+                }
+                Some((item, Some((chunks, Some(token), cancellation))))
+            }
+            Some(None) => None,
+            None => Some((Ok(cancellation.finish()), None)),
+        }
+    })
+}
+
+/// What the last chunk of a cancelled stream needs to know of the chunks before.
+#[derive(Default)]
+struct Cancellation {
+    id: String,
+    created: i64,
+    model: String,
+    /// Whether each choice has finished.
+    finished: Vec<bool>,
+}
+
+impl Cancellation {
+    fn push(&mut self, chunk: &ChatChunk) {
+        if self.id.is_empty() {
+            self.id.clone_from(&chunk.id);
+            self.created = chunk.created;
+            self.model.clone_from(&chunk.model);
+        }
+        for choice in &chunk.choices {
+            if self.finished.len() <= choice.index {
+                self.finished.resize(choice.index + 1, false);
+            }
+            if choice.finish_reason.is_some() {
+                self.finished[choice.index] = true;
+            }
+        }
+    }
+
+    /// A chunk finishing the unfinished choices, or the first choice if there was none.
+    fn finish(self) -> ChatChunk {
+        let mut finished = self.finished;
+        if finished.is_empty() {
+            finished.push(false);
+        }
+        ChatChunk {
+            id: self.id,
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model,
+            choices: finished
+                .into_iter()
+                .enumerate()
+                .filter(|(_, finished)| !finished)
+                .map(|(index, _)| ChunkChoice {
+                    index,
+                    delta: Delta::default(),
+                    finish_reason: Some(CANCELLED_FINISH_REASON.to_string()),
+                    extensions: Map::new(),
+                })
+                .collect(),
+            usage: None,
+            extensions: Map::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_json(content: &str) -> String {
+        format!(
+            r#"{{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{{"index":0,"delta":{{"content":"{content}"}},"finish_reason":null}}]}}"#
+        )
+    }
+
+    async fn collect_contents(parts: Vec<String>) -> Vec<String> {
+        let bytes = stream::iter(parts.into_iter().map(Ok::<_, reqwest::Error>));
+        chunk_stream(bytes, CompatMode::Strict)
+            .map(|chunk| chunk.unwrap().choices[0].delta.content.clone().unwrap())
+            .collect()
+            .await
+    }
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        let mut events = Vec::new();
+        for line in [
+            ": keep-alive",
+            "event: message",
+            "data: {\"a\":",
+            "data:1}",
+            "id: 7",
+            "",
+            "",
+            "data",
+            "",
+            "data: [DONE]",
+            "",
+        ] {
+            events.extend(parser.line(line));
+        }
+        assert_eq!(
+            events,
+            [SseEvent::Data("{\"a\":\n1}".to_string()), SseEvent::Done]
+        );
+    }
+
+    #[test]
+    fn test_next_line() {
+        let mut buffer = b"a\r\nb\rc\nd\r".to_vec();
+        let lines: Vec<String> = std::iter::from_fn(|| next_line(&mut buffer, false)).collect();
+        assert_eq!(lines, ["a", "b", "c"]);
+        assert_eq!(next_line(&mut buffer, true).as_deref(), Some("d"));
+        assert!(buffer.is_empty());
+        buffer.extend_from_slice(b"data: e");
+        assert_eq!(next_line(&mut buffer, false), None);
+        assert_eq!(next_line(&mut buffer, true).as_deref(), Some("data: e"));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_stream_with_comments_and_multiline_data() {
+        let json = chunk_json("hi");
+        let (head, tail) = json.split_at(json.find(r#","choices""#).unwrap());
+        let body = format!(
+            "\u{feff}: ping\r\n\r\ndata: {head}\r\ndata: {tail}\r\n\r\n:\n\ndata: [DONE]\n\ndata: {json}\n\n"
+        );
+        let (first, second) = body.split_at(body.find("\n\r\n").unwrap() + 2);
+        let contents = collect_contents(vec![first.to_string(), second.to_string()]).await;
+        assert_eq!(contents, vec!["hi"]);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_stream_split_across_reads() {
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk_json("Hel"),
+            chunk_json("lo")
+        );
+        let (first, second) = body.split_at(body.len() / 3);
+        let contents = collect_contents(vec![first.to_string(), second.to_string()]).await;
+        assert_eq!(contents, vec!["Hel", "lo"]);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_stream_without_done_marker() {
+        let body = format!("data: {}\r\n\r\ndata: {}", chunk_json("a"), chunk_json("b"));
+        let contents = collect_contents(vec![body]).await;
+        assert_eq!(contents, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_stream() {
+        let token = CancellationToken::new();
+        let body = format!("data: {}\n\n", chunk_json("first"));
+        let bytes = stream::iter(vec![Ok::<_, reqwest::Error>(body)]).chain(stream::pending());
+        let mut chunks = Box::pin(cancellable(
+            chunk_stream(bytes, CompatMode::Strict),
+            Some(token.clone()),
+        ));
+
+        let mut accumulator = ResponseAccumulator::new();
+        accumulator.push(&chunks.next().await.unwrap().unwrap());
+        token.cancel();
+        let last = chunks.next().await.unwrap().unwrap();
+        assert_eq!(last.id, "chatcmpl-1");
+        assert_eq!(
+            last.choices[0].finish_reason.as_deref(),
+            Some(CANCELLED_FINISH_REASON)
+        );
+        accumulator.push(&last);
+        assert!(chunks.next().await.is_none());
+
+        let response = accumulator.finish();
+        assert_eq!(response.choices[0].message.content.text(), "first");
+        assert_eq!(response.choices[0].finish_reason, CANCELLED_FINISH_REASON);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let body = format!("data: {}\n\n", chunk_json("first"));
+        let bytes = stream::iter(vec![Ok::<_, reqwest::Error>(body)]).chain(stream::pending());
+        let timeout = Duration::from_millis(50);
+        let chunks: Vec<_> = chunk_stream(idle_timeout(bytes, Some(timeout)), CompatMode::Strict)
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(matches!(
+            chunks[1],
+            Err(ChatGPTError::StreamStalled { timeout: t }) if t == timeout
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refusal_delta() {
+        let body = r#"data: {"id":"chatcmpl-1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":null,"refusal":"I can't"},"finish_reason":null}]}"#;
+        let bytes = stream::iter(vec![Ok::<_, reqwest::Error>(format!("{body}\n\n"))]);
+        let chunks: Vec<_> = chunk_stream(bytes, CompatMode::Strict).collect().await;
+        let delta = &chunks[0].as_ref().unwrap().choices[0].delta;
+        assert_eq!(delta.refusal.as_deref(), Some("I can't"));
+        assert_eq!(delta.content, None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_response() {
+        let events = [
+            r#"{"id":"c1","object":"chat.completion.chunk","created":7,"model":"gpt-4o","choices":[{"index":0,"delta":{"role":"assistant","content":"Let me "},"finish_reason":null}]}"#,
+            r#"{"id":"c1","object":"chat.completion.chunk","created":7,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"check.","tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"search","arguments":""}}]},"finish_reason":null}]}"#,
+            r#"{"id":"c1","object":"chat.completion.chunk","created":7,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"q\":"}}]},"finish_reason":null}]}"#,
+            r#"{"id":"c1","object":"chat.completion.chunk","created":7,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"rust\"}"}}]},"finish_reason":"tool_calls"}]}"#,
+            r#"{"id":"c1","object":"chat.completion.chunk","created":7,"model":"gpt-4o","system_fingerprint":"fp_1","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":9,"total_tokens":14}}"#,
+        ];
+        let body: String = events
+            .iter()
+            .map(|event| format!("data: {event}\n\n"))
+            .collect();
+        let bytes = stream::iter(vec![Ok::<_, reqwest::Error>(body)]);
+        let response = collect_response(chunk_stream(bytes, CompatMode::Strict))
+            .await
+            .unwrap();
+
+        assert_eq!(response.id, "c1");
+        assert_eq!(
+            response.created_at(),
+            web_time::UNIX_EPOCH + Duration::from_secs(7)
+        );
+        assert_eq!(response.usage.total_tokens, 14);
+        assert_eq!(response.extensions["system_fingerprint"], "fp_1");
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content, "Let me check.");
+        assert_eq!(choice.finish_reason, "tool_calls");
+        assert_eq!(
+            choice.message.tool_calls,
+            [ToolCall {
+                id: "call_1".to_string(),
+                kind: "function".to_string(),
+                function: crate::tools::ToolFunction {
+                    name: "search".to_string(),
+                    arguments: r#"{"q":"rust"}"#.to_string(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_delta_parallel_tool_calls() {
+        use crate::tools::ToolFunctionDelta;
+
+        let call = |index: usize, id: Option<&str>, arguments: &str| ToolCallDelta {
+            index,
+            id: id.map(str::to_string),
+            function: Some(ToolFunctionDelta {
+                name: id.map(|_| format!("f{index}")),
+                arguments: Some(arguments.to_string()),
+            }),
+            ..Default::default()
+        };
+        let mut message = Message::default();
+        for tool_calls in [
+            vec![call(0, Some("a"), "{")],
+            vec![call(1, Some("b"), "{}")],
+            vec![call(0, None, "}")],
+        ] {
+            let delta = Delta {
+                tool_calls,
+                ..Default::default()
+            };
+            merge_delta(&mut message, &delta);
+        }
+        let calls: Vec<(&str, &str, &str)> = message
+            .tool_calls
+            .iter()
+            .map(|call| {
+                let function = &call.function;
+                (
+                    call.id.as_str(),
+                    function.name.as_str(),
+                    function.arguments.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(calls, [("a", "f0", "{}"), ("b", "f1", "{}")]);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_stream_invalid_json() {
+        let bytes = stream::iter(vec![Ok::<_, reqwest::Error>("data: {not json}\n\n")]);
+        let chunks: Vec<_> = chunk_stream(bytes, CompatMode::Strict).collect().await;
+        assert_eq!(chunks.len(), 1);
+        assert!(matches!(chunks[0], Err(ChatGPTError::Json(_))));
+    }
+}
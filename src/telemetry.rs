@@ -0,0 +1,308 @@
+//! Internal instrumentation of client calls.
+//!
+//! With the `tracing` feature every request runs inside a `chat_gpt.request` span that records
+//! the endpoint, model, `x-request-id`, HTTP status, latency and token usage. Without the
+//! feature the helpers compile down to (almost) nothing.
+//!
+//! The `opentelemetry` feature additionally declares the attributes of the OpenTelemetry GenAI
+//! semantic conventions (`gen_ai.request.model`, `gen_ai.usage.input_tokens`, ...) plus the
+//! `otel.*` fields understood by `tracing-opentelemetry`, so exported spans can be ingested by
+//! LLM observability tools as-is.
+
+use crate::client::{ChatGPTError, ChatInput, ChatResponse, Usage};
+use reqwest::Response;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use web_time::{Duration, Instant};
+
+/// Tracks a single request for instrumentation purposes.
+pub(crate) struct RequestSpan {
+    started: Instant,
+    retries: AtomicU32,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl RequestSpan {
+    /// Starts tracking a request to `endpoint` for the given model.
+    pub(crate) fn new(endpoint: &str, model: &impl Display) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (endpoint, model);
+
+        Self {
+            started: Instant::now(),
+            retries: AtomicU32::new(0),
+            #[cfg(feature = "tracing")]
+            span: new_span(endpoint, model),
+        }
+    }
+
+    /// Runs `future` inside the span.
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::Instrument::instrument(future, self.span.clone())
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            future
+        }
+    }
+
+    /// Records the status and `x-request-id` of an HTTP response.
+    pub(crate) fn record_response(&self, response: &Response) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("status", response.status().as_u16());
+            if let Some(request_id) = response
+                .headers()
+                .get("x-request-id")
+                .and_then(|value| value.to_str().ok())
+            {
+                self.span.record("request_id", request_id);
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = response;
+    }
+
+    /// Counts a retry of the request.
+    pub(crate) fn record_retry(&self) {
+        let retries = self.retries.fetch_add(1, Ordering::Relaxed) + 1;
+        #[cfg(feature = "tracing")]
+        self.span.record("retries", retries);
+        #[cfg(not(feature = "tracing"))]
+        let _ = retries;
+    }
+
+    /// The retries of the request so far.
+    pub(crate) fn retries(&self) -> u32 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Records the target URL of a request.
+    pub(crate) fn record_url(&self, url: &str) {
+        #[cfg(feature = "tracing")]
+        if let Some(address) = server_address(url) {
+            self.span.record("server.address", address.as_str());
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = url;
+    }
+
+    /// Records the sampling parameters of a chat request.
+    pub(crate) fn record_chat_request(&self, input: &ChatInput) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(temperature) = input.temperature {
+                self.span.record("gen_ai.request.temperature", temperature);
+            }
+            if let Some(top_p) = input.top_p {
+                self.span.record("gen_ai.request.top_p", top_p);
+            }
+            if let Some(max_tokens) = input.max_tokens {
+                self.span
+                    .record("gen_ai.request.max_tokens", max_tokens as u64);
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = input;
+    }
+
+    /// Records the id, model, finish reasons and token usage of a chat response.
+    pub(crate) fn record_chat_response(&self, response: &ChatResponse) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("gen_ai.response.id", response.id.as_str());
+            self.span
+                .record("gen_ai.response.model", response.model.as_str());
+            let finish_reasons: Vec<&str> = response
+                .choices
+                .iter()
+                .map(|choice| choice.finish_reason.as_str())
+                .collect();
+            self.span.record(
+                "gen_ai.response.finish_reasons",
+                finish_reasons.join(",").as_str(),
+            );
+        }
+        self.record_usage(&response.usage);
+    }
+
+    /// Records the token usage reported by the API.
+    pub(crate) fn record_usage(&self, usage: &Usage) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("prompt_tokens", usage.prompt_tokens);
+            self.span
+                .record("completion_tokens", usage.completion_tokens);
+            self.span.record("total_tokens", usage.total_tokens);
+            self.span
+                .record("gen_ai.usage.input_tokens", usage.prompt_tokens);
+            self.span
+                .record("gen_ai.usage.output_tokens", usage.completion_tokens);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = usage;
+    }
+
+    /// Records the latency and, if the request failed, the error. Returns the latency.
+    pub(crate) fn finish<T>(&self, result: &Result<T, ChatGPTError>) -> Duration {
+        let latency = self.started.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("latency_ms", latency.as_millis() as u64);
+            match result {
+                Ok(_) => tracing::debug!(parent: &self.span, ?latency, "request completed"),
+                Err(err) => {
+                    self.span.record("error", tracing::field::display(err));
+                    self.span.record("error.type", error_type(err));
+                    self.span.record("otel.status_code", "ERROR");
+                    tracing::warn!(parent: &self.span, ?latency, error = %err, "request failed");
+                }
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = result;
+        latency
+    }
+}
+
+/// Creates the span for a request; fields recorded later must be declared here.
+#[cfg(all(feature = "tracing", not(feature = "opentelemetry")))]
+fn new_span(endpoint: &str, model: &impl Display) -> tracing::Span {
+    tracing::info_span!(
+        "chat_gpt.request",
+        endpoint,
+        model = %model,
+        request_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+        retries = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        prompt_tokens = tracing::field::Empty,
+        completion_tokens = tracing::field::Empty,
+        total_tokens = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+/// Creates the span for a request, including the OpenTelemetry GenAI attributes.
+#[cfg(feature = "opentelemetry")]
+fn new_span(endpoint: &str, model: &impl Display) -> tracing::Span {
+    let operation = operation_name(endpoint);
+    tracing::info_span!(
+        "chat_gpt.request",
+        endpoint,
+        model = %model,
+        request_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+        retries = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        prompt_tokens = tracing::field::Empty,
+        completion_tokens = tracing::field::Empty,
+        total_tokens = tracing::field::Empty,
+        error = tracing::field::Empty,
+        "otel.name" = format!("{operation} {model}"),
+        "otel.kind" = "client",
+        "otel.status_code" = tracing::field::Empty,
+        "gen_ai.operation.name" = operation,
+        "gen_ai.provider.name" = "openai",
+        "gen_ai.system" = "openai",
+        "gen_ai.request.model" = %model,
+        "gen_ai.request.temperature" = tracing::field::Empty,
+        "gen_ai.request.top_p" = tracing::field::Empty,
+        "gen_ai.request.max_tokens" = tracing::field::Empty,
+        "gen_ai.response.id" = tracing::field::Empty,
+        "gen_ai.response.model" = tracing::field::Empty,
+        "gen_ai.response.finish_reasons" = tracing::field::Empty,
+        "gen_ai.usage.input_tokens" = tracing::field::Empty,
+        "gen_ai.usage.output_tokens" = tracing::field::Empty,
+        "server.address" = tracing::field::Empty,
+        "error.type" = tracing::field::Empty,
+    )
+}
+
+/// Maps an endpoint path onto the `gen_ai.operation.name` of the semantic conventions.
+#[cfg(feature = "opentelemetry")]
+fn operation_name(endpoint: &str) -> &'static str {
+    if endpoint.ends_with("/embeddings") {
+        "embeddings"
+    } else {
+        "chat"
+    }
+}
+
+/// Extracts the host part of `url` for the `server.address` attribute.
+#[cfg(feature = "tracing")]
+fn server_address(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_string())
+}
+
+/// Low-cardinality description of an error for the `error.type` attribute.
+#[cfg(feature = "tracing")]
+fn error_type(err: &ChatGPTError) -> String {
+    match err {
+        ChatGPTError::RequestFailed { status_code, .. } => status_code.as_u16().to_string(),
+        ChatGPTError::Reqwest(err) if err.is_timeout() => "timeout".to_string(),
+        ChatGPTError::Reqwest(_) => "transport".to_string(),
+        ChatGPTError::Json(_) => "deserialization".to_string(),
+        ChatGPTError::Cancelled => "cancelled".to_string(),
+        ChatGPTError::Io(_) => "io".to_string(),
+        ChatGPTError::Vcr(_) => "vcr".to_string(),
+        ChatGPTError::BudgetExceeded { .. } => "budget_exceeded".to_string(),
+        ChatGPTError::UnpricedModel { .. } => "unpriced_model".to_string(),
+        ChatGPTError::TokenBudgetExceeded { .. } => "token_budget_exceeded".to_string(),
+        ChatGPTError::Credentials(_) => "credentials".to_string(),
+        ChatGPTError::Unsupported(_) => "unsupported".to_string(),
+        ChatGPTError::Invalid(_) => "invalid_request".to_string(),
+        ChatGPTError::CircuitOpen { .. } => "circuit_open".to_string(),
+        ChatGPTError::RateLimited { .. } => "rate_limited".to_string(),
+        ChatGPTError::ContextLengthExceeded { .. } => "context_length_exceeded".to_string(),
+        ChatGPTError::InsufficientQuota { .. } => "insufficient_quota".to_string(),
+        ChatGPTError::InvalidApiKey { .. } => "invalid_api_key".to_string(),
+        ChatGPTError::AccessTerminated { .. } => "access_terminated".to_string(),
+        ChatGPTError::ModelNotFound { .. } => "model_not_found".to_string(),
+        ChatGPTError::DeadlineExceeded { .. } => "deadline_exceeded".to_string(),
+        ChatGPTError::RetryBudgetExhausted { .. } => "retry_budget_exhausted".to_string(),
+        ChatGPTError::StreamStalled { .. } => "stream_stalled".to_string(),
+        ChatGPTError::StreamInterrupted { .. } => "stream_interrupted".to_string(),
+        ChatGPTError::InputRejected(_) => "input_rejected".to_string(),
+        ChatGPTError::OutputRejected(_) => "output_rejected".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Model;
+
+    #[tokio::test]
+    async fn test_request_span_instrument() {
+        let span = RequestSpan::new("/v1/chat/completions", &Model::Gpt_4o);
+        let value = span.instrument(async { 42 }).await;
+        span.finish::<()>(&Err(ChatGPTError::Cancelled));
+
+        assert_eq!(value, 42);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_server_address() {
+        assert_eq!(
+            server_address("https://api.openai.com/v1/chat/completions").as_deref(),
+            Some("api.openai.com")
+        );
+        assert_eq!(server_address("not a url"), None);
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[test]
+    fn test_operation_name() {
+        assert_eq!(operation_name("/v1/chat/completions"), "chat");
+        assert_eq!(operation_name("/v1/embeddings"), "embeddings");
+    }
+}
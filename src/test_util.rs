@@ -0,0 +1,322 @@
+//! Helpers for testing code built on this crate against a [`wiremock`] server.
+//!
+//! Enable the `test-util` feature (usually as a dev-dependency) to get prebuilt matchers and
+//! response templates for chat completions, streaming SSE responses, embeddings, moderations
+//! and OpenAI error bodies.
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::test_util::{chat_completion, chat_input, mock_chat_completions};
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//! use wiremock::MockServer;
+//!
+//! # async fn example() {
+//! let server = MockServer::start().await;
+//! mock_chat_completions()
+//!     .respond_with(chat_completion("Hello from the mock!"))
+//!     .mount(&server)
+//!     .await;
+//!
+//! let client = ChatGPTClient::new("test-key", &server.uri());
+//! let response = client.chat(chat_input()).await.unwrap();
+//! assert_eq!(response.choices[0].message.content, "Hello from the mock!");
+//! # }
+//! ```
+
+use crate::client::{ChatInput, Message};
+use crate::models::Model;
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Match, Mock, MockBuilder, Request, ResponseTemplate};
+
+/// A minimal valid chat request, a user greeting `gpt-4o`.
+pub fn chat_input() -> ChatInput {
+    ChatInput::builder(Model::Gpt_4o)
+        .message(Message::user("Hello!"))
+        .build()
+}
+
+/// Starts a mock for `POST /v1/chat/completions`.
+pub fn mock_chat_completions() -> MockBuilder {
+    Mock::given(method("POST")).and(path("/v1/chat/completions"))
+}
+
+/// Starts a mock for `POST /v1/embeddings`.
+pub fn mock_embeddings() -> MockBuilder {
+    Mock::given(method("POST")).and(path("/v1/embeddings"))
+}
+
+/// Starts a mock for `POST /v1/moderations`.
+pub fn mock_moderations() -> MockBuilder {
+    Mock::given(method("POST")).and(path("/v1/moderations"))
+}
+
+/// Matches requests whose JSON body targets the given model.
+#[derive(Debug, Clone)]
+pub struct ModelMatcher(String);
+
+/// Matches requests whose JSON body has `"model"` set to `model`.
+pub fn body_model(model: Model) -> ModelMatcher {
+    ModelMatcher(model.to_string())
+}
+
+impl Match for ModelMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        json_body(request)
+            .and_then(|body| body.get("model").cloned())
+            .is_some_and(|model| model == self.0.as_str())
+    }
+}
+
+/// Matches requests that ask for a streamed response (`"stream": true`).
+#[derive(Debug, Clone, Copy)]
+pub struct StreamingMatcher;
+
+/// Matches requests with `"stream": true` in their JSON body.
+pub fn streaming() -> StreamingMatcher {
+    StreamingMatcher
+}
+
+impl Match for StreamingMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        json_body(request)
+            .and_then(|body| body.get("stream").and_then(Value::as_bool))
+            .unwrap_or(false)
+    }
+}
+
+fn json_body(request: &Request) -> Option<Value> {
+    serde_json::from_slice(&request.body).ok()
+}
+
+/// The JSON body of a successful, non-streamed chat completion with one assistant message.
+pub fn chat_completion_body(model: &str, content: &str) -> Value {
+    json!({
+        "id": "chatcmpl-test",
+        "object": "chat.completion",
+        "created": 1_700_000_000,
+        "model": model,
+        "usage": {
+            "prompt_tokens": 10,
+            "completion_tokens": 5,
+            "total_tokens": 15
+        },
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+/// A `200 OK` chat completion response with one assistant message.
+pub fn chat_completion(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(chat_completion_body("gpt-4o", content))
+}
+
+/// The JSON body of a successful embeddings response with one embedding per vector.
+pub fn embeddings_body(vectors: &[&[f32]]) -> Value {
+    let data: Vec<Value> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, vector)| json!({"object": "embedding", "embedding": vector, "index": index}))
+        .collect();
+    json!({
+        "object": "list",
+        "data": data,
+        "model": "text-embedding-3-small",
+        "usage": {"prompt_tokens": 8, "total_tokens": 8}
+    })
+}
+
+/// A `200 OK` embeddings response with one embedding per vector.
+pub fn embeddings(vectors: &[&[f32]]) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(embeddings_body(vectors))
+}
+
+/// The JSON body of a moderations response with one result, scoring the named categories
+/// (e.g. `"self-harm/intent"`) as given and all others as `0`.
+///
+/// Categories scoring above `0.5` are flagged.
+pub fn moderation_body(scores: &[(&str, f64)]) -> Value {
+    let categories: serde_json::Map<String, Value> = scores
+        .iter()
+        .map(|(category, score)| (category.to_string(), json!(*score > 0.5)))
+        .collect();
+    let category_scores: serde_json::Map<String, Value> = scores
+        .iter()
+        .map(|(category, score)| (category.to_string(), json!(score)))
+        .collect();
+    json!({
+        "id": "modr-test",
+        "model": "omni-moderation-latest",
+        "results": [{
+            "flagged": scores.iter().any(|(_, score)| *score > 0.5),
+            "categories": categories,
+            "category_scores": category_scores,
+        }]
+    })
+}
+
+/// A `200 OK` moderations response scoring the named categories as given.
+pub fn moderation(scores: &[(&str, f64)]) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(moderation_body(scores))
+}
+
+/// The SSE body of a streamed chat completion emitting `deltas` as content chunks.
+///
+/// The first chunk carries the assistant role, the last one the `stop` finish reason, and the
+/// body ends with the `[DONE]` marker.
+pub fn chat_completion_stream_body(deltas: &[&str]) -> String {
+    let chunk = |delta: Value, finish_reason: Value| {
+        json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion.chunk",
+            "created": 1_700_000_000,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+        })
+    };
+
+    let mut events = vec![chunk(json!({"role": "assistant"}), Value::Null)];
+    events.extend(
+        deltas
+            .iter()
+            .map(|delta| chunk(json!({ "content": delta }), Value::Null)),
+    );
+    events.push(chunk(json!({}), json!("stop")));
+
+    let mut body: String = events
+        .iter()
+        .map(|event| format!("data: {event}\n\n"))
+        .collect();
+    body.push_str("data: [DONE]\n\n");
+    body
+}
+
+/// A `200 OK` `text/event-stream` response streaming `deltas` as content chunks.
+pub fn chat_completion_stream(deltas: &[&str]) -> ResponseTemplate {
+    ResponseTemplate::new(200)
+        .set_body_raw(chat_completion_stream_body(deltas), "text/event-stream")
+}
+
+/// The JSON body of an OpenAI error response.
+pub fn error_body(error_type: &str, code: Option<&str>, message: &str) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": error_type,
+            "param": null,
+            "code": code
+        }
+    })
+}
+
+/// An error response with the given status and OpenAI error body.
+pub fn error_response(
+    status: u16,
+    error_type: &str,
+    code: Option<&str>,
+    message: &str,
+) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(error_body(error_type, code, message))
+}
+
+/// A `429` rate-limit error.
+pub fn rate_limit_exceeded() -> ResponseTemplate {
+    error_response(
+        429,
+        "requests",
+        Some("rate_limit_exceeded"),
+        "Rate limit reached for requests",
+    )
+}
+
+/// A `429` quota error.
+pub fn insufficient_quota() -> ResponseTemplate {
+    error_response(
+        429,
+        "insufficient_quota",
+        Some("insufficient_quota"),
+        "You exceeded your current quota, please check your plan and billing details.",
+    )
+}
+
+/// A `401` invalid API key error.
+pub fn invalid_api_key() -> ResponseTemplate {
+    error_response(
+        401,
+        "invalid_request_error",
+        Some("invalid_api_key"),
+        "Incorrect API key provided.",
+    )
+}
+
+/// A `500` server error.
+pub fn server_error() -> ResponseTemplate {
+    error_response(
+        500,
+        "server_error",
+        None,
+        "The server had an error while processing your request.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatGPTClient;
+    use futures_util::StreamExt;
+    use wiremock::MockServer;
+
+    #[tokio::test]
+    async fn test_chat_completion_mock() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .and(body_model(Model::Gpt_4o))
+            .respond_with(chat_completion("Hello!"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = ChatGPTClient::new("test-key", &server.uri());
+        let response = client.chat(chat_input()).await.unwrap();
+        assert_eq!(response.choices[0].message.content, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_stream_mock() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .and(streaming())
+            .respond_with(chat_completion_stream(&["Hel", "lo"]))
+            .mount(&server)
+            .await;
+
+        let client = ChatGPTClient::new("test-key", &server.uri());
+        let stream = client.chat_stream(chat_input()).await.unwrap();
+        let content: String = stream
+            .map(|chunk| chunk.unwrap().choices[0].delta.content.clone())
+            .filter_map(|content| async move { content })
+            .collect()
+            .await;
+        assert_eq!(content, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_error_response_mock() {
+        let server = MockServer::start().await;
+        mock_chat_completions()
+            .respond_with(invalid_api_key())
+            .mount(&server)
+            .await;
+
+        let client = ChatGPTClient::new("wrong-key", &server.uri());
+        let err = client.chat(chat_input()).await.unwrap_err();
+        match err {
+            crate::client::ChatGPTError::InvalidApiKey { message, .. } => {
+                assert_eq!(message, "Incorrect API key provided.");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+}
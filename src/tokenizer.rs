@@ -1,3 +1,5 @@
+use crate::client::Message;
+
 /// Counts the approximate number of tokens in a string.
 ///
 /// This function provides a rough estimate based on the assumption that
@@ -14,7 +16,55 @@
 /// * An usize representing the approximate number of tokens in `text`.
 pub fn count_tokens(text: &str) -> usize {
     let char_count = text.chars().count();
-    char_count / 4
+    char_count / CHARS_PER_TOKEN
+}
+
+/// Encodes text into the token IDs of a model's tokenizer.
+///
+/// This crate has no tokenizer vocabularies of its own; implement this trait for a BPE
+/// tokenizer, or pass any `Fn(&str) -> Vec<u32>`, to use the token-based helpers such as
+/// [`LogitBias::from_words`](crate::LogitBias::from_words), or to count tokens exactly where
+/// [`count_tokens`] would estimate them, e.g. in
+/// [`TextSplitter::tokenizer`](crate::splitter::TextSplitter::tokenizer).
+pub trait TokenEncoder {
+    /// The token IDs of `text`.
+    fn encode(&self, text: &str) -> Vec<u32>;
+}
+
+impl<F: Fn(&str) -> Vec<u32>> TokenEncoder for F {
+    fn encode(&self, text: &str) -> Vec<u32> {
+        self(text)
+    }
+}
+
+/// The number of characters of English text per token assumed by [`count_tokens`].
+pub(crate) const CHARS_PER_TOKEN: usize = 4;
+
+/// The tokens each message adds to a prompt next to its content, for its role and separators.
+const TOKENS_PER_MESSAGE: usize = 4;
+
+/// Counts the approximate number of prompt tokens of chat messages.
+///
+/// The text content of each message is counted with [`count_tokens`], plus a few tokens per
+/// message for its role and the separators between messages. Images, audio and files are not
+/// counted.
+pub fn count_message_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| count_tokens(&message.content.text()) + TOKENS_PER_MESSAGE)
+        .sum()
+}
+
+/// Counts the prompt tokens of chat messages like [`count_message_tokens`], but with the text
+/// content encoded by `encoder` instead of estimated.
+pub fn count_message_tokens_with(
+    messages: &[Message],
+    encoder: &(impl TokenEncoder + ?Sized),
+) -> usize {
+    messages
+        .iter()
+        .map(|message| encoder.encode(&message.content.text()).len() + TOKENS_PER_MESSAGE)
+        .sum()
 }
 
 #[cfg(test)]
@@ -30,4 +80,14 @@ mod tests {
         );
         assert_eq!(count_tokens(""), 0);
     }
+
+    #[test]
+    fn test_count_message_tokens() {
+        let messages = [Message::system("Be brief."), Message::user("Hello, world!")];
+        assert_eq!(count_message_tokens(&messages), 13);
+        assert_eq!(count_message_tokens(&[]), 0);
+
+        let words = |text: &str| text.split_whitespace().map(|_| 0).collect::<Vec<u32>>();
+        assert_eq!(count_message_tokens_with(&messages, &words), 12);
+    }
 }
@@ -0,0 +1,346 @@
+//! Tool calls made by the model.
+//!
+//! Assistant messages list the functions the model wants to call in
+//! [`Message::tool_calls`](crate::Message::tool_calls); the results are sent back in
+//! [`Message::tool_result`](crate::Message::tool_result) messages answering the calls by ID. In
+//! streamed responses the calls arrive in pieces, as [`ToolCallDelta`]s, which
+//! [`ResponseAccumulator`](crate::stream::ResponseAccumulator) merges into whole calls.
+//! [`ToolFunction::partial_arguments`] parses the arguments received so far, so a UI can show
+//! the call before its arguments are complete.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A call of a function the model makes instead of, or besides, answering.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ToolCall {
+    /// Identifies the call, referenced by the [`Role::Tool`](crate::Role::Tool) message
+    /// answering it.
+    pub id: String,
+    /// The kind of tool, `function`.
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: ToolFunction,
+}
+
+/// The function a [`ToolCall`] calls.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ToolFunction {
+    pub name: String,
+    /// The arguments as a JSON object, which the model may have generated invalid.
+    pub arguments: String,
+}
+
+/// A piece of a [`ToolCall`] in a streamed response.
+///
+/// The first piece of a call carries its ID and function name, the following ones parts of
+/// the arguments.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct ToolCallDelta {
+    /// The position of the call among the calls of the message.
+    #[serde(default)]
+    pub index: usize,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub function: Option<ToolFunctionDelta>,
+}
+
+/// A piece of the function of a [`ToolCallDelta`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct ToolFunctionDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+impl ToolFunction {
+    /// The arguments parsed so far, while they are still streaming.
+    ///
+    /// See [`parse_partial_json`].
+    pub fn partial_arguments(&self) -> Option<Value> {
+        parse_partial_json(&self.arguments)
+    }
+}
+
+/// Parses the beginning of a JSON document, as if it was closed where it ends.
+///
+/// Open strings, arrays and objects are closed and incomplete literals are completed, so
+/// `{"query": "rust str` parses as `{"query": "rust str"}`. Object keys without a value yet
+/// are left out. Returns `None` if the text is not the beginning of a JSON document or has no
+/// value yet.
+///
+/// # Examples
+///
+/// ```
+/// use chat_gpt_lib_rs::tools::parse_partial_json;
+/// use serde_json::json;
+///
+/// assert_eq!(
+///     parse_partial_json(r#"{"query": "weather in Par"#),
+///     Some(json!({"query": "weather in Par"}))
+/// );
+/// assert_eq!(
+///     parse_partial_json(r#"{"limit": 10, "tags": ["a", tr"#),
+///     Some(json!({"limit": 10, "tags": ["a", true]}))
+/// );
+/// ```
+pub fn parse_partial_json(text: &str) -> Option<Value> {
+    let mut parser = PartialJson {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value().ok()??;
+    parser.skip_whitespace();
+    parser.at_end().then_some(value)
+}
+
+/// A parser of the beginning of a JSON document.
+struct PartialJson {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+/// The text is not the beginning of a JSON document.
+struct Invalid;
+
+impl PartialJson {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// The next value, or `None` if the text ends before it starts.
+    fn value(&mut self) -> Result<Option<Value>, Invalid> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(None),
+            Some('{') => self.object().map(Some),
+            Some('[') => self.array().map(Some),
+            Some('"') => Ok(Some(Value::String(self.string()?.0))),
+            Some('t' | 'f' | 'n') => self.literal(),
+            Some('-' | '0'..='9') => Ok(self.number()),
+            Some(_) => Err(Invalid),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Invalid> {
+        self.pos += 1;
+        let mut object = Map::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Value::Object(object)),
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(object));
+                }
+                Some('"') => {}
+                Some(_) => return Err(Invalid),
+            }
+            let (key, complete) = self.string()?;
+            self.skip_whitespace();
+            if !complete || self.at_end() {
+                return Ok(Value::Object(object));
+            }
+            if self.peek() != Some(':') {
+                return Err(Invalid);
+            }
+            self.pos += 1;
+            let Some(value) = self.value()? else {
+                return Ok(Value::Object(object));
+            };
+            object.insert(key, value);
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Value::Object(object)),
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                Some(_) => return Err(Invalid),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Invalid> {
+        self.pos += 1;
+        let mut array = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(array));
+            }
+            let Some(value) = self.value()? else {
+                return Ok(Value::Array(array));
+            };
+            array.push(value);
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Value::Array(array)),
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                Some(_) => return Err(Invalid),
+            }
+        }
+    }
+
+    /// A string, and whether its closing quote was reached.
+    fn string(&mut self) -> Result<(String, bool), Invalid> {
+        self.pos += 1;
+        let mut string = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' => return Ok((string, true)),
+                '\\' => match self.escape()? {
+                    Some(c) => string.push(c),
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        Ok((string, false))
+    }
+
+    /// The character of the escape sequence after a backslash, or `None` if the text ends
+    /// within it.
+    fn escape(&mut self) -> Result<Option<char>, Invalid> {
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        self.pos += 1;
+        Ok(Some(match c {
+            '"' | '\\' | '/' => c,
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let Some(high) = self.hex()? else {
+                    return Ok(None);
+                };
+                if (0xD800..0xDC00).contains(&high)
+                    && self.chars[self.pos..].starts_with(&['\\', 'u'])
+                {
+                    self.pos += 2;
+                    let Some(low) = self.hex()? else {
+                        return Ok(None);
+                    };
+                    let code =
+                        0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                } else {
+                    char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+            }
+            _ => return Err(Invalid),
+        }))
+    }
+
+    /// The four hex digits of a `\u` escape, or `None` if the text ends within them.
+    fn hex(&mut self) -> Result<Option<u32>, Invalid> {
+        let digits: String = self.chars[self.pos..].iter().take(4).collect();
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Invalid);
+        }
+        if digits.len() < 4 {
+            self.pos = self.chars.len();
+            return Ok(None);
+        }
+        self.pos += 4;
+        Ok(u32::from_str_radix(&digits, 16).ok())
+    }
+
+    /// `true`, `false` or `null`, possibly cut off.
+    fn literal(&mut self) -> Result<Option<Value>, Invalid> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        let complete = !self.at_end();
+        [
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+            ("null", Value::Null),
+        ]
+        .into_iter()
+        .find(|(literal, _)| {
+            if complete {
+                *literal == word
+            } else {
+                literal.starts_with(&word)
+            }
+        })
+        .map(|(_, value)| Some(value))
+        .ok_or(Invalid)
+    }
+
+    /// A number, with an incomplete fraction or exponent at the end of the text left out.
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        let number = number.trim_end_matches(['-', '+', '.', 'e', 'E']);
+        serde_json::from_str(number).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_partial_json() {
+        let arguments = r#"{"query": "café \"best\"", "limit": 12.5, "filters": {"open": true}, "tags": ["a"]}"#;
+        let expected: Value = serde_json::from_str(arguments).unwrap();
+        // Every prefix parses as an object.
+        for (end, _) in arguments.char_indices().skip(1) {
+            let prefix = &arguments[..end];
+            assert!(
+                parse_partial_json(prefix).is_some_and(|value| value.is_object()),
+                "{prefix}"
+            );
+        }
+        assert_eq!(parse_partial_json(arguments), Some(expected));
+
+        assert_eq!(parse_partial_json(""), None);
+        assert_eq!(parse_partial_json(r#"{"query"#), Some(json!({})));
+        assert_eq!(parse_partial_json(r#"{"query":"#), Some(json!({})));
+        assert_eq!(
+            parse_partial_json(r#"{"query": "caf\u00"#),
+            Some(json!({"query": "caf"}))
+        );
+        assert_eq!(parse_partial_json(r#"[1, 2."#), Some(json!([1, 2])));
+        assert_eq!(parse_partial_json(r#"{"a": nul"#), Some(json!({"a": null})));
+        assert_eq!(parse_partial_json(r#"{"a": nope}"#), None);
+        assert_eq!(parse_partial_json(r#"{"a": 1} x"#), None);
+    }
+}
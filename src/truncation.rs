@@ -0,0 +1,274 @@
+//! Truncation of message histories that exceed a model's context window.
+//!
+//! With a [`TruncationStrategy`] set through
+//! [`ChatGPTClientBuilder::truncation`](crate::ChatGPTClientBuilder::truncation), the client
+//! drops messages from chat requests whose history would not fit into the context window of
+//! the model, [`Model::context_window`](crate::Model::context_window) minus the `max_tokens`
+//! reserved for the answer. Messages are counted with [`count_message_tokens`], so the limit is
+//! an estimate, unless the model's tokenizer is set with
+//! [`ChatGPTClientBuilder::tokenizer`](crate::ChatGPTClientBuilder::tokenizer) or passed to
+//! [`TruncationStrategy::truncate_with`].
+//!
+//! The context window of a [`Model::Other`](crate::Model::Other) is only known if its name is
+//! a snapshot of a known model; other models are left alone unless the window is given with
+//! [`ChatGPTClientBuilder::context_window`](crate::ChatGPTClientBuilder::context_window) or
+//! [`TruncationStrategy::apply_with_context`].
+//!
+//! The most recent message is always kept, and other tool results are never kept without the
+//! messages before them.
+//!
+//! # Examples
+//!
+//! ```
+//! use chat_gpt_lib_rs::truncation::TruncationStrategy;
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//!
+//! let client = ChatGPTClient::builder("your_api_key", "https://api.openai.com")
+//!     .truncation(TruncationStrategy::KeepInstructions)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use crate::client::{ChatInput, Message};
+use crate::models::{Model, Role};
+use crate::tokenizer::{count_message_tokens, count_message_tokens_with, TokenEncoder};
+
+/// Which messages are dropped from a history that is too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Drops the oldest messages, including instructions, until the history fits.
+    DropOldest,
+    /// Keeps the `system` and `developer` messages and drops the oldest of the others until
+    /// the history fits.
+    KeepInstructions,
+    /// Keeps the `system` and `developer` messages and as many of the most recent others as
+    /// fit into `max_tokens`, even if the model's context window would hold more.
+    SlidingWindow { max_tokens: usize },
+}
+
+impl TruncationStrategy {
+    /// Drops messages from `input` as this strategy dictates, returning how many were dropped.
+    ///
+    /// Only the [`SlidingWindow`](Self::SlidingWindow) is applied to models whose context
+    /// window is unknown.
+    pub fn apply(&self, input: &mut ChatInput) -> usize {
+        self.apply_counted(input, None, &count_message_tokens)
+    }
+
+    /// Drops messages from `input` as this strategy dictates, assuming a context window of
+    /// `context_window` tokens, returning how many were dropped.
+    pub fn apply_with_context(&self, input: &mut ChatInput, context_window: usize) -> usize {
+        self.apply_counted(input, Some(context_window), &count_message_tokens)
+    }
+
+    /// Drops messages from `input` to fit into `context_window`, or else the context window
+    /// of its model, counting their tokens with `count`.
+    pub(crate) fn apply_counted(
+        &self,
+        input: &mut ChatInput,
+        context_window: Option<usize>,
+        count: &dyn Fn(&[Message]) -> usize,
+    ) -> usize {
+        let context = match context_window.or_else(|| known_context_window(&input.model)) {
+            Some(context_window) => context_window.saturating_sub(input.max_tokens.unwrap_or(0)),
+            None => usize::MAX,
+        };
+        self.truncate_counted(&mut input.messages, context, count)
+    }
+
+    /// Drops messages from `messages` until they fit into `max_tokens`, returning how many
+    /// were dropped.
+    pub fn truncate(&self, messages: &mut Vec<Message>, max_tokens: usize) -> usize {
+        self.truncate_counted(messages, max_tokens, &count_message_tokens)
+    }
+
+    /// Drops messages from `messages` like [`TruncationStrategy::truncate`], but with their
+    /// tokens counted exactly by `encoder`, the tokenizer of the model.
+    pub fn truncate_with(
+        &self,
+        messages: &mut Vec<Message>,
+        max_tokens: usize,
+        encoder: &(impl TokenEncoder + ?Sized),
+    ) -> usize {
+        self.truncate_counted(messages, max_tokens, &|messages| {
+            count_message_tokens_with(messages, encoder)
+        })
+    }
+
+    /// Drops messages from `messages` until they fit into `max_tokens` as counted by `count`.
+    pub(crate) fn truncate_counted(
+        &self,
+        messages: &mut Vec<Message>,
+        max_tokens: usize,
+        count: &dyn Fn(&[Message]) -> usize,
+    ) -> usize {
+        let (keep_instructions, max_tokens) = match *self {
+            Self::DropOldest => (false, max_tokens),
+            Self::KeepInstructions => (true, max_tokens),
+            Self::SlidingWindow { max_tokens: window } => (true, window.min(max_tokens)),
+        };
+        let is_pinned = |message: &Message| keep_instructions && message.role.is_instructions();
+
+        let mut tokens = count(messages);
+        let mut keep = vec![true; messages.len()];
+        let mut oldest = 0;
+        while tokens > max_tokens {
+            // The most recent message is never dropped.
+            let Some(index) = (oldest..messages.len().saturating_sub(1))
+                .find(|&index| !is_pinned(&messages[index]))
+            else {
+                break;
+            };
+            keep[index] = false;
+            tokens -= count(&messages[index..=index]);
+            oldest = index + 1;
+        }
+        // Tool results whose preceding messages were dropped would be rejected by the API, but
+        // the most recent message is kept even so.
+        while oldest > 0 && oldest + 1 < messages.len() && messages[oldest].role == Role::Tool {
+            keep[oldest] = false;
+            oldest += 1;
+        }
+
+        let before = messages.len();
+        let mut keep = keep.into_iter();
+        messages.retain(|_| keep.next().unwrap_or(true));
+        before - messages.len()
+    }
+}
+
+/// The context window of `model`, or of the model a snapshot name belongs to, if known.
+fn known_context_window(model: &Model) -> Option<usize> {
+    match model {
+        Model::Other(name) => Model::family_of(name).map(|model| model.context_window()),
+        model => Some(model.context_window()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ToolCall, ToolFunction};
+
+    fn history() -> Vec<Message> {
+        vec![
+            Message::system("Be brief."),
+            Message::user("a".repeat(400)),
+            Message::assistant("b".repeat(400)),
+            Message::tool_result("call_1", "c".repeat(400)),
+            Message::user("Hi"),
+        ]
+    }
+
+    fn roles(messages: &[Message]) -> Vec<Role> {
+        messages
+            .iter()
+            .map(|message| message.role.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut messages = history();
+        assert_eq!(
+            TruncationStrategy::DropOldest.truncate(&mut messages, 1000),
+            0
+        );
+        assert_eq!(messages.len(), 5);
+
+        assert_eq!(
+            TruncationStrategy::DropOldest.truncate(&mut messages, 150),
+            4
+        );
+        assert_eq!(roles(&messages), [Role::User]);
+
+        let mut messages = history();
+        TruncationStrategy::KeepInstructions.truncate(&mut messages, 150);
+        assert_eq!(roles(&messages), [Role::System, Role::User]);
+
+        let mut messages = history();
+        TruncationStrategy::SlidingWindow { max_tokens: 120 }.truncate(&mut messages, 1000);
+        assert_eq!(roles(&messages), [Role::System, Role::User]);
+    }
+
+    #[test]
+    fn test_truncate_keeps_trailing_tool_result() {
+        let mut messages = vec![
+            Message::user("a".repeat(400)),
+            Message {
+                role: Role::Assistant,
+                tool_calls: vec![ToolCall {
+                    id: "call_1".to_string(),
+                    function: ToolFunction {
+                        name: "lookup".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            Message::tool_result("call_1", "Found it."),
+        ];
+        assert_eq!(TruncationStrategy::DropOldest.truncate(&mut messages, 5), 2);
+        assert_eq!(roles(&messages), [Role::Tool]);
+    }
+
+    #[test]
+    fn test_truncate_with_tokenizer() {
+        // One token per word, far fewer than the estimate for the long messages.
+        let words = |text: &str| text.split_whitespace().map(|_| 0).collect::<Vec<u32>>();
+        let mut messages = history();
+        assert_eq!(
+            TruncationStrategy::DropOldest.truncate_with(&mut messages, 30, &words),
+            0
+        );
+        assert_eq!(
+            TruncationStrategy::DropOldest.truncate(&mut messages, 30),
+            4
+        );
+    }
+
+    #[test]
+    fn test_apply_reserves_answer_tokens() {
+        let mut input = ChatInput {
+            model: Model::Gpt_4,
+            messages: vec![Message::user("a".repeat(16000)), Message::user("Hi")],
+            ..Default::default()
+        };
+        assert_eq!(TruncationStrategy::DropOldest.apply(&mut input), 0);
+        input.max_tokens = Some(5000);
+        assert_eq!(TruncationStrategy::DropOldest.apply(&mut input), 1);
+    }
+
+    #[test]
+    fn test_apply_leaves_unknown_models_alone() {
+        let mut input = ChatInput {
+            model: Model::Other("gpt-4.1".to_string()),
+            messages: vec![Message::user("a".repeat(40000)), Message::user("Hi")],
+            max_tokens: Some(5000),
+            ..Default::default()
+        };
+        assert_eq!(TruncationStrategy::DropOldest.apply(&mut input), 0);
+        assert_eq!(
+            TruncationStrategy::DropOldest.apply_with_context(&mut input, 8000),
+            1
+        );
+
+        let mut input = ChatInput {
+            model: Model::Other("gpt-4-0613".to_string()),
+            messages: vec![Message::user("a".repeat(40000)), Message::user("Hi")],
+            ..Default::default()
+        };
+        assert_eq!(TruncationStrategy::DropOldest.apply(&mut input), 1);
+
+        let mut input = ChatInput {
+            model: Model::Other("llama3.2".to_string()),
+            messages: history(),
+            ..Default::default()
+        };
+        assert_eq!(
+            TruncationStrategy::SlidingWindow { max_tokens: 120 }.apply(&mut input),
+            3
+        );
+    }
+}
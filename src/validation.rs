@@ -0,0 +1,257 @@
+//! Checks of chat requests before they are sent.
+//!
+//! [`ChatInput::validate`](crate::ChatInput::validate) finds mistakes the API would reject
+//! with a `400 Bad Request`, or silently ignore, such as an image sent to a model that does not
+//! see images. The client runs it before sending every chat request and fails with
+//! `ChatGPTError::Invalid`, unless turned off with
+//! [`ChatGPTClientBuilder::validate_requests`](crate::ChatGPTClientBuilder::validate_requests).
+//!
+//! Only what this crate knows about a model is checked, so requests to [`Model::Other`] skip
+//! the model-specific checks.
+//!
+//! # Examples
+//!
+//! ```
+//! use chat_gpt_lib_rs::content::ContentPart;
+//! use chat_gpt_lib_rs::validation::ValidationError;
+//! use chat_gpt_lib_rs::{ChatInput, Message, Model};
+//!
+//! let input = ChatInput::builder(Model::Gpt3_5Turbo)
+//!     .message(Message::user(vec![ContentPart::image_url("https://example.com/cat.png")]))
+//!     .build();
+//! assert!(matches!(
+//!     input.validate(&Model::Gpt3_5Turbo),
+//!     Err(ValidationError::ImagesNotSupported { index: 0, .. })
+//! ));
+//! assert!(input.validate(&Model::Gpt_4o).is_ok());
+//! ```
+
+use crate::audio::Modality;
+use crate::client::{ChatInput, Message};
+use crate::content::{Content, ContentPart};
+use crate::models::{BiasOutOfRange, Deprecation, Model};
+use crate::sampling::{OutOfRange, PenaltyValue, Temperature, TopP};
+use thiserror::Error;
+
+/// A mistake in a chat request found before it was sent.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    #[error("The request has no messages")]
+    NoMessages,
+    #[error("Message {index} has no content")]
+    EmptyMessage { index: usize },
+    #[error("Message {index} has an image, but {model} does not accept images")]
+    ImagesNotSupported { index: usize, model: Model },
+    #[error("The request has tools, but {model} does not call tools")]
+    ToolsNotSupported { model: Model },
+    #[error("max_tokens of {max_tokens} exceeds the {limit} tokens {model} can produce")]
+    MaxTokensExceeded {
+        max_tokens: usize,
+        limit: usize,
+        model: Model,
+    },
+    #[error(transparent)]
+    OutOfRange(#[from] OutOfRange),
+    #[error(transparent)]
+    LogitBias(#[from] BiasOutOfRange),
+    /// The request went to a deprecated model, while rejecting them with
+    /// [`ChatGPTClientBuilder::reject_deprecated_models`](crate::ChatGPTClientBuilder::reject_deprecated_models).
+    #[error("{model} is deprecated and {deprecation}")]
+    DeprecatedModel {
+        model: Model,
+        deprecation: Deprecation,
+    },
+    /// Parameters that contradict each other, e.g. `n` of `0`.
+    #[error("Conflicting parameters: {0}")]
+    Conflict(&'static str),
+}
+
+/// Checks `input` as a request to `model`, returning the first mistake found.
+pub(crate) fn validate(input: &ChatInput, model: &Model) -> Result<(), ValidationError> {
+    if input.messages.is_empty() {
+        return Err(ValidationError::NoMessages);
+    }
+    for (index, message) in input.messages.iter().enumerate() {
+        if is_empty(message) {
+            return Err(ValidationError::EmptyMessage { index });
+        }
+        if !model.supports_images() && has_image(&message.content) {
+            return Err(ValidationError::ImagesNotSupported {
+                index,
+                model: model.clone(),
+            });
+        }
+    }
+    if !model.supports_tools() && has_tools(input) {
+        return Err(ValidationError::ToolsNotSupported {
+            model: model.clone(),
+        });
+    }
+    if let (Some(max_tokens), false) = (input.max_tokens, matches!(model, Model::Other(_))) {
+        let limit = model.max_output_tokens();
+        if max_tokens > limit {
+            return Err(ValidationError::MaxTokensExceeded {
+                max_tokens,
+                limit,
+                model: model.clone(),
+            });
+        }
+    }
+
+    if let Some(temperature) = input.temperature {
+        Temperature::new(temperature)?;
+    }
+    if let Some(top_p) = input.top_p {
+        TopP::new(top_p)?;
+    }
+    for penalty in [input.presence_penalty, input.frequency_penalty]
+        .into_iter()
+        .flatten()
+    {
+        PenaltyValue::new(penalty)?;
+    }
+    if let Some(logit_bias) = &input.logit_bias {
+        logit_bias.validate()?;
+    }
+
+    if input.n == Some(0) {
+        return Err(ValidationError::Conflict("n has to be at least 1"));
+    }
+    let audio_output = input
+        .modalities
+        .as_ref()
+        .is_some_and(|modalities| modalities.contains(&Modality::Audio));
+    if input.audio.is_some() && !audio_output {
+        return Err(ValidationError::Conflict(
+            "audio is set, but modalities do not include audio",
+        ));
+    }
+    #[cfg(feature = "legacy-functions")]
+    if input.function_call.is_some() && input.functions.is_none() {
+        return Err(ValidationError::Conflict(
+            "function_call is set, but there are no functions",
+        ));
+    }
+    if input.extra.contains_key("tool_choice") && !input.extra.contains_key("tools") {
+        return Err(ValidationError::Conflict(
+            "tool_choice is set, but there are no tools",
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `message` carries nothing: no content, tool calls, audio or refusal.
+fn is_empty(message: &Message) -> bool {
+    let no_content = match &message.content {
+        Content::Text(text) => text.is_empty(),
+        Content::Parts(parts) => parts.is_empty(),
+        Content::None => true,
+    };
+    #[cfg(feature = "legacy-functions")]
+    let no_content = no_content && message.function_call.is_none();
+    no_content
+        && message.tool_calls.is_empty()
+        && message.audio.is_none()
+        && message.refusal.is_none()
+}
+
+fn has_image(content: &Content) -> bool {
+    match content {
+        Content::Parts(parts) => parts
+            .iter()
+            .any(|part| matches!(part, ContentPart::ImageUrl { .. })),
+        _ => false,
+    }
+}
+
+fn has_tools(input: &ChatInput) -> bool {
+    #[cfg(feature = "legacy-functions")]
+    if input.functions.is_some() {
+        return true;
+    }
+    input.extra.contains_key("tools")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AudioFormat, AudioOutput};
+    use crate::models::LogitBias;
+    use serde_json::json;
+
+    fn input() -> crate::ChatInputBuilder {
+        ChatInput::builder(Model::Gpt_4o).message(Message::user("Hi"))
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(input().build().validate(&Model::Gpt_4o), Ok(()));
+        assert_eq!(
+            ChatInput::builder(Model::Gpt_4o)
+                .build()
+                .validate(&Model::Gpt_4o),
+            Err(ValidationError::NoMessages)
+        );
+        assert_eq!(
+            input()
+                .message(Message::assistant(""))
+                .build()
+                .validate(&Model::Gpt_4o),
+            Err(ValidationError::EmptyMessage { index: 1 })
+        );
+
+        let tools = input().extra("tools", json!([])).build();
+        assert_eq!(tools.validate(&Model::Gpt_4o), Ok(()));
+        assert_eq!(
+            tools.validate(&Model::Gpt_4Turbo_Vision),
+            Err(ValidationError::ToolsNotSupported {
+                model: Model::Gpt_4Turbo_Vision
+            })
+        );
+        assert_eq!(
+            input()
+                .extra("tool_choice", json!("auto"))
+                .build()
+                .validate(&Model::Gpt_4o),
+            Err(ValidationError::Conflict(
+                "tool_choice is set, but there are no tools"
+            ))
+        );
+
+        let long = input().max_tokens(20_000).build();
+        assert!(matches!(
+            long.validate(&Model::Gpt_4o),
+            Err(ValidationError::MaxTokensExceeded { limit: 16384, .. })
+        ));
+        assert_eq!(long.validate(&Model::Other("llama3.2".to_string())), Ok(()));
+
+        assert!(matches!(
+            input().temperature(3.0).build().validate(&Model::Gpt_4o),
+            Err(ValidationError::OutOfRange(OutOfRange {
+                parameter: "temperature",
+                ..
+            }))
+        ));
+        let mut logit_bias = LogitBias::new();
+        logit_bias.biases.insert(1, 200.0);
+        assert!(matches!(
+            input()
+                .logit_bias(logit_bias)
+                .build()
+                .validate(&Model::Gpt_4o),
+            Err(ValidationError::LogitBias(_))
+        ));
+        assert!(matches!(
+            input().n(0).build().validate(&Model::Gpt_4o),
+            Err(ValidationError::Conflict(_))
+        ));
+        assert!(matches!(
+            input()
+                .audio(AudioOutput::new("alloy", AudioFormat::Wav))
+                .build()
+                .validate(&Model::Gpt_4o),
+            Err(ValidationError::Conflict(_))
+        ));
+    }
+}
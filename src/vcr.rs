@@ -0,0 +1,305 @@
+//! Record/replay ("VCR") mode for deterministic, offline integration tests.
+//!
+//! In record mode the client talks to the real API and appends every request/response pair to
+//! a JSON cassette file. In replay mode the client never touches the network and serves the
+//! recorded responses back instead, matching requests by method, path and JSON body.
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::vcr::Vcr;
+//! use chat_gpt_lib_rs::ChatGPTClient;
+//!
+//! // Run once with a real key to record the fixture ...
+//! let recording = ChatGPTClient::builder("sk-...", "https://api.openai.com")
+//!     .vcr(Vcr::record("tests/fixtures/chat.json"))
+//!     .build()
+//!     .unwrap();
+//!
+//! // ... and replay it in CI without network access or credentials.
+//! let replaying = ChatGPTClient::builder("unused", "https://api.openai.com")
+//!     .vcr(Vcr::replay("tests/fixtures/chat.json").unwrap())
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! The `Authorization` header and other request headers are never written to the cassette.
+
+use crate::client::ChatGPTError;
+use reqwest::{Client, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A recorded request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and query of the request URL; the host is not recorded so cassettes can be
+    /// replayed against any base URL.
+    pub path: String,
+    pub body: Option<serde_json::Value>,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+/// A single recorded request/response pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// The contents of a cassette file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+/// Whether a [`Vcr`] records new interactions or replays existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcrMode {
+    Record,
+    Replay,
+}
+
+/// Records or replays HTTP interactions to or from a cassette file.
+#[derive(Debug)]
+pub struct Vcr {
+    mode: VcrMode,
+    path: PathBuf,
+    state: Mutex<VcrState>,
+}
+
+#[derive(Debug, Default)]
+struct VcrState {
+    cassette: Cassette,
+    /// Replay mode only: which interactions have already been served.
+    used: Vec<bool>,
+}
+
+impl Vcr {
+    /// Creates a recorder that writes all interactions to `path`, replacing its contents.
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Self {
+            mode: VcrMode::Record,
+            path: path.as_ref().to_path_buf(),
+            state: Mutex::new(VcrState::default()),
+        }
+    }
+
+    /// Loads the cassette at `path` for replaying.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatGPTError::Vcr` if the file cannot be read or parsed.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, ChatGPTError> {
+        let path = path.as_ref().to_path_buf();
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| ChatGPTError::Vcr(format!("reading {}: {err}", path.display())))?;
+        let cassette: Cassette = serde_json::from_str(&contents)
+            .map_err(|err| ChatGPTError::Vcr(format!("parsing {}: {err}", path.display())))?;
+        Ok(Self::from_cassette(cassette, path))
+    }
+
+    /// Creates a replayer serving the given in-memory cassette.
+    pub fn from_cassette(cassette: Cassette, path: impl AsRef<Path>) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            mode: VcrMode::Replay,
+            path: path.as_ref().to_path_buf(),
+            state: Mutex::new(VcrState { cassette, used }),
+        }
+    }
+
+    /// The mode of this VCR.
+    pub fn mode(&self) -> VcrMode {
+        self.mode
+    }
+
+    /// A copy of the interactions recorded or loaded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.state.lock().unwrap().cassette.clone()
+    }
+
+    /// Executes `request`, either through `client` while recording, or from the cassette.
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        request: Request,
+    ) -> Result<Response, ChatGPTError> {
+        let recorded_request = recorded_request(&request);
+        match self.mode {
+            VcrMode::Replay => {
+                let recorded_response = self.find(&recorded_request)?;
+                into_response(&recorded_response)
+            }
+            VcrMode::Record => {
+                let response = client.execute(request).await?;
+                let status = response.status().as_u16();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect();
+                let body = response.text().await?;
+                let recorded_response = RecordedResponse {
+                    status,
+                    headers,
+                    body,
+                };
+                self.store(Interaction {
+                    request: recorded_request,
+                    response: recorded_response.clone(),
+                })?;
+                into_response(&recorded_response)
+            }
+        }
+    }
+
+    /// Returns the first not yet served interaction matching `request`.
+    fn find(&self, request: &RecordedRequest) -> Result<RecordedResponse, ChatGPTError> {
+        let mut state = self.state.lock().unwrap();
+        let VcrState { cassette, used } = &mut *state;
+        let index = cassette
+            .interactions
+            .iter()
+            .zip(used.iter())
+            .position(|(interaction, used)| !used && interaction.request == *request)
+            .ok_or_else(|| {
+                ChatGPTError::Vcr(format!(
+                    "no unused interaction in {} matches {} {}",
+                    self.path.display(),
+                    request.method,
+                    request.path
+                ))
+            })?;
+        used[index] = true;
+        Ok(cassette.interactions[index].response.clone())
+    }
+
+    /// Appends `interaction` to the cassette and rewrites the cassette file.
+    fn store(&self, interaction: Interaction) -> Result<(), ChatGPTError> {
+        let mut state = self.state.lock().unwrap();
+        state.cassette.interactions.push(interaction);
+        let contents = serde_json::to_string_pretty(&state.cassette)?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                ChatGPTError::Vcr(format!("creating {}: {err}", parent.display()))
+            })?;
+        }
+        fs::write(&self.path, contents)
+            .map_err(|err| ChatGPTError::Vcr(format!("writing {}: {err}", self.path.display())))
+    }
+}
+
+fn recorded_request(request: &Request) -> RecordedRequest {
+    let url = request.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .and_then(|bytes| serde_json::from_slice(bytes).ok());
+    RecordedRequest {
+        method: request.method().to_string(),
+        path,
+        body,
+    }
+}
+
+fn into_response(recorded: &RecordedResponse) -> Result<Response, ChatGPTError> {
+    let mut builder = http::Response::builder().status(recorded.status);
+    for (name, value) in &recorded.headers {
+        builder = builder.header(name, value);
+    }
+    let response = builder
+        .body(recorded.body.clone())
+        .map_err(|err| ChatGPTError::Vcr(format!("invalid recorded response: {err}")))?;
+    Ok(Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: &str) -> Request {
+        Client::new()
+            .post("https://api.openai.com/v1/chat/completions?x=1")
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .build()
+            .unwrap()
+    }
+
+    fn interaction(body: serde_json::Value, response_body: &str) -> Interaction {
+        Interaction {
+            request: RecordedRequest {
+                method: "POST".to_string(),
+                path: "/v1/chat/completions?x=1".to_string(),
+                body: Some(body),
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: BTreeMap::from([(
+                    "content-type".to_string(),
+                    "application/json".to_string(),
+                )]),
+                body: response_body.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_recorded_request_ignores_host_and_formatting() {
+        let recorded = recorded_request(&request(r#"{ "model": "gpt-4o" }"#));
+        assert_eq!(recorded.method, "POST");
+        assert_eq!(recorded.path, "/v1/chat/completions?x=1");
+        assert_eq!(recorded.body, Some(serde_json::json!({"model": "gpt-4o"})));
+    }
+
+    #[tokio::test]
+    async fn test_replay_serves_matching_interactions_in_order() {
+        let body = serde_json::json!({"model": "gpt-4o"});
+        let cassette = Cassette {
+            interactions: vec![
+                interaction(body.clone(), "first"),
+                interaction(serde_json::json!({"model": "gpt-4"}), "other"),
+                interaction(body, "second"),
+            ],
+        };
+        let vcr = Vcr::from_cassette(cassette, "memory.json");
+        let client = Client::new();
+
+        for expected in ["first", "second"] {
+            let response = vcr
+                .execute(&client, request(r#"{"model":"gpt-4o"}"#))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers()["content-type"], "application/json");
+            assert_eq!(response.text().await.unwrap(), expected);
+        }
+
+        let exhausted = vcr.execute(&client, request(r#"{"model":"gpt-4o"}"#)).await;
+        assert!(matches!(exhausted, Err(ChatGPTError::Vcr(_))));
+    }
+
+    #[test]
+    fn test_replay_missing_file() {
+        assert!(matches!(
+            Vcr::replay("does/not/exist.json"),
+            Err(ChatGPTError::Vcr(_))
+        ));
+    }
+}
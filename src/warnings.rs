@@ -0,0 +1,218 @@
+//! Problems with a chat response that do not fail the request.
+//!
+//! [`ChatGPTClient::chat`](crate::ChatGPTClient::chat) returns responses that are not quite
+//! what was asked for, such as a completion cut off at `max_tokens`, or one served by another
+//! model than the requested. It lists these problems in
+//! [`ChatResponse::warnings`](crate::ChatResponse::warnings), so callers can check for them
+//! without handling errors.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chat_gpt_lib_rs::warnings::Warning;
+//! use chat_gpt_lib_rs::{ChatGPTClient, ChatInput, Message, Model};
+//!
+//! # async fn run() -> Result<(), chat_gpt_lib_rs::client::ChatGPTError> {
+//! let client = ChatGPTClient::new("your_api_key", "https://api.openai.com");
+//! let input = ChatInput::builder(Model::Gpt_4o)
+//!     .message(Message::user("Write a long story."))
+//!     .max_tokens(100)
+//!     .build();
+//! let response = client.chat(input).await?;
+//! for warning in &response.warnings {
+//!     if let Warning::Truncated { .. } = warning {
+//!         eprintln!("the story is incomplete");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{ChatInput, ChatResponse};
+use crate::models::{Deprecation, Model};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A problem with a chat response that did not fail the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The completion of the choice at `index` was cut off at `max_tokens` or the end of the
+    /// context window.
+    Truncated { index: usize },
+    /// The completion of the choice at `index` was cut off by the content filter.
+    ContentFiltered { index: usize },
+    /// The request used the deprecated parameter `name`, superseded by `replacement`.
+    DeprecatedParameter {
+        name: &'static str,
+        replacement: &'static str,
+    },
+    /// The request failed with the `requested` model and was answered by the `fallback`, see
+    /// [`ChatGPTClientBuilder::fallback_models`](crate::ChatGPTClientBuilder::fallback_models).
+    FellBack { requested: Model, fallback: Model },
+    /// The request went to a `model` OpenAI retires, see [`Model::deprecation`].
+    DeprecatedModel {
+        model: Model,
+        deprecation: Deprecation,
+    },
+    /// The API answered with the model `served`, which is neither the `requested` model nor a
+    /// snapshot of it, e.g. because the requested name is an alias.
+    ModelRedirected { requested: String, served: String },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Warning::Truncated { index } => {
+                write!(
+                    f,
+                    "the completion of choice {index} was cut off at its length limit"
+                )
+            }
+            Warning::ContentFiltered { index } => {
+                write!(
+                    f,
+                    "the completion of choice {index} was cut off by the content filter"
+                )
+            }
+            Warning::DeprecatedParameter { name, replacement } => {
+                write!(f, "`{name}` is deprecated, use `{replacement}` instead")
+            }
+            Warning::FellBack {
+                requested,
+                fallback,
+            } => write!(f, "{requested} failed, the response is from {fallback}"),
+            Warning::DeprecatedModel { model, deprecation } => {
+                write!(f, "{model} is deprecated and {deprecation}")
+            }
+            Warning::ModelRedirected { requested, served } => {
+                write!(f, "{requested} was requested, but {served} answered")
+            }
+        }
+    }
+}
+
+/// The warnings about `response`, answering `input` after `requested` was asked for.
+pub(crate) fn chat_warnings(
+    requested: &Model,
+    input: &ChatInput,
+    response: &ChatResponse,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    #[cfg(feature = "legacy-functions")]
+    {
+        if input.functions.is_some() {
+            warnings.push(Warning::DeprecatedParameter {
+                name: "functions",
+                replacement: "tools",
+            });
+        }
+        if input.function_call.is_some() {
+            warnings.push(Warning::DeprecatedParameter {
+                name: "function_call",
+                replacement: "tool_choice",
+            });
+        }
+    }
+    if input.model != *requested {
+        warnings.push(Warning::FellBack {
+            requested: requested.clone(),
+            fallback: input.model.clone(),
+        });
+    }
+    if let Some(deprecation) = input.model.deprecation() {
+        warnings.push(Warning::DeprecatedModel {
+            model: input.model.clone(),
+            deprecation,
+        });
+    }
+    let sent = input.model.to_string();
+    if !response.model.is_empty() && !is_snapshot_of(&response.model, &sent) {
+        warnings.push(Warning::ModelRedirected {
+            requested: sent,
+            served: response.model.clone(),
+        });
+    }
+    for (index, choice) in response.choices.iter().enumerate() {
+        match choice.finish_reason.as_str() {
+            "length" => warnings.push(Warning::Truncated { index }),
+            "content_filter" => warnings.push(Warning::ContentFiltered { index }),
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// Whether the model `served` is `requested`, or a dated snapshot of it such as
+/// `gpt-4o-2024-08-06` of `gpt-4o`.
+fn is_snapshot_of(served: &str, requested: &str) -> bool {
+    let requested = requested.strip_suffix("-latest").unwrap_or(requested);
+    served.strip_prefix(requested).is_some_and(|rest| {
+        rest.is_empty()
+            || rest
+                .strip_prefix('-')
+                .is_some_and(|date| date.starts_with(|c: char| c.is_ascii_digit()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Choice, Message};
+
+    fn response(model: &str, finish_reasons: &[&str]) -> ChatResponse {
+        ChatResponse {
+            model: model.to_string(),
+            choices: finish_reasons
+                .iter()
+                .map(|reason| Choice::new(Message::assistant("Hi"), *reason))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chat_warnings() {
+        let input = ChatInput::builder(Model::Gpt_4o).build();
+        let ok = response("gpt-4o-2024-08-06", &["stop"]);
+        assert_eq!(chat_warnings(&Model::Gpt_4o, &input, &ok), []);
+
+        let cut_off = response("gpt-4o", &["length", "stop", "content_filter"]);
+        assert_eq!(
+            chat_warnings(&Model::Gpt_4o, &input, &cut_off),
+            [
+                Warning::Truncated { index: 0 },
+                Warning::ContentFiltered { index: 2 }
+            ]
+        );
+
+        let redirected = response("gpt-4o-mini-2024-07-18", &["stop"]);
+        assert_eq!(
+            chat_warnings(&Model::Gpt_4o, &input, &redirected),
+            [Warning::ModelRedirected {
+                requested: "gpt-4o".to_string(),
+                served: "gpt-4o-mini-2024-07-18".to_string(),
+            }]
+        );
+
+        assert_eq!(
+            chat_warnings(&Model::Gpt_4, &input, &ok),
+            [Warning::FellBack {
+                requested: Model::Gpt_4,
+                fallback: Model::Gpt_4o,
+            }]
+        );
+        let retired = ChatInput::builder(Model::Gpt_4_32k).build();
+        let warnings = chat_warnings(&Model::Gpt_4_32k, &retired, &response("gpt-4-32k", &[]));
+        assert!(matches!(
+            &warnings[..],
+            [Warning::DeprecatedModel {
+                model: Model::Gpt_4_32k,
+                ..
+            }]
+        ));
+        assert_eq!(
+            Warning::Truncated { index: 0 }.to_string(),
+            "the completion of choice 0 was cut off at its length limit"
+        );
+    }
+}